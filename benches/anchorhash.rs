@@ -1,5 +1,7 @@
+use std::{hash::BuildHasher, hint::black_box};
+
 use anchorhash::AnchorHash;
-use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
 use fnv::FnvBuildHasher;
 
 fn bench(c: &mut Criterion) {
@@ -80,11 +82,158 @@ fn bench(c: &mut Criterion) {
     }
 }
 
+/// Lookups in large instances after most buckets have been removed, where each
+/// lookup walks the A and K arrays of several removed buckets.
+fn bench_churned(c: &mut Criterion) {
+    /// The number of distinct keys looked up per iteration, spreading the
+    /// lookups over the whole table rather than a single cached path.
+    const KEYS: usize = 4_096;
+
+    let mut group = c.benchmark_group("AnchorHash/churned");
+    group.throughput(Throughput::Elements(KEYS as u64));
+
+    let keys = (0..KEYS as u64).collect::<Vec<_>>();
+
+    for &capacity in &[10_000_u16, 65_535] {
+        for &removed_pct in &[50, 90] {
+            let input = churned(capacity, removed_pct);
+
+            group.bench_with_input(
+                BenchmarkId::new(
+                    "lookup",
+                    format!("capacity={}/removed={}%", capacity, removed_pct),
+                ),
+                &input,
+                |b, a| {
+                    b.iter(|| {
+                        for k in &keys {
+                            black_box(a.get_resource(*k));
+                        }
+                    })
+                },
+            );
+        }
+    }
+}
+
+fn bench_frozen(c: &mut Criterion) {
+    const KEYS: usize = 4_096;
+
+    let mut group = c.benchmark_group("AnchorHash/frozen");
+    group.throughput(Throughput::Elements(KEYS as u64));
+
+    let keys = (0..KEYS as u64).collect::<Vec<_>>();
+
+    for &capacity in &[10_000_u16, 65_535] {
+        for &removed_pct in &[50, 90, 99] {
+            let live = churned(capacity, removed_pct);
+            let frozen = live.freeze();
+            let param = format!("capacity={}/removed={}%", capacity, removed_pct);
+
+            group.bench_with_input(BenchmarkId::new("live", &param), &live, |b, a| {
+                b.iter(|| {
+                    for k in &keys {
+                        black_box(a.get_resource(*k));
+                    }
+                })
+            });
+
+            group.bench_with_input(BenchmarkId::new("frozen", &param), &frozen, |b, a| {
+                b.iter(|| {
+                    for k in &keys {
+                        black_box(a.get_resource(*k));
+                    }
+                })
+            });
+        }
+    }
+}
+
+/// Lookups of keys that visit the longest possible successor chain, after a
+/// long sequence of removals of the bucket at the same position of W.
+fn bench_worst_case(c: &mut Criterion) {
+    const KEYS: usize = 64;
+
+    let mut group = c.benchmark_group("AnchorHash/worst_case");
+    group.throughput(Throughput::Elements(KEYS as u64));
+
+    for &capacity in &[1_000_u16, 10_000, 65_535] {
+        let input = chained(capacity);
+
+        // Keys that initially map to bucket 2, the last bucket removed, are
+        // redirected to position 0 of W, whose successor chain holds every
+        // removed bucket.
+        let hasher = FnvBuildHasher::default();
+        let keys = (0_u64..)
+            .filter(|k| anchorhash::range_map(hasher.hash_one(k) as u32, capacity as u32) == 2)
+            .take(KEYS)
+            .collect::<Vec<_>>();
+
+        group.bench_with_input(
+            BenchmarkId::new("lookup", format!("capacity={}", capacity)),
+            &input,
+            |b, a| {
+                b.iter(|| {
+                    for k in &keys {
+                        black_box(a.get_resource(*k));
+                    }
+                })
+            },
+        );
+    }
+}
+
+/// Build a full instance of `capacity` resources, and repeatedly remove the
+/// bucket at position 0 of W until a single bucket remains.
+///
+/// Each removal moves the last bucket of W into position 0, so position 0 is
+/// occupied by every removed bucket in turn: 0, capacity - 1, capacity - 2,
+/// ..., 2.
+fn chained(capacity: u16) -> AnchorHash<u64, usize, FnvBuildHasher> {
+    let mut a = anchorhash::Builder::with_hasher(FnvBuildHasher::default())
+        .with_resources(0..capacity as usize)
+        .build(capacity);
+
+    a.remove_resource(&0).unwrap();
+    for r in (2..capacity as usize).rev() {
+        a.remove_resource(&r).unwrap();
+    }
+
+    a
+}
+
+/// Build a full instance of `capacity` resources, and remove `removed_pct`
+/// percent of them in a pseudo-random order.
+fn churned(capacity: u16, removed_pct: usize) -> AnchorHash<u64, usize, FnvBuildHasher> {
+    let mut a = anchorhash::Builder::with_hasher(FnvBuildHasher::default())
+        .with_resources(0..capacity as usize)
+        .build(capacity);
+
+    let mut resources = (0..capacity as usize).collect::<Vec<_>>();
+    let mut state = 42_u64;
+    for _ in 0..capacity as usize * removed_pct / 100 {
+        // A linear congruential generator picks the next resource to remove.
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        let i = (state >> 33) as usize % resources.len();
+        a.remove_resource(&resources.swap_remove(i)).unwrap();
+    }
+
+    a
+}
+
 fn new(size: usize, capacity: u16) -> AnchorHash<&'static str, usize, FnvBuildHasher> {
     anchorhash::Builder::with_hasher(FnvBuildHasher::default())
         .with_resources(0..size)
         .build(capacity)
 }
 
-criterion_group!(benches, bench);
+criterion_group!(
+    benches,
+    bench,
+    bench_churned,
+    bench_frozen,
+    bench_worst_case
+);
 criterion_main!(benches);
@@ -0,0 +1,87 @@
+use std::hint::black_box;
+
+use anchorhash::{AnchorHash, ConsistentHash, JumpHash, MementoHash, Rendezvous};
+use criterion::{
+    criterion_group, criterion_main, measurement::WallTime, BatchSize, BenchmarkGroup, BenchmarkId,
+    Criterion,
+};
+use fnv::FnvBuildHasher;
+
+const WORKING_SET_SIZES: &[usize] = &[3, 100, 1_000];
+
+/// Benchmark the operations common to every algorithm, so the results of each
+/// are directly comparable.
+fn bench_impl<C, F>(group: &mut BenchmarkGroup<'_, WallTime>, name: &str, new: F)
+where
+    C: ConsistentHash<usize> + Clone,
+    F: Fn(usize) -> C,
+{
+    for &size in WORKING_SET_SIZES {
+        let input = new(size);
+
+        group.bench_with_input(
+            BenchmarkId::new(format!("{}/lookup", name), size),
+            &input,
+            |b, c| b.iter(|| black_box(c.lookup("k"))),
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new(format!("{}/add", name), size),
+            &input,
+            |b, c| {
+                b.iter_batched(
+                    || c.clone(),
+                    |mut c| c.add(size + 1).unwrap(),
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+
+        // Removing the last resource is the only removal supported by every
+        // algorithm.
+        group.bench_with_input(
+            BenchmarkId::new(format!("{}/remove_last", name), size),
+            &input,
+            |b, c| {
+                b.iter_batched(
+                    || c.clone(),
+                    |mut c| c.remove(&(size - 1)).unwrap(),
+                    BatchSize::NumIterations(1),
+                )
+            },
+        );
+    }
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ConsistentHash");
+
+    bench_impl(&mut group, "AnchorHash", |size| -> AnchorHash<&str, _, _> {
+        // Leave headroom equal to the working set, as a typical deployment
+        // would.
+        anchorhash::Builder::with_hasher(FnvBuildHasher::default())
+            .with_resources(0..size)
+            .build(size as u16 * 2)
+    });
+
+    bench_impl(&mut group, "Memento", |size| -> MementoHash<&str, _, _> {
+        anchorhash::Builder::with_hasher(FnvBuildHasher::default())
+            .with_resources(0..size)
+            .build_unbounded()
+    });
+
+    bench_impl(&mut group, "Jump", |size| {
+        JumpHash::with_hasher(FnvBuildHasher::default()).with_resources(0..size)
+    });
+
+    bench_impl(&mut group, "Rendezvous", |size| {
+        let mut r = Rendezvous::with_hasher(FnvBuildHasher::default());
+        (0..size).for_each(|v| r.add_resource(v).unwrap());
+        r
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
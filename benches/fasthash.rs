@@ -1,5 +1,7 @@
+use std::hint::black_box;
+
 use anchorhash::fasthash;
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion};
 
 fn bench(c: &mut Criterion) {
     c.bench_function("fasthash", |b| {
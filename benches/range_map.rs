@@ -1,5 +1,7 @@
+use std::hint::black_box;
+
 use anchorhash::range_map;
-use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 
 fn bench(c: &mut Criterion) {
     let mut group = c.benchmark_group("range_map");
@@ -0,0 +1,211 @@
+/*
+ * Checks the C API maps keys identically to the Rust implementation, using the
+ * golden vectors in ffi/vectors.txt. From the repository root:
+ *
+ *   cargo rustc --lib --release --features ffi --crate-type staticlib
+ *   cc -Wall -Wextra -Iinclude ffi/test.c target/release/libanchorhash.a \
+ *       -lpthread -ldl -lm -o target/ffi-test
+ *   target/ffi-test ffi/vectors.txt
+ */
+
+#include <inttypes.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "anchorhash.h"
+
+static int failures = 0;
+
+#define CHECK(cond)                                                          \
+    do {                                                                     \
+        if (!(cond)) {                                                       \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, \
+                    #cond);                                                  \
+            failures++;                                                      \
+        }                                                                    \
+    } while (0)
+
+#define CHECK_STATUS(expr, want)                                             \
+    do {                                                                     \
+        int got_ = (expr);                                                   \
+        if (got_ != (want)) {                                                \
+            fprintf(stderr, "%s:%d: %s returned \"%s\", want \"%s\"\n",      \
+                    __FILE__, __LINE__, #expr, anchorhash_status_str(got_),  \
+                    anchorhash_status_str(want));                            \
+            failures++;                                                      \
+        }                                                                    \
+    } while (0)
+
+/* Build the instance the golden vectors are evaluated against. */
+static anchorhash_t *build(uint32_t hasher) {
+    anchorhash_t *a = anchorhash_new(64, hasher);
+    CHECK(a != NULL);
+
+    for (uint64_t id = 1; id <= 10; id++) {
+        CHECK_STATUS(anchorhash_add_id(a, id), ANCHORHASH_OK);
+    }
+    CHECK_STATUS(anchorhash_remove_id(a, 3), ANCHORHASH_OK);
+    CHECK_STATUS(anchorhash_remove_id(a, 7), ANCHORHASH_OK);
+    CHECK_STATUS(anchorhash_add_id(a, 11), ANCHORHASH_OK);
+
+    return a;
+}
+
+/* Round trip a through a snapshot, returning the restored instance. */
+static anchorhash_t *restore(const anchorhash_t *a, uint32_t hasher) {
+    uint8_t *buf = NULL;
+    size_t len = 0;
+    CHECK_STATUS(anchorhash_snapshot_save(a, &buf, &len), ANCHORHASH_OK);
+
+    anchorhash_t *restored = NULL;
+    CHECK_STATUS(anchorhash_snapshot_load(buf, len, hasher, ANCHORHASH_RESOURCE_ID, &restored),
+                 ANCHORHASH_OK);
+
+    anchorhash_bytes_free(buf, len);
+    return restored;
+}
+
+static void check_vectors(const char *path) {
+    FILE *f = fopen(path, "r");
+    if (f == NULL) {
+        perror(path);
+        failures++;
+        return;
+    }
+
+    anchorhash_t *fnv = build(ANCHORHASH_HASHER_FNV);
+    anchorhash_t *sip = build(ANCHORHASH_HASHER_SIP);
+    anchorhash_t *fnv_restored = restore(fnv, ANCHORHASH_HASHER_FNV);
+    anchorhash_t *sip_restored = restore(sip, ANCHORHASH_HASHER_SIP);
+
+    char line[256];
+    int n = 0;
+    while (fgets(line, sizeof(line), f) != NULL) {
+        if (line[0] == '#' || line[0] == '\n') {
+            continue;
+        }
+
+        char hasher[8], kind[8], key[64];
+        uint64_t want_hash, want_id;
+        if (sscanf(line, "%7s %7s %63s %" SCNx64 " %" SCNu64, hasher, kind, key, &want_hash,
+                   &want_id) != 5) {
+            fprintf(stderr, "malformed vector: %s", line);
+            failures++;
+            continue;
+        }
+
+        int is_fnv = strcmp(hasher, "fnv") == 0;
+        int is_str = strcmp(kind, "str") == 0;
+        const anchorhash_t *instances[] = {
+            is_fnv ? fnv : sip,
+            is_fnv ? fnv_restored : sip_restored,
+        };
+
+        for (size_t i = 0; i < sizeof(instances) / sizeof(instances[0]); i++) {
+            const anchorhash_t *a = instances[i];
+            const uint8_t *k = (const uint8_t *)key;
+            size_t len = strlen(key);
+
+            uint64_t hash = 0;
+            anchorhash_resource_t r;
+            if (is_str) {
+                CHECK_STATUS(anchorhash_hash_str(a, k, len, &hash), ANCHORHASH_OK);
+                CHECK_STATUS(anchorhash_lookup_str(a, k, len, &r), ANCHORHASH_OK);
+            } else {
+                CHECK_STATUS(anchorhash_hash_bytes(a, k, len, &hash), ANCHORHASH_OK);
+                CHECK_STATUS(anchorhash_lookup_bytes(a, k, len, &r), ANCHORHASH_OK);
+            }
+            CHECK(hash == want_hash);
+            CHECK(r.kind == ANCHORHASH_RESOURCE_ID);
+            CHECK(r.id == want_id);
+
+            CHECK_STATUS(anchorhash_lookup_hash(a, want_hash, &r), ANCHORHASH_OK);
+            CHECK(r.id == want_id);
+        }
+        n++;
+    }
+    fclose(f);
+
+    CHECK(n > 0);
+    printf("checked %d vectors\n", n);
+
+    anchorhash_free(fnv);
+    anchorhash_free(sip);
+    anchorhash_free(fnv_restored);
+    anchorhash_free(sip_restored);
+}
+
+static void check_bytes_resources(void) {
+    anchorhash_t *a = anchorhash_new(4, ANCHORHASH_HASHER_FNV);
+    const uint8_t *cache1 = (const uint8_t *)"cache1";
+    const uint8_t *key = (const uint8_t *)"user-A";
+
+    CHECK_STATUS(anchorhash_add_bytes(a, cache1, 6), ANCHORHASH_OK);
+
+    anchorhash_resource_t r;
+    CHECK_STATUS(anchorhash_lookup_bytes(a, key, 6, &r), ANCHORHASH_OK);
+    CHECK(r.kind == ANCHORHASH_RESOURCE_BYTES);
+    CHECK(r.len == 6 && memcmp(r.bytes, cache1, 6) == 0);
+
+    /* Byte string resources survive a snapshot. */
+    uint8_t *buf = NULL;
+    size_t len = 0;
+    CHECK_STATUS(anchorhash_snapshot_save(a, &buf, &len), ANCHORHASH_OK);
+    anchorhash_t *restored = NULL;
+    CHECK_STATUS(anchorhash_snapshot_load(buf, len, ANCHORHASH_HASHER_FNV,
+                                          ANCHORHASH_RESOURCE_BYTES, &restored),
+                 ANCHORHASH_OK);
+    anchorhash_bytes_free(buf, len);
+
+    CHECK_STATUS(anchorhash_lookup_bytes(restored, key, 6, &r), ANCHORHASH_OK);
+    CHECK(r.len == 6 && memcmp(r.bytes, cache1, 6) == 0);
+    anchorhash_free(restored);
+
+    CHECK_STATUS(anchorhash_remove_bytes(a, cache1, 6), ANCHORHASH_OK);
+    CHECK_STATUS(anchorhash_lookup_bytes(a, key, 6, &r), ANCHORHASH_NO_RESOURCES);
+
+    anchorhash_free(a);
+}
+
+static void check_errors(void) {
+    CHECK(anchorhash_new(4, 42) == NULL);
+
+    anchorhash_t *a = anchorhash_new(1, ANCHORHASH_HASHER_SIP);
+    CHECK_STATUS(anchorhash_add_id(a, 1), ANCHORHASH_OK);
+    CHECK_STATUS(anchorhash_add_id(a, 2), ANCHORHASH_CAPACITY_LIMIT_REACHED);
+    CHECK_STATUS(anchorhash_remove_id(a, 2), ANCHORHASH_RESOURCE_NOT_FOUND);
+    CHECK_STATUS(anchorhash_add_id(NULL, 1), ANCHORHASH_INVALID_ARGUMENT);
+
+    anchorhash_resource_t r;
+    const uint8_t invalid_utf8[] = {0xff, 0xfe};
+    CHECK_STATUS(anchorhash_lookup_str(a, invalid_utf8, 2, &r), ANCHORHASH_INVALID_ARGUMENT);
+    CHECK_STATUS(anchorhash_lookup_bytes(a, invalid_utf8, 2, &r), ANCHORHASH_OK);
+
+    anchorhash_t *restored = NULL;
+    const uint8_t garbage[] = "not a snapshot";
+    CHECK_STATUS(anchorhash_snapshot_load(garbage, sizeof(garbage), ANCHORHASH_HASHER_SIP,
+                                          ANCHORHASH_RESOURCE_ID, &restored),
+                 ANCHORHASH_INVALID_SNAPSHOT);
+    CHECK(restored == NULL);
+
+    anchorhash_free(a);
+    anchorhash_free(NULL);
+}
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        fprintf(stderr, "usage: %s <vectors>\n", argv[0]);
+        return 2;
+    }
+
+    check_vectors(argv[1]);
+    check_bytes_resources();
+    check_errors();
+
+    if (failures > 0) {
+        fprintf(stderr, "%d checks failed\n", failures);
+        return 1;
+    }
+    printf("ok\n");
+    return 0;
+}
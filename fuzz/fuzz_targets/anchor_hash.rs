@@ -0,0 +1,12 @@
+#![no_main]
+
+use anchorhash::AnchorHash;
+use anchorhash_fuzz::{run, Input};
+use fnv::FnvBuildHasher;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: Input| {
+    // Bound the capacity to keep the naive model fast.
+    let capacity = input.capacity % 1024;
+    run::<AnchorHash<u64, u32, FnvBuildHasher>>(capacity, input);
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use anchorhash::ArrayAnchorHash;
+use anchorhash_fuzz::{run, Input};
+use fnv::FnvBuildHasher;
+use libfuzzer_sys::fuzz_target;
+
+const N: usize = 64;
+
+fuzz_target!(|input: Input| {
+    run::<ArrayAnchorHash<u64, u32, FnvBuildHasher, N>>(N as u16, input);
+});
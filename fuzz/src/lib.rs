@@ -0,0 +1,301 @@
+//! Fuzzing support for the `anchorhash` crate.
+//!
+//! The fuzz targets apply random sequences of resource additions, removals and
+//! key lookups to an implementation, and compare every result against
+//! [`Model`] - a naive reference implementation of the AnchorHash algorithm
+//! that keeps an explicit copy of the working set `W_b` of every removed
+//! bucket, rather than the compact `A`, `K`, `L` and `R` arrays.
+//!
+//! Run a target with [`cargo fuzz`] from the root of the repository:
+//!
+//! ```text
+//! cargo +nightly fuzz run anchor_hash
+//! ```
+//!
+//! [`cargo fuzz`]: https://github.com/rust-fuzz/cargo-fuzz
+
+use std::{collections::HashMap, hash::BuildHasher};
+
+use anchorhash::{
+    fasthash, range_map, AnchorHash, ArrayAnchorHash, Builder, Error, InvariantViolation,
+};
+use arbitrary::Arbitrary;
+use fnv::FnvBuildHasher;
+
+/// A removed bucket, and the state of the working set when it was removed.
+#[derive(Debug)]
+struct Removed {
+    bucket: u16,
+    /// The position of the bucket in the working set before it was removed.
+    position: usize,
+    /// The working set immediately after the bucket was removed (`W_b`).
+    working: Vec<u16>,
+}
+
+/// A naive reference implementation of the AnchorHash algorithm.
+///
+/// The working set is an ordered list of buckets, and removing a bucket moves
+/// the last working bucket into its position. Each removed bucket keeps a copy
+/// of the working set immediately after its removal (`W_b`), and a key that
+/// maps to a removed bucket `b` is rehashed onto `W_b` - repeating until it
+/// maps to a working bucket.
+#[derive(Debug)]
+pub struct Model {
+    capacity: u16,
+    working: Vec<u16>,
+    removed: Vec<Removed>,
+}
+
+impl Model {
+    /// Initialise a model with `capacity` buckets, of which the first
+    /// `working` are working.
+    pub fn new(capacity: u16, working: u16) -> Self {
+        let mut m = Self {
+            capacity,
+            working: (0..capacity).collect(),
+            removed: Vec::new(),
+        };
+
+        // The unused buckets are removed from the end of the working set.
+        for b in (working..capacity).rev() {
+            m.remove(b);
+        }
+        m
+    }
+
+    /// Restore the most recently removed bucket, returning it.
+    pub fn add(&mut self) -> Option<u16> {
+        let r = self.removed.pop()?;
+
+        // The bucket returns to its original position, moving the bucket that
+        // replaced it back to the end of the working set.
+        if r.position == self.working.len() {
+            self.working.push(r.bucket);
+        } else {
+            let replaced = std::mem::replace(&mut self.working[r.position], r.bucket);
+            self.working.push(replaced);
+        }
+
+        Some(r.bucket)
+    }
+
+    /// Remove the working bucket `b`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `b` is not working.
+    pub fn remove(&mut self, b: u16) {
+        let position = self
+            .working
+            .iter()
+            .position(|&v| v == b)
+            .expect("removing bucket that is not working");
+
+        self.working.swap_remove(position);
+        self.removed.push(Removed {
+            bucket: b,
+            position,
+            working: self.working.clone(),
+        });
+    }
+
+    /// Resolve the hash `k` to a working bucket.
+    pub fn lookup(&self, k: u32) -> Option<u16> {
+        if self.working.is_empty() {
+            return None;
+        }
+
+        let mut b = range_map(k, u32::from(self.capacity)) as u16;
+        while let Some(r) = self.removed.iter().find(|r| r.bucket == b) {
+            let h = range_map(fasthash(u32::from(b), k), r.working.len() as u32);
+            b = r.working[h as usize];
+        }
+
+        Some(b)
+    }
+}
+
+/// An operation applied to both an implementation and the [`Model`].
+#[derive(Debug, Arbitrary)]
+pub enum Op {
+    /// Add a new resource.
+    Add,
+    /// Remove the resource at this index of the working resources, or a
+    /// resource that does not exist when there are none.
+    Remove(u16),
+    /// Look up a key.
+    Lookup(u64),
+}
+
+/// The input to a fuzz target.
+#[derive(Debug, Arbitrary)]
+pub struct Input {
+    /// The capacity of the instance, bounded by the capacity of fixed size
+    /// implementations.
+    pub capacity: u16,
+    /// The number of resources the instance is built with.
+    pub initial: u16,
+    /// The operations to apply.
+    pub ops: Vec<Op>,
+}
+
+/// An implementation under test.
+pub trait Target {
+    /// Build an instance with `capacity` buckets, assigning the resources
+    /// `0..initial` to buckets `0..initial`.
+    fn build(capacity: u16, initial: u16) -> Self;
+    /// Add `resource`.
+    fn add(&mut self, resource: u32) -> Result<(), Error>;
+    /// Remove `resource`.
+    fn remove(&mut self, resource: u32) -> Result<(), Error>;
+    /// Map `key` to a resource.
+    fn lookup(&self, key: u64) -> Option<u32>;
+    /// Check the internal invariants hold.
+    fn validate(&self) -> Result<(), InvariantViolation>;
+}
+
+impl Target for AnchorHash<u64, u32, FnvBuildHasher> {
+    fn build(capacity: u16, initial: u16) -> Self {
+        Builder::with_hasher(FnvBuildHasher::default())
+            .with_resources(0..u32::from(initial))
+            .build(capacity)
+    }
+
+    fn add(&mut self, resource: u32) -> Result<(), Error> {
+        self.add_resource(resource)
+    }
+
+    fn remove(&mut self, resource: u32) -> Result<(), Error> {
+        self.remove_resource(&resource)
+    }
+
+    fn lookup(&self, key: u64) -> Option<u32> {
+        self.get_resource(key).copied()
+    }
+
+    fn validate(&self) -> Result<(), InvariantViolation> {
+        AnchorHash::validate(self)
+    }
+}
+
+impl<const N: usize> Target for ArrayAnchorHash<u64, u32, FnvBuildHasher, N> {
+    fn build(capacity: u16, initial: u16) -> Self {
+        assert_eq!(usize::from(capacity), N);
+
+        let mut a = Self::with_hasher(FnvBuildHasher::default());
+        for r in 0..u32::from(initial) {
+            a.add_resource(r).unwrap();
+        }
+        a
+    }
+
+    fn add(&mut self, resource: u32) -> Result<(), Error> {
+        self.add_resource(resource)
+    }
+
+    fn remove(&mut self, resource: u32) -> Result<(), Error> {
+        self.remove_resource(&resource)
+    }
+
+    fn lookup(&self, key: u64) -> Option<u32> {
+        self.get_resource(key).copied()
+    }
+
+    fn validate(&self) -> Result<(), InvariantViolation> {
+        ArrayAnchorHash::validate(self)
+    }
+}
+
+/// Apply `input` to the implementation `T` with the given `capacity`, panicking
+/// if it disagrees with the [`Model`] or violates an invariant.
+pub fn run<T: Target>(capacity: u16, input: Input) {
+    let initial = input.initial % (capacity + 1);
+
+    let mut target = T::build(capacity, initial);
+    let mut model = Model::new(capacity, initial);
+
+    // The resource assigned to each working bucket, and the next resource to
+    // add.
+    let mut resources = (0..initial)
+        .map(|b| (b, u32::from(b)))
+        .collect::<HashMap<_, _>>();
+    let mut next = u32::from(initial);
+
+    assert_eq!(target.validate(), Ok(()));
+
+    for op in input.ops {
+        match op {
+            Op::Add => match model.add() {
+                Some(b) => {
+                    assert_eq!(target.add(next), Ok(()));
+                    resources.insert(b, next);
+                    next += 1;
+                }
+                None => assert_eq!(target.add(next), Err(Error::CapacityLimitReached)),
+            },
+            Op::Remove(i) => {
+                if model.working.is_empty() {
+                    assert_eq!(target.remove(next), Err(Error::ResourceNotFound));
+                    continue;
+                }
+
+                let b = model.working[usize::from(i) % model.working.len()];
+                model.remove(b);
+                let r = resources.remove(&b).unwrap();
+                assert_eq!(target.remove(r), Ok(()));
+            }
+            Op::Lookup(key) => {
+                let hash = FnvBuildHasher::default().hash_one(key) as u32;
+                let want = model.lookup(hash).map(|b| resources[&b]);
+                assert_eq!(target.lookup(key), want, "key {}", key);
+            }
+        }
+
+        assert_eq!(target.validate(), Ok(()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Generate pseudo-random inputs, so the model can be checked against the
+    /// implementations without a fuzzer.
+    fn inputs(capacity: u16) -> impl Iterator<Item = Input> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        (0..100).map(move |_| Input {
+            capacity,
+            initial: next() as u16,
+            ops: (0..2_000)
+                .map(|_| match next() % 3 {
+                    0 => Op::Add,
+                    1 => Op::Remove(next() as u16),
+                    _ => Op::Lookup(next()),
+                })
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn test_anchor_hash() {
+        for capacity in [0, 1, 2, 17, 300] {
+            for input in inputs(capacity) {
+                run::<AnchorHash<u64, u32, FnvBuildHasher>>(capacity, input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_array_anchor_hash() {
+        for input in inputs(64) {
+            run::<ArrayAnchorHash<u64, u32, FnvBuildHasher, 64>>(64, input);
+        }
+    }
+}
@@ -0,0 +1,304 @@
+/*
+ * The C API of the anchorhash crate, see src/ffi.rs.
+ *
+ * Keys map to the same resource as a Rust AnchorHash with the same hasher and
+ * history only when both run on platforms with the same pointer width and
+ * endianness, and both libraries are built the same way: a 64-bit build with
+ * the "fastmod" feature (the default) maps keys differently from a build
+ * without it or for a 32-bit target, and a build with the "simd" feature for a
+ * target enabling SSE4.2 maps keys differently from one without.
+ */
+
+#ifndef ANCHORHASH_H
+#define ANCHORHASH_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The operation completed successfully.
+ */
+#define ANCHORHASH_OK 0
+
+/**
+ * The instance has reached its capacity and cannot accept more resources.
+ */
+#define ANCHORHASH_CAPACITY_LIMIT_REACHED 1
+
+/**
+ * The resource is not registered with the instance.
+ */
+#define ANCHORHASH_RESOURCE_NOT_FOUND 2
+
+/**
+ * The instance has no resources to map a key to.
+ */
+#define ANCHORHASH_NO_RESOURCES 3
+
+/**
+ * An argument is a NULL pointer, an unknown constant or invalid UTF-8.
+ */
+#define ANCHORHASH_INVALID_ARGUMENT 4
+
+/**
+ * The buffer is not a valid encoded snapshot.
+ */
+#define ANCHORHASH_INVALID_SNAPSHOT 5
+
+/**
+ * The operation failed for any other reason.
+ */
+#define ANCHORHASH_ERROR 6
+
+/**
+ * Hash keys with FNV-1a, as [`FnvBuildHasher`] does.
+ *
+ * [`FnvBuildHasher`]: fnv::FnvBuildHasher
+ */
+#define ANCHORHASH_HASHER_FNV 0
+
+/**
+ * Hash keys with the standard library's SipHash with zero keys, as
+ * `BuildHasherDefault<DefaultHasher>` does.
+ */
+#define ANCHORHASH_HASHER_SIP 1
+
+/**
+ * The resource is an integer id.
+ */
+#define ANCHORHASH_RESOURCE_ID 0
+
+/**
+ * The resource is a byte string.
+ */
+#define ANCHORHASH_RESOURCE_BYTES 1
+
+/**
+ * An opaque [`AnchorHash`] instance, created with [`anchorhash_new()`] or
+ * [`anchorhash_snapshot_load()`] and released with [`anchorhash_free()`].
+ */
+typedef struct anchorhash_t anchorhash_t;
+
+/**
+ * The resource a key maps to, populated by the lookup functions.
+ *
+ * When `kind` is [`ANCHORHASH_RESOURCE_ID`], `id` holds the resource and
+ * `bytes` is NULL. When `kind` is [`ANCHORHASH_RESOURCE_BYTES`], `bytes` and
+ * `len` describe the resource and `id` is zero - the bytes are owned by the
+ * instance, and remain valid until the instance is next modified or freed.
+ */
+typedef struct anchorhash_resource_t {
+  /**
+   * The type of the resource, one of the `ANCHORHASH_RESOURCE_*` constants.
+   */
+  uint32_t kind;
+  /**
+   * The integer id of the resource.
+   */
+  uint64_t id;
+  /**
+   * A pointer to the bytes of the resource.
+   */
+  const uint8_t *bytes;
+  /**
+   * The number of bytes of the resource.
+   */
+  size_t len;
+} anchorhash_resource_t;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Create an instance with room for `capacity` resources, hashing keys with
+ * `hasher` - one of the `ANCHORHASH_HASHER_*` constants.
+ *
+ * Returns NULL if `hasher` is unknown.
+ */
+anchorhash_t *anchorhash_new(uint16_t capacity, uint32_t hasher);
+
+/**
+ * Free an instance. Passing NULL is a no-op.
+ *
+ * # Safety
+ *
+ * `anchor` must be NULL or a pointer returned by [`anchorhash_new()`] or
+ * [`anchorhash_snapshot_load()`] that has not already been freed.
+ */
+void anchorhash_free(anchorhash_t *anchor);
+
+/**
+ * Add the integer resource `id`.
+ *
+ * # Safety
+ *
+ * `anchor` must be a valid instance.
+ */
+int anchorhash_add_id(anchorhash_t *anchor, uint64_t id);
+
+/**
+ * Add the byte string resource of `len` bytes at `resource`, which is copied
+ * into the instance.
+ *
+ * # Safety
+ *
+ * `anchor` must be a valid instance, and `resource` must be valid for reads
+ * of `len` bytes.
+ */
+int anchorhash_add_bytes(anchorhash_t *anchor, const uint8_t *resource, size_t len);
+
+/**
+ * Remove the integer resource `id`.
+ *
+ * # Safety
+ *
+ * `anchor` must be a valid instance.
+ */
+int anchorhash_remove_id(anchorhash_t *anchor, uint64_t id);
+
+/**
+ * Remove the byte string resource of `len` bytes at `resource`.
+ *
+ * # Safety
+ *
+ * `anchor` must be a valid instance, and `resource` must be valid for reads
+ * of `len` bytes.
+ */
+int anchorhash_remove_bytes(anchorhash_t *anchor, const uint8_t *resource, size_t len);
+
+/**
+ * Hash the `len` byte key at `key` as a Rust `&[u8]`, writing the hash to
+ * `out`.
+ *
+ * # Safety
+ *
+ * `anchor` must be a valid instance, `key` must be valid for reads of `len`
+ * bytes and `out` must be valid for writes.
+ */
+int anchorhash_hash_bytes(const anchorhash_t *anchor,
+                          const uint8_t *key,
+                          size_t len,
+                          uint64_t *out);
+
+/**
+ * Hash the `len` byte UTF-8 key at `key` as a Rust `&str`, writing the hash
+ * to `out`.
+ *
+ * # Safety
+ *
+ * `anchor` must be a valid instance, `key` must be valid for reads of `len`
+ * bytes and `out` must be valid for writes.
+ */
+int anchorhash_hash_str(const anchorhash_t *anchor,
+                        const uint8_t *key,
+                        size_t len,
+                        uint64_t *out);
+
+/**
+ * Map the key `hash`, as returned by [`anchorhash_hash_bytes()`] or
+ * [`anchorhash_hash_str()`], to a resource written to `out`.
+ *
+ * Returns [`ANCHORHASH_NO_RESOURCES`] if the instance has no resources.
+ *
+ * # Safety
+ *
+ * `anchor` must be a valid instance and `out` must be valid for writes.
+ */
+int anchorhash_lookup_hash(const anchorhash_t *anchor, uint64_t hash, anchorhash_resource_t *out);
+
+/**
+ * Map the `len` byte key at `key`, hashed as a Rust `&[u8]`, to a resource
+ * written to `out`.
+ *
+ * Returns [`ANCHORHASH_NO_RESOURCES`] if the instance has no resources.
+ *
+ * # Safety
+ *
+ * `anchor` must be a valid instance, `key` must be valid for reads of `len`
+ * bytes and `out` must be valid for writes.
+ */
+int anchorhash_lookup_bytes(const anchorhash_t *anchor,
+                            const uint8_t *key,
+                            size_t len,
+                            anchorhash_resource_t *out);
+
+/**
+ * Map the `len` byte UTF-8 key at `key`, hashed as a Rust `&str`, to a
+ * resource written to `out`.
+ *
+ * Returns [`ANCHORHASH_NO_RESOURCES`] if the instance has no resources.
+ *
+ * # Safety
+ *
+ * `anchor` must be a valid instance, `key` must be valid for reads of `len`
+ * bytes and `out` must be valid for writes.
+ */
+int anchorhash_lookup_str(const anchorhash_t *anchor,
+                          const uint8_t *key,
+                          size_t len,
+                          anchorhash_resource_t *out);
+
+/**
+ * Encode the state of `anchor` as a [`Snapshot`], writing a pointer to the
+ * encoded bytes to `out` and their length to `out_len`.
+ *
+ * Integer resources are encoded as 8 little-endian bytes, and byte string
+ * resources as-is. The buffer must be released with
+ * [`anchorhash_bytes_free()`].
+ *
+ * # Safety
+ *
+ * `anchor` must be a valid instance, and `out` and `out_len` must be valid
+ * for writes.
+ */
+int anchorhash_snapshot_save(const anchorhash_t *anchor, uint8_t **out, size_t *out_len);
+
+/**
+ * Free a buffer returned by [`anchorhash_snapshot_save()`]. Passing NULL is a
+ * no-op.
+ *
+ * # Safety
+ *
+ * `buf` and `len` must be a pointer and length returned by
+ * [`anchorhash_snapshot_save()`] that has not already been freed.
+ */
+void anchorhash_bytes_free(uint8_t *buf, size_t len);
+
+/**
+ * Restore an instance from the `len` byte encoded [`Snapshot`] at `buf`,
+ * hashing keys with `hasher`, and write a pointer to it to `out`.
+ *
+ * Every resource in the snapshot is decoded as `resource_kind` - one of the
+ * `ANCHORHASH_RESOURCE_*` constants. Integer resources must be encoded as 8
+ * little-endian bytes, as [`anchorhash_snapshot_save()`] does, and a snapshot
+ * of an instance holding both kinds of resource restores only as byte
+ * strings.
+ *
+ * Snapshots are interchangeable with those encoded by Rust programs using
+ * [`Snapshot::encode()`], provided the resources are encoded the same way.
+ *
+ * # Safety
+ *
+ * `buf` must be valid for reads of `len` bytes, and `out` must be valid for
+ * writes.
+ */
+int anchorhash_snapshot_load(const uint8_t *buf,
+                             size_t len,
+                             uint32_t hasher,
+                             uint32_t resource_kind,
+                             anchorhash_t **out);
+
+/**
+ * Returns a static, NUL terminated description of the status code `status`.
+ */
+const char *anchorhash_status_str(int status);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif // __cplusplus
+
+#endif  /* ANCHORHASH_H */
@@ -0,0 +1,501 @@
+//! Measure how evenly an [`AnchorHash`] spreads keys across its resources, and
+//! how many keys move between two states.
+//!
+//! Both measurements are taken over a caller provided set of keys - either a
+//! sample of real keys, or a generator such as a range of integers:
+//!
+//! ```rust
+//! use anchorhash::analysis;
+//!
+//! let before = anchorhash::Builder::default()
+//!     .with_resources(vec!["cache1", "cache2", "cache3"])
+//!     .build::<u64>(20);
+//!
+//! let load = analysis::load(&before, 0..10_000_u64);
+//! assert_eq!(load.keys(), 10_000);
+//! assert!(load.max_min_ratio().unwrap() < 1.3);
+//!
+//! let mut after = before.clone();
+//! after.add_resource("cache4").unwrap();
+//!
+//! // Adding a fourth resource should move roughly a quarter of the keys.
+//! let d = analysis::disruption(&before, &after, 0..10_000_u64);
+//! assert_eq!(d.optimal_fraction(), 0.25);
+//! assert!((d.moved_fraction() - d.optimal_fraction()).abs() < 0.05);
+//! ```
+//!
+//! [`AnchorHash`]: crate::AnchorHash
+
+use core::hash::{BuildHasher, Hash};
+
+use alloc::vec::Vec;
+
+use hashbrown::HashMap;
+
+use crate::AnchorHash;
+
+/// The number of keys mapped to each resource of an [`AnchorHash`], as
+/// measured by [`load()`].
+///
+/// [`AnchorHash`]: crate::AnchorHash
+#[derive(Debug, Clone)]
+pub struct Load<'a, R> {
+    /// The bucket, resource and number of keys mapped to it, ordered by
+    /// bucket.
+    buckets: Vec<(u16, &'a R, u64)>,
+    keys: u64,
+}
+
+impl<'a, R> Load<'a, R> {
+    /// Returns the number of keys measured.
+    pub fn keys(&self) -> u64 {
+        self.keys
+    }
+
+    /// Returns an iterator yielding each resource and the number of keys
+    /// mapped to it.
+    ///
+    /// Resources are yielded in bucket order. A duplicate resource is yielded
+    /// once for each copy.
+    pub fn loads(&self) -> impl Iterator<Item = (&'a R, u64)> + '_ {
+        self.buckets.iter().map(|&(_b, r, n)| (r, n))
+    }
+
+    /// Returns Pearson's chi-square statistic comparing the measured load
+    /// against a perfectly uniform distribution of keys.
+    ///
+    /// For keys hashed uniformly at random, the statistic follows a chi-square
+    /// distribution with [`degrees_of_freedom()`] degrees of freedom - values
+    /// far above the degrees of freedom indicate an imbalanced distribution.
+    ///
+    /// Returns `0.0` when there are no resources or no keys.
+    ///
+    /// [`degrees_of_freedom()`]: Self::degrees_of_freedom
+    pub fn chi_square(&self) -> f64 {
+        if self.buckets.is_empty() || self.keys == 0 {
+            return 0.0;
+        }
+
+        let expected = self.keys as f64 / self.buckets.len() as f64;
+        self.buckets
+            .iter()
+            .map(|&(_b, _r, n)| {
+                let d = n as f64 - expected;
+                d * d / expected
+            })
+            .sum()
+    }
+
+    /// Returns the degrees of freedom of the [`chi_square()`] statistic, one
+    /// less than the number of resources.
+    ///
+    /// [`chi_square()`]: Self::chi_square
+    pub fn degrees_of_freedom(&self) -> usize {
+        self.buckets.len().saturating_sub(1)
+    }
+
+    /// Returns the ratio of the most loaded resource to the least loaded
+    /// resource, where `1.0` is a perfect balance.
+    ///
+    /// Returns [`f64::INFINITY`] if a resource has no keys, or [`None`] when
+    /// there are no resources or no keys.
+    pub fn max_min_ratio(&self) -> Option<f64> {
+        let max = self.buckets.iter().map(|l| l.2).max()?;
+        let min = self.buckets.iter().map(|l| l.2).min()?;
+        if max == 0 {
+            return None;
+        }
+
+        Some(max as f64 / min as f64)
+    }
+}
+
+/// Hash each key in `keys` with `anchor`, returning the number of keys mapped
+/// to each resource.
+pub fn load<K, R, B, I>(anchor: &AnchorHash<K, R, B>, keys: I) -> Load<'_, R>
+where
+    B: BuildHasher,
+    I: IntoIterator,
+    I::Item: Hash,
+{
+    let mut buckets = anchor
+        .assignments()
+        .map(|(b, r)| (b, r, 0))
+        .collect::<Vec<_>>();
+
+    let mut n = 0;
+    for key in keys {
+        n += 1;
+        let b = match anchor.lookup_hash_bucket(anchor.hash_key(&key)) {
+            Some(v) => v,
+            None => continue,
+        };
+        let i = buckets
+            .binary_search_by_key(&b, |l| l.0)
+            .expect("working bucket has no resource");
+        buckets[i].2 += 1;
+    }
+
+    Load { buckets, keys: n }
+}
+
+/// The number of keys that map to a different resource in two states, as
+/// measured by [`disruption()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Disruption {
+    keys: u64,
+    moved: u64,
+    optimal: f64,
+}
+
+impl Disruption {
+    /// Returns the number of keys measured.
+    pub fn keys(&self) -> u64 {
+        self.keys
+    }
+
+    /// Returns the number of keys that map to a different resource.
+    pub fn moved(&self) -> u64 {
+        self.moved
+    }
+
+    /// Returns the fraction of keys that map to a different resource.
+    ///
+    /// Returns `0.0` when no keys were measured.
+    pub fn moved_fraction(&self) -> f64 {
+        if self.keys == 0 {
+            return 0.0;
+        }
+        self.moved as f64 / self.keys as f64
+    }
+
+    /// Returns the smallest fraction of keys that must move for both states
+    /// to be perfectly balanced.
+    ///
+    /// Adding a resource to `n` resources must move at least `1/(n+1)` of the
+    /// keys, and removing one of `n` resources at least `1/n` of the keys.
+    /// More generally, a key can only stay in place if its resource is present
+    /// in both states, and each such resource can keep at most the smaller of
+    /// its two balanced shares.
+    pub fn optimal_fraction(&self) -> f64 {
+        self.optimal
+    }
+
+    /// Returns the fraction of keys moved in excess of the
+    /// [`optimal_fraction()`].
+    ///
+    /// A consistent hash that moves only the keys it must has an excess close
+    /// to `0.0`, differing only by the sampling error of the measured keys.
+    ///
+    /// [`optimal_fraction()`]: Self::optimal_fraction
+    pub fn excess(&self) -> f64 {
+        self.moved_fraction() - self.optimal
+    }
+}
+
+/// Hash each key in `keys` with both `before` and `after`, returning the
+/// number of keys that map to a different resource.
+///
+/// Both instances MUST be configured with the same hasher.
+pub fn disruption<K, R, B, I>(
+    before: &AnchorHash<K, R, B>,
+    after: &AnchorHash<K, R, B>,
+    keys: I,
+) -> Disruption
+where
+    R: Eq + Hash,
+    B: BuildHasher,
+    I: IntoIterator,
+    I::Item: Hash,
+{
+    let mut n = 0;
+    let mut moved = 0;
+    for key in keys {
+        n += 1;
+        if before.lookup(&key) != after.lookup(&key) {
+            moved += 1;
+        }
+    }
+
+    Disruption {
+        keys: n,
+        moved,
+        optimal: optimal_disruption(before, after),
+    }
+}
+
+/// Compute the smallest fraction of keys that must move between the balanced
+/// states of `before` and `after`.
+fn optimal_disruption<K, R, B>(before: &AnchorHash<K, R, B>, after: &AnchorHash<K, R, B>) -> f64
+where
+    R: Eq + Hash,
+    B: BuildHasher,
+{
+    // Count the copies of each resource in both states - a resource assigned
+    // to multiple buckets has a proportionally larger share of the keys.
+    let mut copies: HashMap<&R, (u32, u32)> = HashMap::new();
+    for r in before.resources() {
+        copies.entry(r).or_default().0 += 1;
+    }
+    for r in after.resources() {
+        copies.entry(r).or_default().1 += 1;
+    }
+
+    let (nb, na) = copies
+        .values()
+        .fold((0, 0), |(nb, na), &(b, a)| (nb + b, na + a));
+    match (nb, na) {
+        (0, 0) => return 0.0,
+        (0, _) | (_, 0) => return 1.0,
+        _ => {}
+    }
+    let (nb, na) = (f64::from(nb), f64::from(na));
+
+    let kept = copies
+        .values()
+        .map(|&(b, a)| (f64::from(b) / nb).min(f64::from(a) / na))
+        .sum::<f64>();
+
+    (1.0 - kept).max(0.0)
+}
+
+/// The largest deviation of a resource load from the mean, as a fraction of
+/// the mean, excluding sampling error.
+#[cfg(test)]
+pub(crate) const MAX_IMBALANCE: f64 = 0.1;
+
+/// Returns the largest difference between the number of keys observed and
+/// `expected` allowed by the balance tests - [`MAX_IMBALANCE`] of `expected`,
+/// plus the sampling error exceeded with a probability of roughly 1e-9.
+#[cfg(test)]
+pub(crate) fn tolerance(expected: f64) -> f64 {
+    expected * MAX_IMBALANCE + 6.0 * expected.sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::hash_map::DefaultHasher, hash::BuildHasherDefault};
+
+    use quickcheck::Arbitrary;
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+    use crate::{Builder, DuplicatePolicy};
+
+    type Hasher = BuildHasherDefault<DefaultHasher>;
+
+    fn anchor(
+        resources: impl IntoIterator<Item = u32>,
+        capacity: u16,
+    ) -> AnchorHash<u64, u32, Hasher> {
+        Builder::with_hasher(Hasher::default())
+            .with_resources(resources)
+            .build(capacity)
+    }
+
+    /// Assert every resource holds within [`MAX_IMBALANCE`] of the mean load,
+    /// allowing for sampling error.
+    fn assert_balanced<R: core::fmt::Debug>(load: &Load<'_, R>) {
+        let n = load.loads().count();
+        let mean = load.keys() as f64 / n as f64;
+        let tolerance = tolerance(mean);
+
+        for (r, got) in load.loads() {
+            assert!(
+                (got as f64 - mean).abs() <= tolerance,
+                "resource {:?} has {} keys, want {} +/- {} ({} resources)",
+                r,
+                got,
+                mean,
+                tolerance,
+                n
+            );
+        }
+    }
+
+    #[test]
+    fn test_load() {
+        let a = anchor(0..10, 20);
+        let got = load(&a, 0..100_000_u64);
+
+        assert_eq!(got.keys(), 100_000);
+        assert_eq!(got.loads().count(), 10);
+        assert_eq!(got.loads().map(|(_r, n)| n).sum::<u64>(), 100_000);
+        assert_eq!(got.degrees_of_freedom(), 9);
+
+        // Every resource has the load observed by lookups.
+        for (r, n) in got.loads() {
+            let want = (0..100_000_u64).filter(|k| a.lookup(k) == Some(r)).count();
+            assert_eq!(n, want as u64);
+        }
+
+        assert_balanced(&got);
+        assert!(got.max_min_ratio().unwrap() < 1.25);
+        assert!(got.chi_square() > 0.0);
+    }
+
+    #[test]
+    fn test_load_empty() {
+        let a = anchor(0..0, 20);
+        let got = load(&a, 0..100_u64);
+        assert_eq!(got.keys(), 100);
+        assert_eq!(got.loads().count(), 0);
+        assert_eq!(got.chi_square(), 0.0);
+        assert_eq!(got.max_min_ratio(), None);
+
+        let a = anchor(0..2, 20);
+        let got = load(&a, 0..0_u64);
+        assert_eq!(got.chi_square(), 0.0);
+        assert_eq!(got.max_min_ratio(), None);
+    }
+
+    #[test]
+    fn test_load_statistics() {
+        let a = anchor(0..2, 20);
+
+        // A single key leaves the other resource empty.
+        let got = load(&a, 0..1_u64);
+        assert_eq!(got.max_min_ratio(), Some(f64::INFINITY));
+        assert_eq!(got.chi_square(), 1.0);
+    }
+
+    #[test]
+    fn test_optimal_disruption() {
+        let a = anchor(0..4, 20);
+
+        let mut add = a.clone();
+        add.add_resource(4).unwrap();
+        assert!((optimal_disruption(&a, &add) - 1.0 / 5.0).abs() < 1e-12);
+
+        let mut remove = a.clone();
+        remove.remove_resource(&2).unwrap();
+        assert!((optimal_disruption(&a, &remove) - 1.0 / 4.0).abs() < 1e-12);
+
+        // Replacing a resource moves its share of the keys.
+        let mut replace = remove.clone();
+        replace.add_resource(42).unwrap();
+        assert!((optimal_disruption(&a, &replace) - 1.0 / 4.0).abs() < 1e-12);
+
+        assert_eq!(optimal_disruption(&a, &a), 0.0);
+        assert_eq!(optimal_disruption(&a, &anchor(4..8, 20)), 1.0);
+        assert_eq!(optimal_disruption(&a, &anchor(0..0, 20)), 1.0);
+        assert_eq!(optimal_disruption(&anchor(0..0, 20), &a), 1.0);
+        assert_eq!(
+            optimal_disruption(&anchor(0..0, 20), &anchor(0..0, 20)),
+            0.0
+        );
+
+        // A duplicate resource grows its share from 1/4 to 2/5, while the
+        // other resources shrink from 1/4 to 1/5.
+        let mut dup = a.clone();
+        dup.set_duplicate_policy(DuplicatePolicy::Weighted);
+        dup.add_resource(0).unwrap();
+        assert!((optimal_disruption(&a, &dup) - 0.15).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_disruption() {
+        let a = anchor(0..4, 20);
+        let got = disruption(&a, &a, 0..1_000_u64);
+        assert_eq!(got.keys(), 1_000);
+        assert_eq!(got.moved(), 0);
+        assert_eq!(got.moved_fraction(), 0.0);
+        assert_eq!(got.excess(), 0.0);
+
+        let got = disruption(&a, &a, 0..0_u64);
+        assert_eq!(got.moved_fraction(), 0.0);
+
+        let mut b = a.clone();
+        b.remove_resource(&1).unwrap();
+        let got = disruption(&a, &b, 0..100_000_u64);
+
+        // Exactly the keys of the removed resource move.
+        let want = load(&a, 0..100_000_u64)
+            .loads()
+            .find(|(r, _n)| **r == 1)
+            .unwrap()
+            .1;
+        assert_eq!(got.moved(), want);
+        assert_eq!(got.optimal_fraction(), 0.25);
+
+        // The removed resource held a quarter of the keys, within the balance
+        // tolerance.
+        assert!(got.excess().abs() * 100_000.0 <= tolerance(25_000.0));
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    enum Op {
+        Add,
+        Remove(usize),
+    }
+
+    impl Arbitrary for Op {
+        fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+            if bool::arbitrary(g) {
+                Op::Add
+            } else {
+                Op::Remove(usize::arbitrary(g))
+            }
+        }
+    }
+
+    /// Apply a random history of additions and removals, asserting every step
+    /// moves only the keys it must.
+    #[quickcheck]
+    fn test_random_history(initial: u8, ops: Vec<Op>) {
+        const CAPACITY: u16 = 48;
+        const KEYS: u64 = 2_000;
+
+        let initial = 1 + u32::from(initial) % 16;
+        let mut a = anchor(0..initial, CAPACITY);
+        let mut live = (0..initial).collect::<Vec<_>>();
+        let mut next = initial;
+
+        for op in ops.into_iter().take(12) {
+            let before = a.clone();
+            let changed = match op {
+                Op::Add if live.len() < usize::from(CAPACITY) => {
+                    a.add_resource(next).unwrap();
+                    live.push(next);
+                    next += 1;
+                    next - 1
+                }
+                Op::Remove(i) if live.len() > 1 => {
+                    let r = live.swap_remove(i % live.len());
+                    a.remove_resource(&r).unwrap();
+                    r
+                }
+                _ => continue,
+            };
+
+            // Exactly the keys mapped to the added resource, or from the
+            // removed resource, move.
+            let mut moved = 0;
+            for k in 0..KEYS {
+                let (old, new) = (before.lookup(&k), a.lookup(&k));
+                let want = old == Some(&changed) || new == Some(&changed);
+                assert_eq!(
+                    old != new,
+                    want,
+                    "key {} moved from {:?} to {:?}",
+                    k,
+                    old,
+                    new
+                );
+                moved += u64::from(want);
+            }
+
+            // Which is close to the optimal number of keys.
+            let optimal = optimal_disruption(&before, &a) * KEYS as f64;
+            assert!(
+                (moved as f64 - optimal).abs() <= tolerance(optimal),
+                "moved {} keys, optimal {}",
+                moved,
+                optimal
+            );
+        }
+
+        let got = load(&a, 0..KEYS);
+        assert_eq!(got.loads().count(), live.len());
+    }
+}
@@ -1,31 +1,75 @@
-use std::{
-    collections::hash_map::RandomState,
+use core::{
     convert::TryFrom,
-    default::Default,
-    hash::{BuildHasher, Hash, Hasher},
-    iter::FromIterator,
+    hash::{BuildHasher, Hash},
     marker::PhantomData,
 };
 
-use hashbrown::HashMap;
-use thiserror::Error;
+#[cfg(feature = "std")]
+use std::{collections::hash_map::RandomState, iter::FromIterator};
 
-use crate::{anchor::Anchor, ResourceIterator, ResourceMutIterator};
+#[cfg(feature = "metrics")]
+use std::borrow::Cow;
 
-/// Errors returned when operating on an [`AnchorHash`] instance.
-#[derive(Debug, Error, PartialEq, Eq, Clone, Copy)]
-pub enum Error {
-    /// A new bucket cannot be added to the AnchorHash instance as it has
-    /// reached the configured capacity.
-    #[error("configured resource capacity reached")]
-    CapacityLimitReached,
+#[cfg(feature = "tracing")]
+use core::fmt;
 
-    /// The requested resource is not registered with the AnchorHash instance.
-    #[error("resource not found")]
-    ResourceNotFound,
-}
+use alloc::vec::Vec;
+
+#[cfg(feature = "metrics")]
+use crate::telemetry::{self, Metrics};
+
+#[cfg(feature = "tracing")]
+use crate::telemetry::{DebugFn, TracedResource};
 
-type Result<T, E = Error> = std::result::Result<T, E>;
+use crate::{
+    anchor::Anchor, archive, consistency, error::Result, BucketDiff, Error, FrozenAnchorHash,
+    InvariantViolation, LookupPath, MementoHash, ResourceIterator, ResourceMutIterator, Snapshot,
+};
+
+/// A marker used as the key type of an [`AnchorHash`] that is not restricted to
+/// a single key type.
+///
+/// An `AnchorHash<AnyKey, R, B>` is built with [`Builder::build_any_key()`] and
+/// queried with [`AnchorHash::lookup()`], accepting any key type that
+/// implements [`Hash`].
+///
+/// [`Hash`]: core::hash::Hash  
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnyKey {}
+
+/// Controls how an [`AnchorHash`] handles a resource that is equal to a
+/// resource it already contains.
+///
+/// The policy is configured with [`Builder::with_duplicate_policy()`] and
+/// applies to the initial resources given to [`Builder::with_resources()`] and
+/// to every subsequent call to [`AnchorHash::add_resource()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// Refuse duplicate resources, returning [`Error::DuplicateResource`].
+    Reject,
+
+    /// Accept duplicate resources, assigning each copy its own bucket.
+    ///
+    /// Each copy receives a share of the keys, and [`remove_resource()`]
+    /// removes a single copy - the one assigned the lowest bucket - so that
+    /// every instance with the same history removes the same copy.
+    ///
+    /// This is the default policy.
+    ///
+    /// [`remove_resource()`]: AnchorHash::remove_resource
+    #[default]
+    Allow,
+
+    /// Treat each duplicate as one additional unit of weight for the resource.
+    ///
+    /// As with [`Allow`](Self::Allow), each copy is assigned its own bucket,
+    /// so a resource added twice receives twice the share of keys. Unlike
+    /// `Allow`, [`remove_resource()`] removes all copies of the resource at
+    /// once.
+    ///
+    /// [`remove_resource()`]: AnchorHash::remove_resource
+    Weighted,
+}
 
 /// Initialise a new [`AnchorHash`] instance.
 ///
@@ -82,17 +126,36 @@ where
 {
     resources: Option<Vec<R>>,
     hasher: B,
+    duplicates: DuplicatePolicy,
+    eq: fn(&R, &R) -> bool,
+    #[cfg(feature = "metrics")]
+    metrics_label: Cow<'static, str>,
+    #[cfg(feature = "tracing")]
+    debug: Option<DebugFn<R>>,
+}
+
+/// The resource equality used when no [`DuplicatePolicy`] has been configured,
+/// and duplicates are therefore allowed.
+fn never_equal<R>(_a: &R, _b: &R) -> bool {
+    false
 }
 
 /// Initialise an empty AnchorHash instance using the [`DefaultHasher`] and no
 /// pre-populated resources.
 ///
 /// [`DefaultHasher`]: std::collections::hash_map::DefaultHasher  
+#[cfg(feature = "std")]
 impl<R> Default for Builder<R, RandomState> {
     fn default() -> Self {
         Self {
             hasher: RandomState::default(),
             resources: None,
+            duplicates: DuplicatePolicy::default(),
+            eq: never_equal,
+            #[cfg(feature = "metrics")]
+            metrics_label: Cow::Borrowed(telemetry::DEFAULT_LABEL),
+            #[cfg(feature = "tracing")]
+            debug: None,
         }
     }
 }
@@ -107,27 +170,217 @@ where
     /// # Panics
     ///
     /// This method panics if the number of resources given to
-    /// [`with_resources()`] exceeds `capacity`.
+    /// [`with_resources()`] exceeds `capacity` - use [`try_build()`] to handle
+    /// this case without panicking.
     ///
     /// [`with_resources()`]: Self::with_resources  
-    pub fn build<K: Hash>(self, capacity: u16) -> AnchorHash<K, R, B> {
-        let mut anchor = Anchor::new(capacity, 0);
-        let mut resources = HashMap::new();
-
-        if let Some(res) = self.resources {
-            for r in res {
-                let bucket = anchor
-                    .add_bucket()
-                    .expect("number of resources cannot exceed capacity");
-                resources.insert(bucket, r);
-            }
-        }
+    /// [`try_build()`]: Self::try_build  
+    pub fn build<K>(self, capacity: u16) -> AnchorHash<K, R, B> {
+        self.try_build(capacity)
+            .expect("number of resources cannot exceed capacity")
+    }
 
-        AnchorHash {
+    /// Initialise the [`AnchorHash`] instance with support for up to `capacity`
+    /// number of resources, returning an error if the configuration is
+    /// invalid.
+    ///
+    /// This method returns [`Error::CapacityExceeded`] if the number of
+    /// resources given to [`with_resources()`] exceeds `capacity`:
+    ///
+    /// ```rust
+    /// use anchorhash::{AnchorHash, Builder, Error};
+    ///
+    /// let err = Builder::default()
+    ///     .with_resources(vec!["cache1", "cache2", "cache3"])
+    ///     .try_build::<&str>(2)
+    ///     .unwrap_err();
+    ///
+    /// assert_eq!(
+    ///     err,
+    ///     Error::CapacityExceeded {
+    ///         requested: 3,
+    ///         capacity: 2
+    ///     }
+    /// );
+    /// ```
+    ///
+    /// If the [`DuplicatePolicy`] is [`Reject`], [`Error::DuplicateResource`]
+    /// is returned when two of the resources are equal.
+    ///
+    /// [`with_resources()`]: Self::with_resources  
+    /// [`Reject`]: DuplicatePolicy::Reject  
+    pub fn try_build<K>(mut self, capacity: u16) -> Result<AnchorHash<K, R, B>> {
+        let res = self.resources.take().unwrap_or_default();
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("build", capacity, resources = res.len()).entered();
+
+        let working = u16::try_from(res.len())
+            .ok()
+            .filter(|&n| n <= capacity)
+            .ok_or(Error::CapacityExceeded {
+                requested: res.len(),
+                capacity,
+            })?;
+
+        self.check_duplicates(&res)?;
+
+        // Initialising the anchor with `working` buckets is equivalent to
+        // adding them one at a time, assigning buckets 0..working in order.
+        let anchor = Anchor::new(capacity, working)?;
+        let len = res.len();
+        let mut resources = Vec::with_capacity(usize::from(capacity));
+        resources.extend(res.into_iter().map(Some));
+        resources.resize_with(usize::from(capacity), || None);
+
+        let a = AnchorHash {
             anchor,
             hasher: self.hasher,
             resources,
-            _key_type: PhantomData::default(),
+            len,
+            duplicates: self.duplicates,
+            #[cfg(feature = "metrics")]
+            metrics: Metrics::new(self.metrics_label),
+            #[cfg(feature = "tracing")]
+            debug: self.debug,
+            _key_type: PhantomData,
+        };
+        a.record_size();
+
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            working = a.anchor.working(),
+            capacity,
+            removed_stack = a.anchor.removed().len(),
+            "anchorhash built"
+        );
+
+        Ok(a)
+    }
+
+    /// Initialise a [`MementoHash`] instance, which has no capacity limit.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the [`DuplicatePolicy`] is [`Reject`] and two of
+    /// the resources given to [`with_resources()`] are equal - use
+    /// [`try_build_unbounded()`] to handle this case without panicking.
+    ///
+    /// [`with_resources()`]: Self::with_resources  
+    /// [`try_build_unbounded()`]: Self::try_build_unbounded  
+    /// [`Reject`]: DuplicatePolicy::Reject  
+    pub fn build_unbounded<K>(self) -> MementoHash<K, R, B> {
+        self.try_build_unbounded()
+            .expect("resources must not contain duplicates")
+    }
+
+    /// Initialise a [`MementoHash`] instance, which has no capacity limit,
+    /// returning an error if the configuration is invalid.
+    ///
+    /// If the [`DuplicatePolicy`] is [`Reject`], [`Error::DuplicateResource`]
+    /// is returned when two of the resources are equal.
+    ///
+    /// [`Reject`]: DuplicatePolicy::Reject  
+    pub fn try_build_unbounded<K>(mut self) -> Result<MementoHash<K, R, B>> {
+        let res = self.resources.take().unwrap_or_default();
+        self.check_duplicates(&res)?;
+
+        MementoHash::new(res, self.hasher, self.duplicates)
+    }
+
+    /// Return [`Error::DuplicateResource`] if the [`DuplicatePolicy`] is
+    /// [`Reject`] and `res` contains two equal resources.
+    ///
+    /// [`Reject`]: DuplicatePolicy::Reject
+    fn check_duplicates(&self, res: &[R]) -> Result<()> {
+        // Resources have no Hash or Ord bound, so duplicates are found with a
+        // pairwise comparison.
+        let eq = self.eq;
+        if self.duplicates == DuplicatePolicy::Reject
+            && res
+                .iter()
+                .enumerate()
+                .any(|(i, a)| res[..i].iter().any(|b| eq(a, b)))
+        {
+            return Err(Error::DuplicateResource);
+        }
+        Ok(())
+    }
+
+    /// Initialise an [`AnchorHash`] instance with support for up to `capacity`
+    /// number of resources that is not restricted to a single key type.
+    ///
+    /// The returned instance is queried with [`AnchorHash::lookup()`], which
+    /// accepts any key type implementing [`Hash`].
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the number of resources given to
+    /// [`with_resources()`] exceeds `capacity`.
+    ///
+    /// [`with_resources()`]: Self::with_resources  
+    /// [`Hash`]: core::hash::Hash  
+    pub fn build_any_key(self, capacity: u16) -> AnchorHash<AnyKey, R, B> {
+        self.build(capacity)
+    }
+
+    /// Configure how resources equal to an existing resource are handled by
+    /// the [`AnchorHash`] instance.
+    ///
+    /// ```rust
+    /// use anchorhash::{AnchorHash, Builder, DuplicatePolicy, Error};
+    ///
+    /// let err = Builder::default()
+    ///     .with_duplicate_policy(DuplicatePolicy::Reject)
+    ///     .with_resources(vec!["cache1", "cache2", "cache1"])
+    ///     .try_build::<&str>(10)
+    ///     .unwrap_err();
+    ///
+    /// assert_eq!(err, Error::DuplicateResource);
+    /// ```
+    ///
+    /// Defaults to [`DuplicatePolicy::Allow`].
+    pub fn with_duplicate_policy(self, policy: DuplicatePolicy) -> Self
+    where
+        R: PartialEq,
+    {
+        Self {
+            duplicates: policy,
+            eq: R::eq,
+            ..self
+        }
+    }
+
+    /// Label the metrics emitted by the [`AnchorHash`] instance with `label`,
+    /// distinguishing them from the metrics of other instances.
+    ///
+    /// Metrics are registered with the [`metrics`] recorder installed when the
+    /// instance is built. Defaults to `"default"`.
+    ///
+    /// [`metrics`]: https://docs.rs/metrics
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics_label(self, label: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            metrics_label: label.into(),
+            ..self
+        }
+    }
+
+    /// Include the [`Debug`] rendering of resources in the `resource` field of
+    /// the spans emitted when resources are added and removed.
+    ///
+    /// Resources are not rendered by default, as they may be large or contain
+    /// sensitive data.
+    ///
+    /// [`Debug`]: core::fmt::Debug
+    #[cfg(feature = "tracing")]
+    pub fn with_traced_resources(self) -> Self
+    where
+        R: fmt::Debug,
+    {
+        Self {
+            debug: Some(R::fmt),
+            ..self
         }
     }
 
@@ -136,6 +389,12 @@ where
         Self {
             hasher: builder,
             resources: None,
+            duplicates: DuplicatePolicy::default(),
+            eq: never_equal,
+            #[cfg(feature = "metrics")]
+            metrics_label: Cow::Borrowed(telemetry::DEFAULT_LABEL),
+            #[cfg(feature = "tracing")]
+            debug: None,
         }
     }
 
@@ -148,14 +407,39 @@ where
     }
 }
 
-impl<R, K> FromIterator<R> for AnchorHash<K, R, RandomState>
-where
-    K: Hash,
-{
+/// Construct an [`AnchorHash`] with a capacity equal to the number of
+/// resources yielded by the iterator.
+///
+/// The instance uses the default [`DuplicatePolicy`], so duplicate resources
+/// are each assigned a bucket - use a [`Builder`] to configure a different
+/// policy.
+///
+/// # Panics
+///
+/// Collecting more than [`u16::MAX`] resources panics - use
+/// [`AnchorHash::try_from_iter()`] to handle this case without panicking.
+#[cfg(feature = "std")]
+impl<R, K> FromIterator<R> for AnchorHash<K, R, RandomState> {
     fn from_iter<T: IntoIterator<Item = R>>(iter: T) -> Self {
+        Self::try_from_iter(iter).expect("too many resources")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R, K> AnchorHash<K, R, RandomState> {
+    /// Construct an [`AnchorHash`] with a capacity equal to the number of
+    /// resources yielded by `iter`.
+    ///
+    /// This is the fallible equivalent of collecting into an `AnchorHash`,
+    /// returning [`Error::CapacityExceeded`] if `iter` yields more than
+    /// [`u16::MAX`] resources.
+    pub fn try_from_iter<T: IntoIterator<Item = R>>(iter: T) -> Result<Self> {
         let resources = iter.into_iter().collect::<Vec<_>>();
-        let n = u16::try_from(resources.len()).expect("too many resources");
-        Builder::default().with_resources(resources).build(n)
+        let n = u16::try_from(resources.len()).map_err(|_| Error::CapacityExceeded {
+            requested: resources.len(),
+            capacity: u16::MAX,
+        })?;
+        Builder::default().with_resources(resources).try_build(n)
     }
 }
 
@@ -243,19 +527,38 @@ where
 /// println!("user mapped to: {}", backend);
 /// ```
 ///
+/// The key type `K` only restricts [`get_resource()`] - [`lookup()`] accepts
+/// a reference to any key type that implements [`Hash`], allowing a single
+/// instance to be queried with both `&str` and `String` keys, or several key
+/// types that hash identically. An instance that is not tied to any key type
+/// can be built with [`Builder::build_any_key()`].
+///
 /// [`AnchorHash: A Scalable Consistent Hash`]: https://arxiv.org/abs/1812.09674
 /// [`HashMap`]: std::collections::HashMap  
 /// [easily swapped]: Builder::with_hasher  
 /// [`Hash`]: std::hash::Hash  
+/// [`get_resource()`]: Self::get_resource  
+/// [`lookup()`]: Self::lookup  
 #[derive(Debug)]
 pub struct AnchorHash<K, R, B>
 where
-    K: Hash,
     B: BuildHasher,
 {
     anchor: Anchor,
     hasher: B,
-    resources: HashMap<u16, R>,
+    /// The resource assigned to each bucket, indexed by bucket.
+    resources: Vec<Option<R>>,
+    /// The number of assigned resources.
+    len: usize,
+    duplicates: DuplicatePolicy,
+
+    #[cfg(feature = "metrics")]
+    metrics: Metrics,
+
+    /// Renders resources into tracing spans, when enabled by
+    /// [`Builder::with_traced_resources()`].
+    #[cfg(feature = "tracing")]
+    debug: Option<DebugFn<R>>,
 
     _key_type: PhantomData<K>,
 }
@@ -266,7 +569,6 @@ where
 /// Note the key type (`K`) does NOT have to implement `Clone`.
 impl<K, R, B> Clone for AnchorHash<K, R, B>
 where
-    K: Hash,
     B: BuildHasher + Clone,
     R: Clone,
 {
@@ -275,31 +577,425 @@ where
             anchor: self.anchor.clone(),
             hasher: self.hasher.clone(),
             resources: self.resources.clone(),
-            _key_type: PhantomData::default(),
+            len: self.len,
+            duplicates: self.duplicates,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics.clone(),
+            #[cfg(feature = "tracing")]
+            debug: self.debug,
+            _key_type: PhantomData,
         }
     }
 }
 
+/// Two instances are equal when they hold the same resources and map every key
+/// to the same resource, given the same hasher.
+///
+/// Neither the hash builder (`B`) nor the [`DuplicatePolicy`] are compared.
+impl<K, R, B> PartialEq for AnchorHash<K, R, B>
+where
+    B: BuildHasher,
+    R: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.anchor == other.anchor && self.resources == other.resources
+    }
+}
+
+impl<K, R, B> Eq for AnchorHash<K, R, B>
+where
+    B: BuildHasher,
+    R: Eq,
+{
+}
+
 impl<K, R, B> AnchorHash<K, R, B>
 where
     K: Hash,
     B: BuildHasher,
-    R: PartialEq,
 {
     /// Consistently hash `key` to a configured resource.
     ///
     /// This method will return [`None`] when `self` contains no resources.
+    ///
+    /// This is equivalent to calling [`lookup()`] with a reference to `key`.
+    ///
+    /// [`lookup()`]: Self::lookup
     pub fn get_resource(&self, key: K) -> Option<&R> {
-        // Hash the key to a u32 value
-        let mut hasher = self.hasher.build_hasher();
-        key.hash(&mut hasher);
-        let key = hasher.finish();
+        self.lookup(&key)
+    }
+}
 
-        // Lookup the bucket this key maps to
-        let b = self.anchor.get_bucket(key as u32);
+impl<K, R, B> AnchorHash<K, R, B>
+where
+    B: BuildHasher,
+{
+    /// Consistently hash `key` to a configured resource.
+    ///
+    /// Unlike [`get_resource()`], the key type is chosen per call and is not
+    /// restricted to `K`, allowing any type that hashes identically to be used
+    /// for lookups - for example, both `&str` and `String` map to the same
+    /// resource:
+    ///
+    /// ```rust
+    /// let anchor = anchorhash::Builder::default()
+    ///     .with_resources(vec!["cache1", "cache2", "cache3"])
+    ///     .build_any_key(20);
+    ///
+    /// assert_eq!(
+    ///     anchor.lookup("user-A"),
+    ///     anchor.lookup(&"user-A".to_string())
+    /// );
+    /// ```
+    ///
+    /// This method will return [`None`] when `self` contains no resources.
+    ///
+    /// [`get_resource()`]: Self::get_resource
+    pub fn lookup<Q>(&self, key: &Q) -> Option<&R>
+    where
+        Q: Hash + ?Sized,
+    {
+        self.lookup_hash(self.hash_key(key))
+    }
+
+    /// Resolve `key` to a configured resource as [`lookup()`] does, recording
+    /// every bucket examined along the way.
+    ///
+    /// Keys that map to a removed bucket are redirected to a working bucket,
+    /// and the returned [`LookupPath`] describes the buckets visited - useful
+    /// for debugging a routing decision, or measuring lookup cost:
+    ///
+    /// ```rust
+    /// let anchor = anchorhash::Builder::default()
+    ///     .with_resources(vec!["cache1", "cache2", "cache3"])
+    ///     .build_any_key(20);
+    ///
+    /// let path = anchor.explain("user-A").unwrap();
+    /// assert_eq!(Some(path.resource()), anchor.lookup("user-A"));
+    /// println!("visited buckets {:?}", path.buckets());
+    /// ```
+    ///
+    /// A removed bucket is followed by the working bucket that replaced it,
+    /// found either through its successor or by searching the history of the
+    /// working set - the intermediate successors of a long chain are not
+    /// examined, and are not included in the path.
+    ///
+    /// This method will return [`None`] when `self` contains no resources.
+    ///
+    /// [`lookup()`]: Self::lookup
+    pub fn explain<Q>(&self, key: &Q) -> Option<LookupPath<'_, R>>
+    where
+        Q: Hash + ?Sized,
+    {
+        if self.anchor.working() == 0 {
+            return None;
+        }
+
+        let hash = self.hash_key(key);
+        let mut buckets = Vec::new();
+        let b = self
+            .anchor
+            .get_bucket_with(hash as u32, |b| buckets.push(b));
+
+        let resource = self.bucket_resource(b)?;
+        Some(LookupPath::new(hash, buckets, resource))
+    }
+
+    /// Hash `key` with the hasher of this instance.
+    pub(crate) fn hash_key<Q>(&self, key: &Q) -> u64
+    where
+        Q: Hash + ?Sized,
+    {
+        self.hasher.hash_one(key)
+    }
 
+    /// Resolve the (already hashed) key `hash` to a configured resource.
+    pub(crate) fn lookup_hash(&self, hash: u64) -> Option<&R> {
         // Resolve the bucket -> resource indirection
-        self.resources.get(&b)
+        self.lookup_hash_bucket(hash)
+            .and_then(|b| self.bucket_resource(b))
+    }
+
+    /// Returns the resource assigned to bucket `b`, if any.
+    pub(crate) fn bucket_resource(&self, b: u16) -> Option<&R> {
+        self.resources.get(usize::from(b))?.as_ref()
+    }
+
+    /// Returns the maximum number of resources of this instance.
+    #[cfg(feature = "std")]
+    pub(crate) fn capacity(&self) -> u16 {
+        self.anchor.capacity()
+    }
+
+    /// Resolve the (already hashed) key `hash` to the working bucket it maps
+    /// to.
+    pub(crate) fn lookup_hash_bucket(&self, hash: u64) -> Option<u16> {
+        // An instance with no resources (including a zero capacity instance)
+        // maps keys to nothing.
+        if self.anchor.working() == 0 {
+            #[cfg(feature = "metrics")]
+            self.metrics.lookup_empty();
+            return None;
+        }
+
+        #[cfg(feature = "metrics")]
+        let b = {
+            // Every bucket visited before the working bucket is removed.
+            let mut visited = 0;
+            let b = self.anchor.get_bucket_with(hash as u32, |_| visited += 1);
+            self.metrics.lookup(visited - 1);
+            b
+        };
+
+        #[cfg(not(feature = "metrics"))]
+        let b = self.anchor.get_bucket(hash as u32);
+
+        #[cfg(feature = "trace-lookups")]
+        tracing::trace!(hash, bucket = b, "lookup");
+
+        Some(b)
+    }
+
+    /// Returns the resource assigned to each working bucket, in bucket order.
+    pub(crate) fn assignments(&self) -> impl Iterator<Item = (u16, &R)> + '_ {
+        // The slab never exceeds u16::MAX entries.
+        (0..=u16::MAX)
+            .zip(self.resources.iter())
+            .filter_map(|(b, r)| Some((b, r.as_ref()?)))
+    }
+
+    /// Returns an iterator yielding references to the configured resources in
+    /// bucket order.
+    pub fn resources(&self) -> ResourceIterator<'_, R> {
+        ResourceIterator::new(&self.resources, self.len)
+    }
+
+    /// Returns an iterator yielding mutable references to the configured
+    /// resources in bucket order.
+    pub fn resources_mut(&mut self) -> ResourceMutIterator<'_, R> {
+        ResourceMutIterator::new(&mut self.resources, self.len)
+    }
+
+    /// Check the internal state of this instance satisfies the invariants of
+    /// the AnchorHash algorithm, returning the first violation found.
+    ///
+    /// The invariants always hold for an instance manipulated through this
+    /// API, and the invariants of the internal arrays are checked after every
+    /// change in debug builds - a violation indicates a bug in this crate.
+    /// This method allows the state to be checked in release builds, for
+    /// example after restoring an instance from a [`Snapshot`]:
+    ///
+    /// ```rust
+    /// let mut anchor = anchorhash::Builder::default()
+    ///     .with_resources(vec!["cache1", "cache2", "cache3"])
+    ///     .build_any_key(20);
+    /// anchor.remove_resource(&"cache2").unwrap();
+    ///
+    /// assert_eq!(anchor.validate(), Ok(()));
+    /// ```
+    ///
+    /// This runs in linear time w.r.t the capacity.
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        self.anchor.validate()?;
+        if self.resources.len() != usize::from(self.anchor.capacity()) {
+            return Err(InvariantViolation::ArrayLength {
+                len: self.resources.len(),
+                capacity: self.anchor.capacity(),
+            });
+        }
+        self.anchor
+            .validate_resources(|b| self.resources[usize::from(b)].is_some())
+    }
+
+    /// Build an immutable [`FrozenAnchorHash`] from the current state of this
+    /// instance, with a lookup table optimised for read-only use.
+    ///
+    /// The frozen instance maps every key to the same resource as `self`, and
+    /// is unaffected by later changes to `self` - freeze the instance again
+    /// after each change to the resource set.
+    ///
+    /// This runs in linear time w.r.t the capacity.
+    pub fn freeze(&self) -> FrozenAnchorHash<K, R, B>
+    where
+        R: Clone,
+        B: Clone,
+    {
+        FrozenAnchorHash::new(&self.anchor, self.assignments(), self.hasher.clone())
+    }
+
+    /// Returns the [`DuplicatePolicy`] applied when adding resources.
+    pub fn duplicate_policy(&self) -> DuplicatePolicy {
+        self.duplicates
+    }
+
+    /// Capture the current state of this instance as a [`Snapshot`].
+    pub fn to_snapshot(&self) -> Snapshot<R>
+    where
+        R: Clone,
+    {
+        let resources = self
+            .assignments()
+            .map(|(b, r)| (b, r.clone()))
+            .collect::<Vec<_>>();
+
+        Snapshot::new(&self.anchor, resources)
+    }
+
+    /// Serialise the lookup table of this instance into an archive that can be
+    /// read in place by an [`ArchiveView`], calling `resource_id` to obtain an
+    /// ID for each resource.
+    ///
+    /// `generation` is recorded in the archive, and is typically incremented
+    /// each time a new archive is published.
+    ///
+    /// Returns [`Error::InvalidArchive`] if `resource_id` returns
+    /// [`u64::MAX`], which is reserved to mark buckets with no resource.
+    ///
+    /// [`ArchiveView`]: crate::ArchiveView
+    pub fn to_archive<F>(&self, generation: u64, mut resource_id: F) -> Result<Vec<u8>>
+    where
+        F: FnMut(&R) -> u64,
+    {
+        archive::encode(&self.anchor, generation, |b| {
+            self.bucket_resource(b).map(&mut resource_id)
+        })
+    }
+
+    /// Returns a stable digest of the routing state of this instance - the
+    /// internal bucket state and the resource assigned to each bucket.
+    ///
+    /// Instances that map keys identically have equal fingerprints, making the
+    /// fingerprint a cheap way to confirm multiple nodes hold the same routing
+    /// state:
+    ///
+    /// ```rust
+    /// let mut a = anchorhash::Builder::default()
+    ///     .with_resources(vec!["cache1", "cache2", "cache3"])
+    ///     .build::<&str>(20);
+    /// let mut b = a.clone();
+    /// assert_eq!(a.fingerprint(), b.fingerprint());
+    ///
+    /// b.remove_resource(&"cache2").unwrap();
+    /// assert_ne!(a.fingerprint(), b.fingerprint());
+    ///
+    /// // Disagreeing buckets can be identified with diff().
+    /// assert_eq!(a.diff(&b)[0].bucket(), 1);
+    /// ```
+    ///
+    /// The digest does not depend on the hasher used by this instance, and is
+    /// comparable across processes provided the [`Hash`] implementation of the
+    /// resource type is itself stable - note the hash of integer types such as
+    /// `usize` may differ between platforms.
+    ///
+    /// [`Hash`]: core::hash::Hash
+    pub fn fingerprint(&self) -> u64
+    where
+        R: Hash,
+    {
+        consistency::fingerprint(&self.anchor, self.assignments())
+    }
+
+    /// Compare the state of every bucket in `self` and `other`, returning the
+    /// buckets that disagree in their resource, whether they are working, or
+    /// their internal `A[b]` / `K[b]` state.
+    ///
+    /// An empty diff means both instances are equal.
+    pub fn diff<'a>(&'a self, other: &'a Self) -> Vec<BucketDiff<'a, R>>
+    where
+        R: PartialEq,
+    {
+        consistency::diff(
+            &self.anchor,
+            |b| self.bucket_resource(b),
+            &other.anchor,
+            |b| other.bucket_resource(b),
+        )
+    }
+
+    /// Restore an instance from `snapshot`, using `hasher` to hash keys.
+    ///
+    /// The restored instance maps keys to the same resources as the instance
+    /// the snapshot was taken from, provided `hasher` is configured identically
+    /// to the original hasher.
+    ///
+    /// The restored instance uses the default [`DuplicatePolicy`], which can be
+    /// changed with [`set_duplicate_policy()`].
+    ///
+    /// [`set_duplicate_policy()`]: Self::set_duplicate_policy
+    pub fn from_snapshot(snapshot: Snapshot<R>, hasher: B) -> Result<Self> {
+        let (anchor, resources) = snapshot.into_parts()?;
+
+        let len = resources.len();
+        let mut slots = Vec::new();
+        slots.resize_with(usize::from(anchor.capacity()), || None);
+        for (b, r) in resources {
+            slots[usize::from(b)] = Some(r);
+        }
+
+        let a = Self {
+            anchor,
+            hasher,
+            resources: slots,
+            len,
+            duplicates: DuplicatePolicy::default(),
+            #[cfg(feature = "metrics")]
+            metrics: Metrics::new(Cow::Borrowed(telemetry::DEFAULT_LABEL)),
+            #[cfg(feature = "tracing")]
+            debug: None,
+            _key_type: PhantomData,
+        };
+        a.record_size();
+
+        Ok(a)
+    }
+
+    /// Returns the label of the metrics emitted by this instance.
+    #[cfg(feature = "metrics")]
+    pub fn metrics_label(&self) -> &str {
+        self.metrics.label()
+    }
+
+    /// Label the metrics emitted by this instance with `label`, registering
+    /// them with the currently installed [`metrics`] recorder.
+    ///
+    /// [`metrics`]: https://docs.rs/metrics
+    #[cfg(feature = "metrics")]
+    pub fn set_metrics_label(&mut self, label: impl Into<Cow<'static, str>>) {
+        self.metrics = Metrics::new(label.into());
+        self.record_size();
+    }
+
+    /// Record the `Debug` rendering of `resource` in the `resource` field of
+    /// `span`, if enabled.
+    #[cfg(feature = "tracing")]
+    fn record_resource(&self, span: &tracing::Span, resource: &R) {
+        if let Some(f) = self.debug {
+            span.record(
+                "resource",
+                tracing::field::debug(TracedResource::new(resource, f)),
+            );
+        }
+    }
+
+    /// Record the working set size and capacity metrics.
+    fn record_size(&self) {
+        #[cfg(feature = "metrics")]
+        self.metrics
+            .set_size(self.anchor.working(), self.anchor.capacity());
+    }
+}
+
+impl<K, R, B> AnchorHash<K, R, B>
+where
+    B: BuildHasher,
+    R: PartialEq,
+{
+    /// Change the [`DuplicatePolicy`] applied when adding resources.
+    ///
+    /// The new policy only affects subsequent additions and removals - any
+    /// duplicates already present are retained.
+    pub fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicates = policy;
     }
 
     /// Add `resource`, allowing keys to map to it.
@@ -312,14 +1008,55 @@ where
     ///
     /// A subset of keys from each resource is mapped to the new resource
     /// ensuring minimal disruption with optimal load sharing.
+    ///
+    /// If `resource` is equal to an existing resource and the
+    /// [`DuplicatePolicy`] is [`Reject`], [`Error::DuplicateResource`] is
+    /// returned and the instance is left unchanged.
+    ///
+    /// [`Reject`]: DuplicatePolicy::Reject
     pub fn add_resource(&mut self, resource: R) -> Result<()> {
-        let b = self
-            .anchor
-            .add_bucket()
-            .ok_or(Error::CapacityLimitReached)?;
+        #[cfg(feature = "tracing")]
+        let _span = {
+            let span = tracing::debug_span!("add_resource", resource = tracing::field::Empty);
+            self.record_resource(&span, &resource);
+            span.entered()
+        };
+
+        if self.duplicates == DuplicatePolicy::Reject && self.resources().any(|r| *r == resource) {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(error = %Error::DuplicateResource, "failed to add resource");
+            return Err(Error::DuplicateResource);
+        }
+
+        let b = match self.anchor.add_bucket() {
+            Some(v) => v,
+            None => {
+                #[cfg(feature = "metrics")]
+                self.metrics.capacity_limit_reached();
+                #[cfg(feature = "tracing")]
+                tracing::debug!(error = %Error::CapacityLimitReached, "failed to add resource");
+                return Err(Error::CapacityLimitReached);
+            }
+        };
 
         // The bucket MUST NOT already be in use
-        assert!(self.resources.insert(b, resource).is_none());
+        let slot = &mut self.resources[usize::from(b)];
+        assert!(slot.is_none());
+        *slot = Some(resource);
+        self.len += 1;
+
+        #[cfg(feature = "metrics")]
+        self.metrics.added();
+        self.record_size();
+
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            bucket = b,
+            working_before = self.anchor.working() - 1,
+            working_after = self.anchor.working(),
+            removed_stack = self.anchor.removed().len(),
+            "resource added"
+        );
 
         Ok(())
     }
@@ -331,8 +1068,18 @@ where
     /// not map to `resource` continue mapping to the same resource as before
     /// the removal.
     ///
+    /// If `resource` was added more than once, the [`DuplicatePolicy`]
+    /// determines whether a single copy or all copies are removed.
+    ///
     /// Removal runs in linear time w.r.t the number of resources.
     pub fn remove_resource(&mut self, resource: &R) -> Result<()> {
+        #[cfg(feature = "tracing")]
+        let _span = {
+            let span = tracing::debug_span!("remove_resource", resource = tracing::field::Empty);
+            self.record_resource(&span, resource);
+            span.entered()
+        };
+
         // This could be an O(1) operation by using a bimap, but then R would
         // require Hash bounds making this implementation less flexible.
         //
@@ -340,33 +1087,55 @@ where
         // data - removing an element from a capacity=10000 & resources=1000
         // AnchorHash instance takes ~1us on a 2.6Ghz Intel Core i7.
 
-        let b = self
-            .resources
-            .iter()
-            .find(|(_k, r)| *r == resource)
-            .map(|(&k, _r)| k)
-            .ok_or(Error::ResourceNotFound)?;
-
-        self.resources.remove(&b);
-        self.anchor.remove_bucket(b);
-        Ok(())
-    }
+        let mut buckets = self
+            .assignments()
+            .filter(|(_b, r)| *r == resource)
+            .map(|(b, _r)| b)
+            .collect::<Vec<_>>();
+
+        if buckets.is_empty() {
+            #[cfg(feature = "metrics")]
+            self.metrics.resource_not_found();
+            #[cfg(feature = "tracing")]
+            tracing::debug!(error = %Error::ResourceNotFound, "failed to remove resource");
+            return Err(Error::ResourceNotFound);
+        }
 
-    /// Returns an iterator yielding references to the configured resources in
-    /// an arbitrary order.
-    pub fn resources(&self) -> ResourceIterator<'_, R> {
-        self.resources.values().into()
-    }
+        // The buckets are yielded in bucket order, so every instance removes
+        // the same buckets in the same order.
+        if self.duplicates != DuplicatePolicy::Weighted {
+            buckets.truncate(1);
+        }
 
-    /// Returns an iterator yielding mutable references to the configured
-    /// resources in an arbitrary order.
-    pub fn resources_mut(&mut self) -> ResourceMutIterator<'_, R> {
-        self.resources.values_mut().into()
+        #[cfg(feature = "metrics")]
+        let n = buckets.len();
+
+        for b in buckets {
+            self.anchor.remove_bucket(b)?;
+            self.resources[usize::from(b)] = None;
+            self.len -= 1;
+
+            #[cfg(feature = "tracing")]
+            tracing::info!(
+                bucket = b,
+                working_before = self.anchor.working() + 1,
+                working_after = self.anchor.working(),
+                removed_stack = self.anchor.removed().len(),
+                "resource removed"
+            );
+        }
+
+        #[cfg(feature = "metrics")]
+        self.metrics.removed(n);
+        self.record_size();
+
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use fnv::FnvBuildHasher;
     use hashbrown::HashSet;
 
     use super::*;
@@ -409,19 +1178,18 @@ mod tests {
         let working = a.anchor.working_buckets();
         assert_eq!(working.len(), servers.len());
 
-        // Check the resource map is fully populated
-        assert_eq!(a.resources.len(), servers.len());
+        // Check the resource slab is fully populated
+        assert_eq!(a.resources.len(), 10);
         assert_eq!(a.resources().len(), servers.len());
 
-        // All the keys map to working buckets (and are distinct as the map
-        // capacity matches the number of working buckets)
-        for bucket in a.resources.keys() {
-            assert!(working.contains(bucket));
+        // All the resources are assigned to working buckets
+        for (bucket, _r) in a.assignments() {
+            assert!(working.contains(&bucket));
         }
 
-        // All the resources are present in the map
-        let values = a.resources.values().cloned().collect::<HashSet<_>>();
-        assert_eq!(values, servers.into_iter().collect::<HashSet<_>>());
+        // All the resources are present in the slab, in bucket order
+        let values = a.resources().cloned().collect::<Vec<_>>();
+        assert_eq!(values, servers);
     }
 
     #[test]
@@ -513,4 +1281,315 @@ mod tests {
             assert_eq!(a.get_resource(i), b.get_resource(i));
         }
     }
+
+    #[test]
+    fn test_lookup_borrowed_keys() {
+        let a: AnchorHash<String, _, _> = Builder::default()
+            .with_resources(vec!["A", "B", "C", "D"])
+            .build(10);
+
+        for i in 0..100 {
+            let key = i.to_string();
+            let want = a.get_resource(key.clone());
+            assert_eq!(a.lookup(&key), want);
+            assert_eq!(a.lookup(key.as_str()), want);
+        }
+    }
+
+    #[test]
+    fn test_lookup_any_key() {
+        // Resources that do not implement PartialEq can still be looked up.
+        #[derive(Debug)]
+        struct Backend(usize);
+
+        let a = Builder::default()
+            .with_resources(vec![Backend(1), Backend(2), Backend(3)])
+            .build_any_key(10);
+
+        let typed: AnchorHash<u64, _, _> = Builder::with_hasher(a.hasher.clone())
+            .with_resources(vec![1, 2, 3])
+            .build(10);
+
+        // Keys of different types can be used against the same instance, and
+        // map to the same resource as a typed instance.
+        for i in 0..100_u64 {
+            let want = typed.get_resource(i).unwrap();
+            assert_eq!(a.lookup(&i).unwrap().0, *want);
+        }
+        assert!(a.lookup("a key").is_some());
+        assert!(a.lookup(&("compound", 42)).is_some());
+    }
+
+    #[test]
+    fn test_try_build_capacity_exceeded() {
+        let err = Builder::default()
+            .with_resources(vec![1, 2, 3])
+            .try_build::<usize>(2)
+            .expect_err("should not allow 3 resources for capacity == 2");
+
+        assert_eq!(
+            err,
+            Error::CapacityExceeded {
+                requested: 3,
+                capacity: 2
+            }
+        );
+
+        // Exactly filling the capacity is fine.
+        let a: AnchorHash<usize, _, _> = Builder::default()
+            .with_resources(vec![1, 2, 3])
+            .try_build(3)
+            .expect("resources fit within capacity");
+        assert_eq!(a.resources().len(), 3);
+    }
+
+    #[test]
+    fn test_try_from_iter() {
+        let a = AnchorHash::<usize, _, _>::try_from_iter(vec!["A", "B"]).unwrap();
+        assert_eq!(a.resources().len(), 2);
+
+        let err = AnchorHash::<usize, _, _>::try_from_iter(0..=u16::MAX as usize)
+            .expect_err("should not exceed u16::MAX resources");
+        assert_eq!(
+            err,
+            Error::CapacityExceeded {
+                requested: u16::MAX as usize + 1,
+                capacity: u16::MAX
+            }
+        );
+    }
+
+    #[test]
+    fn test_duplicate_reject() {
+        let err = Builder::default()
+            .with_duplicate_policy(DuplicatePolicy::Reject)
+            .with_resources(vec!["A", "B", "A"])
+            .try_build::<usize>(10)
+            .unwrap_err();
+        assert_eq!(err, Error::DuplicateResource);
+
+        let mut a: AnchorHash<usize, _, _> = Builder::default()
+            .with_duplicate_policy(DuplicatePolicy::Reject)
+            .with_resources(vec!["A", "B"])
+            .build(10);
+        assert_eq!(a.add_resource("A"), Err(Error::DuplicateResource));
+        assert_eq!(a.resources().len(), 2);
+
+        a.add_resource("C").expect("should add distinct resource");
+        assert_eq!(a.resources().len(), 3);
+    }
+
+    #[test]
+    fn test_resources_bucket_order() {
+        let mut a: AnchorHash<usize, _, _> = Builder::default()
+            .with_resources(vec!["A", "B", "C", "D"])
+            .build(10);
+
+        // "E" reuses the bucket released by "B".
+        a.remove_resource(&"B").unwrap();
+        a.add_resource("E").unwrap();
+        a.add_resource("F").unwrap();
+
+        let got = a.resources().cloned().collect::<Vec<_>>();
+        assert_eq!(got, ["A", "E", "C", "D", "F"]);
+
+        let got = a.resources_mut().map(|v| *v).collect::<Vec<_>>();
+        assert_eq!(got, ["A", "E", "C", "D", "F"]);
+    }
+
+    #[test]
+    fn test_duplicate_allow() {
+        let mut a: AnchorHash<usize, _, _> = Builder::default()
+            .with_resources(vec!["A", "B", "A"])
+            .build(10);
+        assert_eq!(a.duplicate_policy(), DuplicatePolicy::Allow);
+
+        a.add_resource("B").expect("duplicates allowed");
+        assert_eq!(a.resources().len(), 4);
+
+        // Each removal removes the copy in the lowest bucket.
+        a.remove_resource(&"A").unwrap();
+        assert_eq!(a.bucket_resource(0), None);
+        assert_eq!(a.bucket_resource(2), Some(&"A"));
+
+        a.remove_resource(&"A").unwrap();
+        assert_eq!(a.remove_resource(&"A"), Err(Error::ResourceNotFound));
+        assert_eq!(a.resources().len(), 2);
+    }
+
+    #[test]
+    fn test_duplicate_weighted() {
+        let mut a: AnchorHash<usize, _, _> = Builder::default()
+            .with_duplicate_policy(DuplicatePolicy::Weighted)
+            .with_resources(vec!["A", "B", "A"])
+            .build(10);
+        a.add_resource("A").expect("weight added");
+
+        // "A" holds 3 of the 4 buckets.
+        let got = (0..10_000)
+            .filter(|k| a.get_resource(*k) == Some(&"A"))
+            .count();
+        assert!((6_500..8_500).contains(&got), "got {}", got);
+
+        // Removing "A" removes all of its weight.
+        a.remove_resource(&"A").unwrap();
+        assert_eq!(a.resources().collect::<Vec<_>>(), [&"B"]);
+        assert!((0..100).all(|k| a.get_resource(k) == Some(&"B")));
+        assert_eq!(a.remove_resource(&"A"), Err(Error::ResourceNotFound));
+    }
+
+    /// Instances with the same history remove the same copy of a duplicate
+    /// resource, regardless of map iteration order.
+    #[test]
+    fn test_duplicate_removal_deterministic() {
+        let build = || {
+            let mut a: AnchorHash<usize, _, _> = Builder::with_hasher(FnvBuildHasher::default())
+                .with_resources((0..100).map(|v| v % 10))
+                .build(100);
+            for r in 0..5 {
+                a.remove_resource(&r).unwrap();
+            }
+            a
+        };
+
+        assert_eq!(build().to_snapshot(), build().to_snapshot());
+    }
+
+    #[test]
+    fn test_eq_and_fingerprint() {
+        let a: AnchorHash<usize, _, _> = Builder::default()
+            .with_resources(vec!["A", "B", "C"])
+            .build(10);
+
+        // A different history resulting in the same state, using a different
+        // hasher.
+        let mut b: AnchorHash<usize, _, _> =
+            Builder::default().with_resources(vec!["A", "B"]).build(10);
+        b.add_resource("D").unwrap();
+        b.remove_resource(&"D").unwrap();
+        b.add_resource("C").unwrap();
+
+        assert_eq!(a, b);
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        assert!(a.diff(&b).is_empty());
+
+        // Same resources, different bucket assignment.
+        let c: AnchorHash<usize, _, _> = Builder::default()
+            .with_resources(vec!["A", "C", "B"])
+            .build(10);
+        assert_ne!(a, c);
+        assert_ne!(a.fingerprint(), c.fingerprint());
+
+        // Same resources, different capacity.
+        let d: AnchorHash<usize, _, _> = Builder::default()
+            .with_resources(vec!["A", "B", "C"])
+            .build(11);
+        assert_ne!(a, d);
+        assert_ne!(a.fingerprint(), d.fingerprint());
+    }
+
+    #[test]
+    fn test_diff() {
+        let a: AnchorHash<usize, _, _> = Builder::default()
+            .with_resources(vec!["A", "B", "C", "D"])
+            .build(6);
+
+        // Different resource in bucket 1.
+        let b: AnchorHash<usize, _, _> = Builder::default()
+            .with_resources(vec!["A", "X", "C", "D"])
+            .build(6);
+        let diff = a.diff(&b);
+        assert_eq!(diff.len(), 1);
+        assert_eq!(diff[0].bucket(), 1);
+        assert_eq!(diff[0].left().unwrap().resource(), Some(&"B"));
+        assert_eq!(diff[0].right().unwrap().resource(), Some(&"X"));
+
+        // Bucket 3 removed, and bucket 2 removed then re-added.
+        let mut c = a.clone();
+        c.remove_resource(&"D").unwrap();
+        c.remove_resource(&"C").unwrap();
+        c.add_resource("C").unwrap();
+        let diff = a.diff(&c);
+        assert_eq!(diff.iter().map(|d| d.bucket()).collect::<Vec<_>>(), [3]);
+        let (l, r) = (diff[0].left().unwrap(), diff[0].right().unwrap());
+        assert!(l.is_working());
+        assert!(!r.is_working());
+        assert_eq!(r.resource(), None);
+        assert_eq!((l.a(), r.a()), (0, 3));
+
+        // Buckets beyond the capacity of one instance.
+        let d: AnchorHash<usize, _, _> = Builder::default()
+            .with_resources(vec!["A", "B", "C", "D"])
+            .build(7);
+        let diff = a.diff(&d);
+        assert!(diff.iter().any(|d| d.bucket() == 6 && d.left().is_none()));
+    }
+
+    #[test]
+    fn test_explain() {
+        let mut a: AnchorHash<usize, _, _> = Builder::default().with_resources(0..10).build(20);
+        for r in [3, 7, 1] {
+            a.remove_resource(&r).unwrap();
+        }
+
+        let mut redirected = false;
+        for k in 0..1_000 {
+            let path = a.explain(&k).unwrap();
+            assert_eq!(Some(path.resource()), a.get_resource(k));
+            assert_eq!(path.hops(), path.buckets().len() - 1);
+            assert_eq!(a.bucket_resource(path.bucket()), Some(path.resource()));
+            redirected |= path.hops() > 0;
+        }
+        assert!(redirected);
+
+        let empty: AnchorHash<usize, usize, _> = Builder::default().build(10);
+        assert!(empty.explain(&42).is_none());
+    }
+
+    #[test]
+    fn test_zero_capacity() {
+        let mut a: AnchorHash<usize, usize, _> = Builder::default().build(0);
+
+        assert!(a.get_resource(42).is_none());
+        assert_eq!(a.add_resource(1), Err(Error::CapacityLimitReached));
+        assert_eq!(a.remove_resource(&1), Err(Error::ResourceNotFound));
+        assert_eq!(a.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut a: AnchorHash<usize, _, _> = Builder::default()
+            .with_resources(vec!["A", "B", "C", "D"])
+            .build(10);
+        a.remove_resource(&"B").unwrap();
+        a.add_resource("E").unwrap();
+        a.remove_resource(&"A").unwrap();
+        assert_eq!(a.validate(), Ok(()));
+
+        // Bucket 0 was assigned "A", and is no longer working.
+        let mut b = a.clone();
+        b.resources[0] = Some("A");
+        assert_eq!(
+            b.validate(),
+            Err(InvariantViolation::UnexpectedResource { bucket: 0 })
+        );
+
+        // A resource assigned to a bucket beyond the capacity.
+        let mut b = a.clone();
+        b.resources.push(Some("X"));
+        assert_eq!(
+            b.validate(),
+            Err(InvariantViolation::ArrayLength {
+                len: 11,
+                capacity: 10
+            })
+        );
+
+        let mut b = a;
+        b.resources[2] = None;
+        assert_eq!(
+            b.validate(),
+            Err(InvariantViolation::MissingResource { bucket: 2 })
+        );
+    }
 }
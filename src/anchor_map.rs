@@ -0,0 +1,475 @@
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+    sync::RwLock,
+};
+
+use hashbrown::HashMap;
+
+use crate::{AnchorHash, Builder, Error, Result};
+
+/// An opaque identifier of a shard in an [`AnchorMap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ShardId(u64);
+
+/// The entries moved between shards by [`AnchorMap::add_shard()`] or
+/// [`AnchorMap::remove_shard()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Migration {
+    shard: ShardId,
+    moved: usize,
+    entries: usize,
+}
+
+impl Migration {
+    /// Returns the shard that was added or removed.
+    pub fn shard(&self) -> ShardId {
+        self.shard
+    }
+
+    /// Returns the number of entries moved to a different shard.
+    pub fn moved(&self) -> usize {
+        self.moved
+    }
+
+    /// Returns the total number of entries in the map.
+    pub fn entries(&self) -> usize {
+        self.entries
+    }
+}
+
+#[derive(Debug)]
+struct Shards<K, V, B>
+where
+    B: BuildHasher,
+{
+    anchor: AnchorHash<K, ShardId, B>,
+    shards: HashMap<ShardId, RwLock<HashMap<K, V>>>,
+    next_id: u64,
+}
+
+/// A concurrent hash map partitioning entries across a set of independently
+/// locked shards with an [`AnchorHash`].
+///
+/// Operations on entries lock only the shard that owns the key, so operations
+/// on keys in different shards do not contend. Shards can be added and
+/// removed at runtime, and because [`AnchorHash`] moves the minimum number of
+/// keys, exactly the entries whose owner changed are migrated:
+///
+/// ```rust
+/// use anchorhash::AnchorMap;
+///
+/// // A map with 4 shards, and room for up to 16.
+/// let map = AnchorMap::new(16, 4);
+/// for i in 0..1_000 {
+///     map.insert(i, i * 2);
+/// }
+///
+/// // Adding a shard moves roughly 1/5 of the entries to it.
+/// let migration = map.add_shard().unwrap();
+/// assert!(migration.moved() < 300);
+///
+/// assert_eq!(map.get(&42), Some(84));
+/// ```
+///
+/// Adding or removing a shard blocks all other operations while the affected
+/// entries are migrated.
+#[derive(Debug)]
+pub struct AnchorMap<K, V, B = RandomState>
+where
+    B: BuildHasher,
+{
+    inner: RwLock<Shards<K, V, B>>,
+}
+
+impl<K, V> AnchorMap<K, V, RandomState>
+where
+    K: Hash + Eq,
+{
+    /// Initialise an empty map with `shards` shards, and support for up to
+    /// `capacity` shards.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `shards` is 0 or exceeds `capacity`.
+    pub fn new(capacity: u16, shards: u16) -> Self {
+        Self::with_hasher(capacity, shards, RandomState::default())
+    }
+}
+
+impl<K, V, B> AnchorMap<K, V, B>
+where
+    K: Hash + Eq,
+    B: BuildHasher,
+{
+    /// Initialise an empty map with `shards` shards, and support for up to
+    /// `capacity` shards, using `hasher` to map keys to shards.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `shards` is 0 or exceeds `capacity`.
+    pub fn with_hasher(capacity: u16, shards: u16, hasher: B) -> Self {
+        assert_ne!(shards, 0, "map must have at least one shard");
+
+        let ids = (0..u64::from(shards)).map(ShardId).collect::<Vec<_>>();
+        Self {
+            inner: RwLock::new(Shards {
+                anchor: Builder::with_hasher(hasher)
+                    .with_resources(ids.iter().copied())
+                    .build(capacity),
+                shards: ids
+                    .into_iter()
+                    .map(|id| (id, RwLock::new(HashMap::new())))
+                    .collect(),
+                next_id: u64::from(shards),
+            }),
+        }
+    }
+
+    /// Call `f` with the shard that owns `key`.
+    fn with_shard<Q, F, T>(&self, key: &Q, f: F) -> T
+    where
+        K: Borrow<Q>,
+        Q: Hash + ?Sized,
+        F: FnOnce(&RwLock<HashMap<K, V>>) -> T,
+    {
+        let inner = self.inner.read().unwrap();
+        let id = inner.anchor.lookup(key).expect("map must have a shard");
+        f(&inner.shards[id])
+    }
+
+    /// Returns a copy of the value for `key`.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.with_shard(key, |s| s.read().unwrap().get(key).cloned())
+    }
+
+    /// Returns the result of calling `f` with a reference to the value for
+    /// `key`, while holding the shard read lock.
+    pub fn get_with<Q, F, T>(&self, key: &Q, f: F) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&V) -> T,
+    {
+        self.with_shard(key, |s| s.read().unwrap().get(key).map(f))
+    }
+
+    /// Returns true if the map contains a value for `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.with_shard(key, |s| s.read().unwrap().contains_key(key))
+    }
+
+    /// Insert `value` for `key`, returning the previous value, if any.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let inner = self.inner.read().unwrap();
+        let id = inner.anchor.lookup(&key).expect("map must have a shard");
+        // Bind the result so the shard guard is dropped before the map guard.
+        let ret = inner.shards[id].write().unwrap().insert(key, value);
+        ret
+    }
+
+    /// Remove the entry for `key`, returning its value, if any.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.with_shard(key, |s| s.write().unwrap().remove(key))
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        let inner = self.inner.read().unwrap();
+        inner.shards.values().map(|s| s.read().unwrap().len()).sum()
+    }
+
+    /// Returns true if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Call `f` with every entry in the map, locking one shard at a time.
+    ///
+    /// Entries are visited in an arbitrary order.
+    ///
+    /// `f` is called while holding a read lock over the map and over the shard
+    /// of the entry, so it MUST NOT call any method of this map - modifying it
+    /// (such as [`insert()`] or [`add_shard()`]) deadlocks, and reading it may
+    /// deadlock if another thread is waiting to modify it.
+    ///
+    /// [`insert()`]: Self::insert
+    /// [`add_shard()`]: Self::add_shard
+    pub fn for_each<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &V),
+    {
+        let inner = self.inner.read().unwrap();
+        for shard in inner.shards.values() {
+            for (k, v) in shard.read().unwrap().iter() {
+                f(k, v);
+            }
+        }
+    }
+
+    /// Returns the number of entries in each shard.
+    pub fn shard_lens(&self) -> Vec<(ShardId, usize)> {
+        let inner = self.inner.read().unwrap();
+        let mut lens = inner
+            .shards
+            .iter()
+            .map(|(id, s)| (*id, s.read().unwrap().len()))
+            .collect::<Vec<_>>();
+        lens.sort_unstable();
+        lens
+    }
+
+    /// Add a new shard, migrating the entries that now map to it.
+    ///
+    /// Returns [`Error::CapacityLimitReached`] if the map has as many shards as
+    /// its capacity.
+    pub fn add_shard(&self) -> Result<Migration> {
+        let mut guard = self.inner.write().unwrap();
+        let inner = &mut *guard;
+
+        let id = ShardId(inner.next_id);
+        inner.anchor.add_resource(id)?;
+        inner.next_id += 1;
+
+        // Only keys that now map to the new shard move, so every other key
+        // remains in its shard.
+        let anchor = &inner.anchor;
+        let mut added = HashMap::new();
+        let mut entries = 0;
+        for shard in inner.shards.values_mut() {
+            let shard = shard.get_mut().unwrap();
+            added.extend(shard.extract_if(|k, _v| anchor.lookup(k) == Some(&id)));
+            entries += shard.len();
+        }
+
+        let moved = added.len();
+        inner.shards.insert(id, RwLock::new(added));
+
+        Ok(Migration {
+            shard: id,
+            moved,
+            entries: entries + moved,
+        })
+    }
+
+    /// Remove the shard identified by `id`, migrating its entries to the
+    /// remaining shards.
+    ///
+    /// Returns [`Error::ResourceNotFound`] if the shard does not exist, and
+    /// [`Error::InvalidState`] if it is the last shard.
+    pub fn remove_shard(&self, id: ShardId) -> Result<Migration> {
+        let mut guard = self.inner.write().unwrap();
+        let inner = &mut *guard;
+
+        if !inner.shards.contains_key(&id) {
+            return Err(Error::ResourceNotFound);
+        }
+        if inner.shards.len() == 1 {
+            return Err(Error::InvalidState("cannot remove the last shard"));
+        }
+
+        inner.anchor.remove_resource(&id)?;
+        let removed = inner
+            .shards
+            .remove(&id)
+            .expect("shard must be present")
+            .into_inner()
+            .unwrap();
+
+        // Only the keys of the removed shard move.
+        let moved = removed.len();
+        for (k, v) in removed {
+            let dst = inner.anchor.lookup(&k).expect("map must have a shard");
+            inner
+                .shards
+                .get_mut(dst)
+                .unwrap()
+                .get_mut()
+                .unwrap()
+                .insert(k, v);
+        }
+
+        let entries = inner
+            .shards
+            .values_mut()
+            .map(|s| s.get_mut().unwrap().len())
+            .sum();
+
+        Ok(Migration {
+            shard: id,
+            moved,
+            entries,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::hash_map::DefaultHasher, hash::BuildHasherDefault, sync::Arc, thread};
+
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+
+    type Map = AnchorMap<u64, u64, BuildHasherDefault<DefaultHasher>>;
+
+    fn map(shards: u16) -> Map {
+        AnchorMap::with_hasher(16, shards, BuildHasherDefault::default())
+    }
+
+    /// Returns the shard holding each key.
+    fn owners(m: &Map) -> HashMap<u64, ShardId> {
+        let inner = m.inner.read().unwrap();
+        let mut owners = HashMap::new();
+        for (id, s) in &inner.shards {
+            for k in s.read().unwrap().keys() {
+                owners.insert(*k, *id);
+            }
+        }
+        owners
+    }
+
+    /// Assert every entry is held by the shard its key maps to.
+    fn assert_placed(m: &Map) {
+        let inner = m.inner.read().unwrap();
+        for (k, id) in owners(m) {
+            assert_eq!(inner.anchor.lookup(&k), Some(&id), "key {}", k);
+        }
+    }
+
+    #[test]
+    fn test_map() {
+        let m = map(4);
+        assert!(m.is_empty());
+        assert_eq!(m.insert(1, 10), None);
+        assert_eq!(m.insert(1, 11), Some(10));
+        assert_eq!(m.get(&1), Some(11));
+        assert_eq!(m.get_with(&1, |v| v + 1), Some(12));
+        assert!(m.contains_key(&1));
+        assert_eq!(m.len(), 1);
+        assert_eq!(m.remove(&1), Some(11));
+        assert_eq!(m.remove(&1), None);
+        assert!(!m.contains_key(&1));
+    }
+
+    #[test]
+    fn test_borrowed_key() {
+        let m = AnchorMap::new(4, 2);
+        m.insert("bananas".to_string(), 42);
+        assert_eq!(m.get("bananas"), Some(42));
+        assert_eq!(m.remove("bananas"), Some(42));
+    }
+
+    #[test]
+    fn test_for_each() {
+        let m = map(4);
+        for i in 0..100 {
+            m.insert(i, i * 2);
+        }
+
+        let mut got = Vec::new();
+        m.for_each(|k, v| got.push((*k, *v)));
+        got.sort_unstable();
+        assert_eq!(got, (0..100).map(|i| (i, i * 2)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_shard_errors() {
+        let m = map(1);
+        assert_eq!(m.remove_shard(ShardId(42)), Err(Error::ResourceNotFound));
+        assert_eq!(
+            m.remove_shard(ShardId(0)),
+            Err(Error::InvalidState("cannot remove the last shard"))
+        );
+
+        for _ in 1..16 {
+            m.add_shard().unwrap();
+        }
+        assert_eq!(m.add_shard(), Err(Error::CapacityLimitReached));
+        assert_eq!(m.shard_lens().len(), 16);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one shard")]
+    fn test_no_shards() {
+        map(0);
+    }
+
+    #[test]
+    fn test_concurrent() {
+        let m = Arc::new(map(4));
+        let handles = (0..4_u64)
+            .map(|t| {
+                let m = Arc::clone(&m);
+                thread::spawn(move || {
+                    for i in 0..1_000 {
+                        m.insert(t * 1_000 + i, i);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let added = m.add_shard().unwrap();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+        assert_eq!(m.len(), 4_000);
+        assert_placed(&m);
+
+        m.remove_shard(added.shard()).unwrap();
+        assert_eq!(m.len(), 4_000);
+        assert_placed(&m);
+    }
+
+    /// Adding and removing shards moves exactly the entries whose owner
+    /// changed.
+    #[quickcheck]
+    fn test_migration(ops: Vec<Option<u8>>) {
+        let m = map(4);
+        for i in 0..500 {
+            m.insert(i, i);
+        }
+
+        let mut live = vec![ShardId(0), ShardId(1), ShardId(2), ShardId(3)];
+        for op in ops.into_iter().take(20) {
+            let before = owners(&m);
+            let migration = match op {
+                None if live.len() < 16 => {
+                    let got = m.add_shard().unwrap();
+                    live.push(got.shard());
+                    got
+                }
+                Some(i) if live.len() > 1 => {
+                    let id = live.swap_remove(usize::from(i) % live.len());
+                    m.remove_shard(id).unwrap()
+                }
+                _ => continue,
+            };
+
+            let after = owners(&m);
+            let moved = before.iter().filter(|(k, id)| after[*k] != **id).count();
+            assert_eq!(migration.moved(), moved);
+            assert_eq!(migration.entries(), 500);
+            assert!(before.iter().all(|(k, id)| after[k] == *id
+                || *id == migration.shard()
+                || after[k] == migration.shard()));
+
+            assert_placed(&m);
+        }
+
+        assert_eq!(m.shard_lens().iter().map(|(_, n)| n).sum::<usize>(), 500);
+        assert!((0..500).all(|i| m.get(&i) == Some(i)));
+    }
+}
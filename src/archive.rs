@@ -0,0 +1,818 @@
+use core::{
+    convert::TryInto,
+    hash::{BuildHasher, Hash, Hasher},
+};
+
+use alloc::{vec, vec::Vec};
+
+use fnv::FnvHasher;
+
+use crate::{
+    anchor::{lookup_bucket_history, Anchor, Change, History, Storage},
+    error::Result,
+    Error,
+};
+
+/// The magic bytes prefixing an archive.
+const MAGIC: &[u8; 4] = b"ANCA";
+
+/// The version of the archive layout.
+const VERSION: u8 = 2;
+
+/// The size of the fixed header preceding the arrays.
+const HEADER_LEN: usize = 24;
+
+/// The size of the checksum trailing the arrays.
+const CHECKSUM_LEN: usize = 8;
+
+/// The resource ID stored for buckets with no resource assigned.
+const NO_RESOURCE: u64 = u64::MAX;
+
+/// Returns the total length of an archive for an anchor of `capacity`.
+fn archive_len(capacity: u16) -> usize {
+    // A, K and T are u16 arrays, the resource IDs are u64, and each change
+    // is four u16 fields.
+    HEADER_LEN + capacity as usize * (2 + 2 + 8 + 2 + 8) + CHECKSUM_LEN
+}
+
+/// Serialise `anchor` into the archive layout read by [`ArchiveView`], calling
+/// `resource_id` to obtain the ID of the resource assigned to each bucket.
+///
+/// The layout is, with all integers little-endian:
+///
+/// ```text
+/// offset        size      field
+/// 0             4         magic "ANCA"
+/// 4             1         version
+/// 5             3         reserved (zero)
+/// 8             2         capacity (c)
+/// 10            2         working buckets
+/// 12            4         reserved (zero)
+/// 16            8         generation
+/// 24            2c        A array
+/// 24 + 2c       2c        K array
+/// 24 + 4c       8c        resource ID per bucket (u64::MAX if none)
+/// 24 + 12c      2c        T array, the most recent change of each position
+/// 24 + 14c      8c        H array, the change made by each removal - bucket,
+///                         prev, jump and depth, zeroed beyond the removals
+/// 24 + 22c      8         FNV-1a 64 checksum of all preceding bytes
+/// ```
+///
+/// Returns [`Error::InvalidArchive`] if `resource_id` returns the reserved
+/// value [`u64::MAX`].
+pub(crate) fn encode<S, F>(
+    anchor: &Anchor<S>,
+    generation: u64,
+    mut resource_id: F,
+) -> Result<Vec<u8>>
+where
+    S: Storage,
+    F: FnMut(u16) -> Option<u64>,
+{
+    let capacity = anchor.capacity();
+    let slots = anchor.lookup_tables();
+
+    let mut buf = Vec::with_capacity(archive_len(capacity));
+    buf.extend_from_slice(MAGIC);
+    buf.push(VERSION);
+    buf.extend_from_slice(&[0; 3]);
+    buf.extend_from_slice(&capacity.to_le_bytes());
+    buf.extend_from_slice(&anchor.working().to_le_bytes());
+    buf.extend_from_slice(&[0; 4]);
+    buf.extend_from_slice(&generation.to_le_bytes());
+
+    // The archive stores the A and K arrays separately.
+    let a = slots.iter().map(|s| s.a);
+    let k = slots.iter().map(|s| s.k);
+    for v in a.chain(k) {
+        buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    for b in 0..capacity {
+        let id = match resource_id(b) {
+            Some(NO_RESOURCE) => {
+                return Err(Error::InvalidArchive("resource ID u64::MAX is reserved"))
+            }
+            Some(id) => id,
+            None => NO_RESOURCE,
+        };
+        buf.extend_from_slice(&id.to_le_bytes());
+    }
+
+    let history = anchor.history();
+    for v in history.top {
+        buf.extend_from_slice(&v.to_le_bytes());
+    }
+    for i in 0..capacity as usize {
+        let c = history.changes.get(i).copied().unwrap_or_default();
+        for v in [c.bucket, c.prev, c.jump, c.depth] {
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+
+    let checksum = checksum(&buf);
+    buf.extend_from_slice(&checksum.to_le_bytes());
+
+    debug_assert_eq!(buf.len(), archive_len(capacity));
+    Ok(buf)
+}
+
+fn checksum(buf: &[u8]) -> u64 {
+    let mut h = FnvHasher::default();
+    h.write(buf);
+    h.finish()
+}
+
+/// A read-only, zero-copy view of an archived [`AnchorHash`] or
+/// [`ArrayAnchorHash`] lookup table.
+///
+/// An archive is a flat, versioned byte layout of the internal lookup arrays
+/// and a bucket to resource ID table, produced by [`AnchorHash::to_archive()`]
+/// or [`ArrayAnchorHash::to_archive()`]. Because the layout uses fixed-width,
+/// little-endian integers with no pointers or alignment requirements, a view
+/// can be constructed directly over the bytes of a memory mapped file or a
+/// shared memory segment, allowing many processes to share a single copy of
+/// the routing table:
+///
+/// ```rust
+/// use anchorhash::{AnchorHash, ArchiveView, Builder};
+/// use fnv::FnvBuildHasher;
+///
+/// let backends = vec!["cache1", "cache2", "cache3"];
+///
+/// let mut anchor: AnchorHash<&str, _, _> = Builder::with_hasher(FnvBuildHasher::default())
+///     .with_resources(backends.clone())
+///     .build(20);
+/// anchor.remove_resource(&"cache2").unwrap();
+///
+/// // Use the position of each backend in the config as its ID.
+/// let buf = anchor
+///     .to_archive(1, |r| backends.iter().position(|v| v == r).unwrap() as u64)
+///     .unwrap();
+///
+/// // Typically `buf` is written to disk, and mapped into other processes.
+/// let view = ArchiveView::new(&buf).unwrap();
+///
+/// let id = view.lookup(&FnvBuildHasher::default(), "user-A").unwrap();
+/// assert_eq!(Some(&backends[id as usize]), anchor.get_resource("user-A"));
+/// ```
+///
+/// The view resolves keys with exactly the same algorithm as the instance it
+/// was archived from, so both map keys to the same resource provided the same
+/// hasher is used.
+///
+/// The archive MUST NOT be modified while a view of it exists - new versions
+/// should be published by atomically replacing the file (see `write_archive()`)
+/// or by writing them to a new shared memory segment, after which readers pick
+/// up the new version without any locking.
+///
+/// [`AnchorHash`]: crate::AnchorHash
+/// [`ArrayAnchorHash`]: crate::ArrayAnchorHash
+/// [`AnchorHash::to_archive()`]: crate::AnchorHash::to_archive
+/// [`ArrayAnchorHash::to_archive()`]: crate::ArrayAnchorHash::to_archive
+#[derive(Debug, Clone, Copy)]
+pub struct ArchiveView<'a> {
+    buf: &'a [u8],
+    capacity: u16,
+    working: u16,
+    generation: u64,
+}
+
+impl<'a> ArchiveView<'a> {
+    /// Construct a view over the archive in `buf`.
+    ///
+    /// The archive is validated in full, ensuring lookups against it are
+    /// well-defined - this runs in linear time w.r.t the capacity of the
+    /// archived instance, but lookups perform no further validation or
+    /// copying.
+    ///
+    /// Returns [`Error::InvalidArchive`] if `buf` is not a valid archive.
+    pub fn new(buf: &'a [u8]) -> Result<Self> {
+        let view = Self::parse_header(buf)?;
+        view.validate()?;
+        Ok(view)
+    }
+
+    /// Read the header of `buf`, checking only the framing of the archive and
+    /// not its contents.
+    fn parse_header(buf: &'a [u8]) -> Result<Self> {
+        if buf.len() < HEADER_LEN + CHECKSUM_LEN {
+            return Err(Error::InvalidArchive("truncated"));
+        }
+        if &buf[..4] != MAGIC {
+            return Err(Error::InvalidArchive("bad magic"));
+        }
+        if buf[4] != VERSION {
+            return Err(Error::InvalidArchive("unsupported version"));
+        }
+
+        let capacity = u16::from_le_bytes([buf[8], buf[9]]);
+        let working = u16::from_le_bytes([buf[10], buf[11]]);
+        let generation = u64::from_le_bytes(buf[16..24].try_into().unwrap());
+
+        if buf.len() != archive_len(capacity) {
+            return Err(Error::InvalidArchive("length does not match capacity"));
+        }
+
+        if working > capacity {
+            return Err(Error::InvalidArchive("working buckets exceed capacity"));
+        }
+
+        Ok(Self {
+            buf,
+            capacity,
+            working,
+            generation,
+        })
+    }
+
+    /// Ensure the archived arrays describe a reachable anchor state, and that
+    /// exactly the working buckets have a resource assigned.
+    fn validate(&self) -> Result<()> {
+        let (body, sum) = self.buf.split_at(self.buf.len() - CHECKSUM_LEN);
+        if checksum(body).to_le_bytes() != sum {
+            return Err(Error::InvalidArchive("checksum mismatch"));
+        }
+
+        let invalid = Error::InvalidArchive("inconsistent lookup table");
+
+        // Every removed bucket records the number of working buckets
+        // immediately after its removal, which is unique to its position in
+        // the stack of removed buckets - recover the stack from it.
+        let mut removed = vec![None; (self.capacity - self.working) as usize];
+        for b in 0..self.capacity {
+            let v = self.a(b as usize);
+            if v < self.working {
+                continue;
+            }
+            let slot = removed
+                .get_mut((self.capacity - 1).wrapping_sub(v) as usize)
+                .ok_or(invalid)?;
+            if slot.replace(b).is_some() {
+                return Err(invalid);
+            }
+        }
+        let removed = removed
+            .into_iter()
+            .collect::<Option<Vec<u16>>>()
+            .ok_or(invalid)?;
+
+        // Replay the removals, which must reproduce the archived arrays.
+        let anchor = Anchor::<Vec<u16>>::from_removed(self.capacity, &removed).ok_or(invalid)?;
+        let slots = anchor.lookup_tables();
+        let mut want = slots.iter().map(|s| s.a).chain(slots.iter().map(|s| s.k));
+        if !(0..2 * self.capacity as usize).all(|i| want.next() == Some(self.u16_at(i))) {
+            return Err(invalid);
+        }
+
+        // Including the history of each position, with no stale changes.
+        let history = anchor.history();
+        if (0..self.capacity as usize).any(|h| history.top[h] != self.top(h)) {
+            return Err(invalid);
+        }
+        if (1..=self.capacity).any(|c| {
+            history
+                .changes
+                .get(c as usize - 1)
+                .copied()
+                .unwrap_or_default()
+                != self.change(c)
+        }) {
+            return Err(invalid);
+        }
+
+        if (0..self.capacity).any(|b| anchor.is_working(b) != self.is_assigned(b)) {
+            return Err(Error::InvalidArchive("resource assigned to removed bucket"));
+        }
+
+        Ok(())
+    }
+
+    /// Read the `i`-th u16 of the A and K arrays, which are stored
+    /// consecutively.
+    #[inline(always)]
+    fn u16_at(&self, i: usize) -> u16 {
+        let o = HEADER_LEN + 2 * i;
+        u16::from_le_bytes([self.buf[o], self.buf[o + 1]])
+    }
+
+    #[inline(always)]
+    fn a(&self, b: usize) -> u16 {
+        self.u16_at(b)
+    }
+
+    #[inline(always)]
+    fn k(&self, b: usize) -> u16 {
+        self.u16_at(self.capacity as usize + b)
+    }
+
+    /// Read the `i`-th u16 of the history, stored after the resource IDs as
+    /// the T array followed by the changes.
+    #[inline(always)]
+    fn history_u16_at(&self, i: usize) -> u16 {
+        let o = HEADER_LEN + 12 * self.capacity as usize + 2 * i;
+        u16::from_le_bytes([self.buf[o], self.buf[o + 1]])
+    }
+
+    fn is_assigned(&self, bucket: u16) -> bool {
+        self.resource_id(bucket).is_some()
+    }
+
+    /// Returns the generation recorded when the archive was written.
+    ///
+    /// The generation is chosen by the writer, and is typically incremented
+    /// for each new version of the archive.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the capacity of the archived instance.
+    pub fn capacity(&self) -> u16 {
+        self.capacity
+    }
+
+    /// Returns the number of resources in the archived instance.
+    pub fn len(&self) -> usize {
+        self.working as usize
+    }
+
+    /// Returns true if the archived instance contains no resources.
+    pub fn is_empty(&self) -> bool {
+        self.working == 0
+    }
+
+    /// Resolve the (already hashed) key `k` to a bucket.
+    ///
+    /// This method will return [`None`] when the archived instance contains no
+    /// resources.
+    pub fn get_bucket(&self, k: u32) -> Option<u16> {
+        if self.working == 0 {
+            return None;
+        }
+
+        Some(lookup_bucket_history(
+            self.capacity,
+            k,
+            |b| self.a(b),
+            |b| self.k(b),
+            self,
+            |_| {},
+        ))
+    }
+
+    /// Returns the ID of the resource assigned to `bucket`, or [`None`] if the
+    /// bucket has no resource.
+    pub fn resource_id(&self, bucket: u16) -> Option<u64> {
+        if bucket >= self.capacity {
+            return None;
+        }
+
+        let o = HEADER_LEN + 4 * self.capacity as usize + 8 * bucket as usize;
+        let id = u64::from_le_bytes(self.buf[o..o + 8].try_into().unwrap());
+        Some(id).filter(|&id| id != NO_RESOURCE)
+    }
+
+    /// Consistently hash `key` using `hasher`, returning the ID of the resource
+    /// it maps to.
+    ///
+    /// This method will return [`None`] when the archived instance contains no
+    /// resources.
+    pub fn lookup<Q, B>(&self, hasher: &B, key: &Q) -> Option<u64>
+    where
+        Q: Hash + ?Sized,
+        B: BuildHasher,
+    {
+        let b = self.get_bucket(hasher.hash_one(key) as u32)?;
+        self.resource_id(b)
+    }
+}
+
+impl History for &ArchiveView<'_> {
+    #[inline(always)]
+    fn top(&self, h: usize) -> u16 {
+        self.history_u16_at(h)
+    }
+
+    #[inline(always)]
+    fn change(&self, c: u16) -> Change {
+        let o = self.capacity as usize + 4 * (c as usize - 1);
+        Change {
+            bucket: self.history_u16_at(o),
+            prev: self.history_u16_at(o + 1),
+            jump: self.history_u16_at(o + 2),
+            depth: self.history_u16_at(o + 3),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub use file::*;
+
+#[cfg(feature = "std")]
+mod file {
+    use std::{fs, io, io::Write, path::Path};
+
+    /// Atomically replace the file at `path` with `archive`.
+    ///
+    /// The archive is written with [`write_atomic()`], so readers observe
+    /// either the previous archive or the new one in full - never a partially
+    /// written file. Readers that have the previous file open (or mapped)
+    /// continue to see the previous version until they reopen `path`.
+    pub fn write_archive<P: AsRef<Path>>(path: P, archive: &[u8]) -> io::Result<()> {
+        write_atomic(path, archive)
+    }
+
+    /// Atomically replace the file at `path` with `contents`.
+    ///
+    /// The contents are written to a temporary file in the same directory and
+    /// flushed to disk before being renamed over `path`, and the directory is
+    /// then flushed to persist the rename. A crash at any point leaves either
+    /// the previous file or the new one in full at `path`.
+    pub fn write_atomic<P: AsRef<Path>>(path: P, contents: &[u8]) -> io::Result<()> {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+
+        let mut tmp_name = std::ffi::OsString::from(".");
+        tmp_name.push(name);
+        tmp_name.push(format!(".tmp-{}", std::process::id()));
+        let tmp = path.with_file_name(tmp_name);
+
+        let res = (|| {
+            let mut f = fs::File::create(&tmp)?;
+            f.write_all(contents)?;
+            f.sync_all()?;
+            fs::rename(&tmp, path)
+        })();
+
+        if res.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        res?;
+
+        // Persist the rename itself.
+        #[cfg(unix)]
+        if let Some(dir) = path.parent() {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            fs::File::open(dir)?.sync_all()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "mmap")]
+pub use mapped::*;
+
+#[cfg(feature = "mmap")]
+mod mapped {
+    use std::{
+        fs, io,
+        path::{Path, PathBuf},
+        time::SystemTime,
+    };
+
+    use memmap2::Mmap;
+
+    use super::ArchiveView;
+
+    /// Identifies a specific version of a file, changing when the file is
+    /// replaced.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct FileId {
+        len: u64,
+        modified: Option<SystemTime>,
+        #[cfg(unix)]
+        dev: u64,
+        #[cfg(unix)]
+        ino: u64,
+    }
+
+    impl FileId {
+        fn new(m: &fs::Metadata) -> Self {
+            #[cfg(unix)]
+            use std::os::unix::fs::MetadataExt;
+
+            Self {
+                len: m.len(),
+                modified: m.modified().ok(),
+                #[cfg(unix)]
+                dev: m.dev(),
+                #[cfg(unix)]
+                ino: m.ino(),
+            }
+        }
+    }
+
+    /// An archive memory mapped from a file, published with
+    /// [`write_archive()`].
+    ///
+    /// The mapping is shared with every other process mapping the same file,
+    /// so the lookup table is held in memory once per host. When a writer
+    /// replaces the file, calling [`refresh()`] maps the new version:
+    ///
+    /// ```rust,no_run
+    /// use anchorhash::MappedArchive;
+    /// use fnv::FnvBuildHasher;
+    ///
+    /// let mut archive = MappedArchive::open("/run/routing/table").unwrap();
+    ///
+    /// loop {
+    ///     archive.refresh().unwrap();
+    ///
+    ///     let id = archive.view().lookup(&FnvBuildHasher::default(), "user-A");
+    ///     // ...
+    /// #   break;
+    /// }
+    /// ```
+    ///
+    /// The file MUST only be replaced by renaming a new file over it (as
+    /// [`write_archive()`] does) - modifying a mapped file in place is
+    /// undefined behaviour.
+    ///
+    /// [`write_archive()`]: crate::write_archive
+    /// [`refresh()`]: Self::refresh
+    #[derive(Debug)]
+    pub struct MappedArchive {
+        path: PathBuf,
+        map: Mmap,
+        id: FileId,
+    }
+
+    impl MappedArchive {
+        /// Map the archive at `path`, validating its contents.
+        ///
+        /// Returns an error of kind [`io::ErrorKind::InvalidData`] wrapping an
+        /// [`Error::InvalidArchive`] if the file is not a valid archive.
+        ///
+        /// [`Error::InvalidArchive`]: crate::Error::InvalidArchive
+        pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+            let path = path.as_ref().to_path_buf();
+            let file = fs::File::open(&path)?;
+            let id = FileId::new(&file.metadata()?);
+
+            // SAFETY: archives are replaced by renaming a new file into place,
+            // never modified in place, so the mapped contents do not change.
+            let map = unsafe { Mmap::map(&file)? };
+
+            ArchiveView::new(&map).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+            Ok(Self { path, map, id })
+        }
+
+        /// Returns a view of the mapped archive.
+        pub fn view(&self) -> ArchiveView<'_> {
+            // The archive was validated when mapped, and is immutable.
+            ArchiveView::parse_header(&self.map).expect("validated archive")
+        }
+
+        /// Map the current version of the archive if the file has been
+        /// replaced since it was last mapped, returning true if a new version
+        /// was mapped.
+        ///
+        /// If the replacement is not a valid archive an error is returned and
+        /// the previous version remains mapped.
+        pub fn refresh(&mut self) -> io::Result<bool> {
+            if FileId::new(&fs::metadata(&self.path)?) == self.id {
+                return Ok(false);
+            }
+
+            *self = Self::open(&self.path)?;
+            Ok(true)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fnv::FnvBuildHasher;
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+    use crate::{AnchorHash, ArrayAnchorHash, Builder};
+
+    fn build(ops: &[(bool, u8)]) -> AnchorHash<u64, u8, FnvBuildHasher> {
+        let mut a: AnchorHash<u64, u8, _> = Builder::with_hasher(FnvBuildHasher::default())
+            .with_duplicate_policy(crate::DuplicatePolicy::Reject)
+            .with_resources(0..10)
+            .build(64);
+        for &(add, r) in ops {
+            let _ = if add {
+                a.add_resource(r)
+            } else {
+                a.remove_resource(&r)
+            };
+        }
+        a
+    }
+
+    #[quickcheck]
+    fn test_view_matches_anchorhash(ops: Vec<(bool, u8)>, keys: Vec<u64>) -> bool {
+        let a = build(&ops);
+        let buf = a.to_archive(42, |r| *r as u64).unwrap();
+        let view = ArchiveView::new(&buf).expect("valid archive");
+
+        assert_eq!(view.generation(), 42);
+        assert_eq!(view.capacity(), 64);
+        assert_eq!(view.len(), a.resources().len());
+
+        let hasher = FnvBuildHasher::default();
+        keys.into_iter()
+            .all(|k| view.lookup(&hasher, &k) == a.get_resource(k).map(|r| *r as u64))
+    }
+
+    /// Undoing a removal that moved a bucket, then making a removal that moves
+    /// none, leaves no stale change in the history.
+    #[test]
+    fn test_undone_removal() {
+        let a = build(&[(false, 4), (true, 4), (false, 9)]);
+        let buf = a.to_archive(1, |r| *r as u64).unwrap();
+        let view = ArchiveView::new(&buf).expect("valid archive");
+
+        let hasher = FnvBuildHasher::default();
+        for k in 0..1000_u64 {
+            assert_eq!(
+                view.lookup(&hasher, &k),
+                a.get_resource(k).map(|r| *r as u64)
+            );
+        }
+    }
+
+    #[test]
+    fn test_array_anchorhash_archive() {
+        let hasher = FnvBuildHasher::default();
+        let mut heap: AnchorHash<u64, u8, _> = Builder::with_hasher(hasher.clone()).build(8);
+        let mut array = ArrayAnchorHash::<u64, u8, _, 8>::with_hasher(hasher);
+        for r in 0..6 {
+            heap.add_resource(r).unwrap();
+            array.add_resource(r).unwrap();
+        }
+        heap.remove_resource(&2).unwrap();
+        array.remove_resource(&2).unwrap();
+
+        assert_eq!(
+            heap.to_archive(1, |r| *r as u64).unwrap(),
+            array.to_archive(1, |r| *r as u64).unwrap()
+        );
+    }
+
+    /// Lookups of an archive search the history rather than walking long
+    /// successor chains, and map keys as the archived instance does.
+    #[test]
+    fn test_long_successor_chain() {
+        let mut a: AnchorHash<u64, u16, _> = Builder::with_hasher(FnvBuildHasher::default())
+            .with_resources(0..1_000)
+            .build(1_000);
+        // Position 0 is occupied by every removed bucket in turn.
+        a.remove_resource(&0).unwrap();
+        for r in (10..1_000).rev() {
+            a.remove_resource(&r).unwrap();
+        }
+
+        let buf = a.to_archive(1, |r| *r as u64).unwrap();
+        let view = ArchiveView::new(&buf).unwrap();
+        let hasher = FnvBuildHasher::default();
+        for key in 0..2_000_u64 {
+            let want = a.get_resource(key).map(|r| *r as u64);
+            assert_eq!(view.lookup(&hasher, &key), want, "key {}", key);
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        // All buckets removed, including the last bucket with A[b] == 0.
+        let a = build(&(0..10).map(|r| (false, r)).collect::<Vec<_>>());
+        let buf = a.to_archive(0, |r| *r as u64).unwrap();
+        let view = ArchiveView::new(&buf).unwrap();
+
+        assert!(view.is_empty());
+        assert_eq!(view.get_bucket(42), None);
+        assert_eq!(view.lookup(&FnvBuildHasher::default(), &42), None);
+
+        let a: AnchorHash<u64, u8, _> = Builder::with_hasher(FnvBuildHasher::default()).build(0);
+        let buf = a.to_archive(0, |r| *r as u64).unwrap();
+        assert!(ArchiveView::new(&buf).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_reserved_resource_id() {
+        let a = build(&[(false, 3)]);
+        assert_eq!(
+            a.to_archive(1, |_| u64::MAX),
+            Err(Error::InvalidArchive("resource ID u64::MAX is reserved"))
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let a = build(&[(false, 3), (false, 7)]);
+        let buf = a.to_archive(1, |r| *r as u64).unwrap();
+
+        for n in 0..buf.len() {
+            ArchiveView::new(&buf[..n]).expect_err("truncated archive");
+        }
+
+        let mut bad = buf.clone();
+        bad[0] = b'X';
+        assert_eq!(
+            ArchiveView::new(&bad).unwrap_err(),
+            Error::InvalidArchive("bad magic")
+        );
+
+        let mut bad = buf.clone();
+        bad[HEADER_LEN] ^= 1;
+        assert_eq!(
+            ArchiveView::new(&bad).unwrap_err(),
+            Error::InvalidArchive("checksum mismatch")
+        );
+    }
+
+    /// Archives with a valid checksum but inconsistent contents are rejected.
+    #[test]
+    fn test_inconsistent() {
+        let a = build(&[(false, 3), (false, 7)]);
+        let buf = a.to_archive(1, |r| *r as u64).unwrap();
+
+        let reseal = |mut buf: Vec<u8>| {
+            let n = buf.len() - CHECKSUM_LEN;
+            let sum = checksum(&buf[..n]);
+            buf[n..].copy_from_slice(&sum.to_le_bytes());
+            buf
+        };
+
+        // A K entry pointing elsewhere.
+        let mut bad = buf.clone();
+        let o = HEADER_LEN + 2 * 64 + 2 * 5;
+        bad[o] = bad[o].wrapping_add(1);
+        assert!(ArchiveView::new(&reseal(bad)).is_err());
+
+        // A removed bucket with a resource ID.
+        let mut bad = buf.clone();
+        let o = HEADER_LEN + 4 * 64 + 8 * 3;
+        bad[o..o + 8].copy_from_slice(&3_u64.to_le_bytes());
+        assert_eq!(
+            ArchiveView::new(&reseal(bad)).unwrap_err(),
+            Error::InvalidArchive("resource assigned to removed bucket")
+        );
+
+        // Position 3 with no history, though bucket 7 then 9 moved into it.
+        let mut bad = buf.clone();
+        let o = HEADER_LEN + 12 * 64 + 2 * 3;
+        bad[o..o + 2].copy_from_slice(&0_u16.to_le_bytes());
+        assert!(ArchiveView::new(&reseal(bad)).is_err());
+
+        // A change recorded beyond the 56 removals.
+        let mut bad = buf.clone();
+        let o = HEADER_LEN + 14 * 64 + 8 * 56;
+        bad[o] = 1;
+        assert!(ArchiveView::new(&reseal(bad)).is_err());
+
+        // Too many working buckets.
+        let mut bad = buf;
+        bad[10] = 9;
+        assert!(ArchiveView::new(&reseal(bad)).is_err());
+    }
+
+    #[test]
+    fn test_write_archive() {
+        let dir = std::env::temp_dir().join(format!("anchorhash-archive-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("table");
+
+        let a = build(&[(false, 3)]);
+        write_archive(&path, &a.to_archive(1, |r| *r as u64).unwrap()).unwrap();
+        let b = build(&[(false, 3), (false, 4)]);
+        write_archive(&path, &b.to_archive(2, |r| *r as u64).unwrap()).unwrap();
+
+        let buf = std::fs::read(&path).unwrap();
+        let view = ArchiveView::new(&buf).unwrap();
+        assert_eq!(view.generation(), 2);
+        assert_eq!(view.len(), 8);
+
+        // No temporary files are left behind.
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        #[cfg(feature = "mmap")]
+        {
+            let mut mapped = MappedArchive::open(&path).unwrap();
+            assert_eq!(mapped.view().generation(), 2);
+            assert!(!mapped.refresh().unwrap());
+
+            write_archive(&path, &a.to_archive(3, |r| *r as u64).unwrap()).unwrap();
+            assert!(mapped.refresh().unwrap());
+            assert_eq!(mapped.view().generation(), 3);
+
+            // An invalid replacement leaves the previous version mapped.
+            write_archive(&path, b"garbage").unwrap();
+            assert!(mapped.refresh().is_err());
+            assert_eq!(mapped.view().generation(), 3);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,404 @@
+use core::{
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+};
+
+use alloc::vec::Vec;
+
+use crate::{
+    anchor::Anchor, archive, consistency, error::Result, Error, InvariantViolation, SlotIterator,
+    SlotMutIterator, Snapshot,
+};
+
+/// A fixed capacity [`AnchorHash`] storing all of its state inline, without
+/// any heap allocation.
+///
+/// An `ArrayAnchorHash` consistently maps keys of type `K` to at most `N`
+/// resources of type `R` using exactly the same algorithm as [`AnchorHash`] -
+/// given the same capacity, hasher and history of resource additions and
+/// removals, both types map every key to the same resource.
+///
+/// All the internal arrays, and the resources themselves, are stored within the
+/// `ArrayAnchorHash` value, making it suitable for embedded environments and
+/// hot paths where allocation is undesirable. Note this means the size of an
+/// `ArrayAnchorHash` grows with `N` - a large capacity instance may not fit on
+/// the stack.
+///
+/// Each bucket takes 20 bytes of algorithm state in addition to its
+/// `Option<R>` resource slot. 10 of these bytes hold the history of the working
+/// set searched by lookups that land on a long successor chain (see
+/// [`AnchorHash`]), and are stored whether or not the instance ever removes a
+/// resource.
+///
+/// ```rust
+/// use anchorhash::ArrayAnchorHash;
+/// use fnv::FnvBuildHasher;
+///
+/// // An instance with space for up to 8 backends.
+/// let mut anchor = ArrayAnchorHash::<_, _, _, 8>::with_hasher(FnvBuildHasher::default());
+///
+/// anchor.add_resource("cache1.itsallbroken.com").unwrap();
+/// anchor.add_resource("cache2.itsallbroken.com").unwrap();
+///
+/// let backend = anchor.get_resource("user-A").unwrap();
+/// ```
+///
+/// The state of an `ArrayAnchorHash` can be exchanged with an [`AnchorHash`]
+/// of the same capacity through a [`Snapshot`].
+///
+/// The capacity `N` MUST NOT exceed [`u16::MAX`], which is enforced at compile
+/// time.
+///
+/// [`AnchorHash`]: crate::AnchorHash
+#[derive(Debug)]
+pub struct ArrayAnchorHash<K, R, B, const N: usize>
+where
+    B: BuildHasher,
+{
+    anchor: Anchor<[u16; N]>,
+    hasher: B,
+    resources: [Option<R>; N],
+    len: usize,
+
+    _key_type: PhantomData<K>,
+}
+
+/// Implement `Clone` when both the resource type (`R`) and the hash builder
+/// (`B`) implement clone.
+///
+/// Note the key type (`K`) does NOT have to implement `Clone`.
+impl<K, R, B, const N: usize> Clone for ArrayAnchorHash<K, R, B, N>
+where
+    B: BuildHasher + Clone,
+    R: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            anchor: self.anchor.clone(),
+            hasher: self.hasher.clone(),
+            resources: self.resources.clone(),
+            len: self.len,
+            _key_type: PhantomData,
+        }
+    }
+}
+
+/// Initialise an empty instance using the default value of the hash builder
+/// (`B`).
+impl<K, R, B, const N: usize> Default for ArrayAnchorHash<K, R, B, N>
+where
+    B: BuildHasher + Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(B::default())
+    }
+}
+
+impl<K, R, B, const N: usize> ArrayAnchorHash<K, R, B, N>
+where
+    K: Hash,
+    B: BuildHasher,
+{
+    /// Consistently hash `key` to a configured resource.
+    ///
+    /// This method will return [`None`] when `self` contains no resources.
+    pub fn get_resource(&self, key: K) -> Option<&R> {
+        self.lookup(&key)
+    }
+}
+
+impl<K, R, B, const N: usize> ArrayAnchorHash<K, R, B, N>
+where
+    B: BuildHasher,
+{
+    /// The capacity of this instance, validated at compile time.
+    const CAPACITY: u16 = {
+        assert!(N <= u16::MAX as usize, "capacity cannot exceed u16::MAX");
+        N as u16
+    };
+
+    /// Initialise an empty instance, using `hasher` when hashing keys.
+    pub fn with_hasher(hasher: B) -> Self {
+        Self {
+            // An empty anchor is always within capacity.
+            anchor: Anchor::new(Self::CAPACITY, 0).expect("empty anchor"),
+            hasher,
+            resources: core::array::from_fn(|_| None),
+            len: 0,
+            _key_type: PhantomData,
+        }
+    }
+
+    /// Restore an instance from `snapshot`, using `hasher` to hash keys.
+    ///
+    /// The snapshot may have been taken from either an [`AnchorHash`] or an
+    /// `ArrayAnchorHash`, but its capacity must equal `N`.
+    ///
+    /// [`AnchorHash`]: crate::AnchorHash
+    pub fn from_snapshot(snapshot: Snapshot<R>, hasher: B) -> Result<Self> {
+        if snapshot.capacity() != Self::CAPACITY {
+            return Err(Error::InvalidSnapshot("capacity mismatch"));
+        }
+
+        let (anchor, resources) = snapshot.into_parts()?;
+
+        let mut slots: [Option<R>; N] = core::array::from_fn(|_| None);
+        let len = resources.len();
+        for (b, r) in resources {
+            slots[b as usize] = Some(r);
+        }
+
+        Ok(Self {
+            anchor,
+            hasher,
+            resources: slots,
+            len,
+            _key_type: PhantomData,
+        })
+    }
+
+    /// Consistently hash `key` to a configured resource, using a key type
+    /// chosen per call.
+    ///
+    /// See [`AnchorHash::lookup()`].
+    ///
+    /// [`AnchorHash::lookup()`]: crate::AnchorHash::lookup
+    pub fn lookup<Q>(&self, key: &Q) -> Option<&R>
+    where
+        Q: Hash + ?Sized,
+    {
+        // An instance with no resources (including a zero capacity instance)
+        // maps keys to nothing.
+        if self.anchor.working() == 0 {
+            return None;
+        }
+
+        // Hash the key to a u32 value
+        let key = self.hasher.hash_one(key);
+
+        // Lookup the bucket this key maps to
+        let b = self.anchor.get_bucket(key as u32);
+
+        // Resolve the bucket -> resource indirection
+        self.resources[b as usize].as_ref()
+    }
+
+    /// Returns an iterator yielding references to the configured resources in
+    /// bucket order.
+    pub fn resources(&self) -> SlotIterator<'_, R> {
+        SlotIterator::new(&self.resources, self.len)
+    }
+
+    /// Returns an iterator yielding mutable references to the configured
+    /// resources in bucket order.
+    pub fn resources_mut(&mut self) -> SlotMutIterator<'_, R> {
+        SlotMutIterator::new(&mut self.resources, self.len)
+    }
+
+    /// Capture the current state of this instance as a [`Snapshot`].
+    pub fn to_snapshot(&self) -> Snapshot<R>
+    where
+        R: Clone,
+    {
+        let resources = self
+            .resources
+            .iter()
+            .enumerate()
+            .filter_map(|(b, r)| Some((b as u16, r.as_ref()?.clone())))
+            .collect::<Vec<_>>();
+
+        Snapshot::new(&self.anchor, resources)
+    }
+
+    /// Check the internal state of this instance satisfies the invariants of
+    /// the AnchorHash algorithm, returning the first violation found.
+    ///
+    /// See [`AnchorHash::validate()`].
+    ///
+    /// [`AnchorHash::validate()`]: crate::AnchorHash::validate
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        self.anchor.validate()?;
+        self.anchor
+            .validate_resources(|b| self.resources[b as usize].is_some())
+    }
+
+    /// Returns a stable digest of the routing state of this instance.
+    ///
+    /// An `ArrayAnchorHash` and an [`AnchorHash`] holding the same state have
+    /// equal fingerprints. See [`AnchorHash::fingerprint()`].
+    ///
+    /// [`AnchorHash`]: crate::AnchorHash
+    /// [`AnchorHash::fingerprint()`]: crate::AnchorHash::fingerprint
+    pub fn fingerprint(&self) -> u64
+    where
+        R: Hash,
+    {
+        let resources = self
+            .resources
+            .iter()
+            .enumerate()
+            .filter_map(|(b, r)| Some((b as u16, r.as_ref()?)));
+
+        consistency::fingerprint(&self.anchor, resources)
+    }
+
+    /// Serialise the lookup table of this instance into an archive that can be
+    /// read in place by an [`ArchiveView`], calling `resource_id` to obtain an
+    /// ID for each resource.
+    ///
+    /// See [`AnchorHash::to_archive()`].
+    ///
+    /// [`ArchiveView`]: crate::ArchiveView
+    /// [`AnchorHash::to_archive()`]: crate::AnchorHash::to_archive
+    pub fn to_archive<F>(&self, generation: u64, mut resource_id: F) -> Result<Vec<u8>>
+    where
+        F: FnMut(&R) -> u64,
+    {
+        archive::encode(&self.anchor, generation, |b| {
+            self.resources[b as usize].as_ref().map(&mut resource_id)
+        })
+    }
+}
+
+impl<K, R, B, const N: usize> ArrayAnchorHash<K, R, B, N>
+where
+    B: BuildHasher,
+    R: PartialEq,
+{
+    /// Add `resource`, allowing keys to map to it.
+    ///
+    /// This method returns [`Error::CapacityLimitReached`] if `N` resources are
+    /// already configured.
+    ///
+    /// See [`AnchorHash::add_resource()`].
+    ///
+    /// [`AnchorHash::add_resource()`]: crate::AnchorHash::add_resource
+    pub fn add_resource(&mut self, resource: R) -> Result<()> {
+        let b = self
+            .anchor
+            .add_bucket()
+            .ok_or(Error::CapacityLimitReached)?;
+
+        // The bucket MUST NOT already be in use
+        let slot = &mut self.resources[b as usize];
+        assert!(slot.is_none());
+        *slot = Some(resource);
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Remove the resource, preventing keys from mapping to `resource`.
+    ///
+    /// See [`AnchorHash::remove_resource()`].
+    ///
+    /// [`AnchorHash::remove_resource()`]: crate::AnchorHash::remove_resource
+    pub fn remove_resource(&mut self, resource: &R) -> Result<()> {
+        let b = self
+            .resources
+            .iter()
+            .position(|r| r.as_ref() == Some(resource))
+            .ok_or(Error::ResourceNotFound)?;
+
+        self.anchor.remove_bucket(b as u16)?;
+        self.resources[b] = None;
+        self.len -= 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::RandomState;
+
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+    use crate::{AnchorHash, Builder};
+
+    #[test]
+    fn test_add_remove() {
+        let mut a = ArrayAnchorHash::<usize, _, RandomState, 2>::default();
+        assert_eq!(a.resources().len(), 0);
+
+        // With no resources, the key maps to nothing.
+        assert!(a.get_resource(42).is_none());
+
+        a.add_resource(24).expect("should add new resource");
+        assert_eq!(a.get_resource(42), Some(&24));
+        assert_eq!(a.lookup(&42), Some(&24));
+
+        a.add_resource(42).expect("should add new resource");
+        assert_eq!(
+            a.add_resource(13),
+            Err(Error::CapacityLimitReached),
+            "should not allow 3rd resource for capacity == 2"
+        );
+
+        a.remove_resource(&24).expect("should remove resource");
+        assert_eq!(a.get_resource(42), Some(&42));
+        assert_eq!(a.remove_resource(&24), Err(Error::ResourceNotFound));
+
+        assert_eq!(a.resources().collect::<Vec<_>>(), [&42]);
+    }
+
+    #[test]
+    fn test_zero_capacity() {
+        let mut a = ArrayAnchorHash::<usize, usize, RandomState, 0>::default();
+        assert!(a.get_resource(42).is_none());
+        assert_eq!(a.add_resource(1), Err(Error::CapacityLimitReached));
+    }
+
+    /// Apply the same history of operations to an AnchorHash and an
+    /// ArrayAnchorHash, ensuring they map keys identically.
+    #[quickcheck]
+    fn test_matches_anchorhash(ops: Vec<(bool, u8)>, keys: Vec<u64>) -> bool {
+        const N: usize = 32;
+
+        let hasher = RandomState::new();
+        let mut heap: AnchorHash<u64, u8, _> = Builder::with_hasher(hasher.clone()).build(N as _);
+        let mut array = ArrayAnchorHash::<u64, u8, _, N>::with_hasher(hasher.clone());
+
+        for (add, r) in ops {
+            // Duplicate resources are removed in an arbitrary order by
+            // AnchorHash, so only add distinct resources.
+            if add && heap.resources().all(|v| *v != r) {
+                assert_eq!(heap.add_resource(r), array.add_resource(r));
+            } else {
+                assert_eq!(heap.remove_resource(&r), array.remove_resource(&r));
+            }
+        }
+
+        assert_eq!(heap.validate(), Ok(()));
+        assert_eq!(array.validate(), Ok(()));
+
+        // The snapshots of both are interchangeable.
+        assert_eq!(heap.to_snapshot(), array.to_snapshot());
+        assert_eq!(heap.fingerprint(), array.fingerprint());
+        let restored: AnchorHash<u64, u8, _> =
+            AnchorHash::from_snapshot(array.to_snapshot(), hasher.clone()).unwrap();
+        let array_restored =
+            ArrayAnchorHash::<u64, u8, _, N>::from_snapshot(heap.to_snapshot(), hasher).unwrap();
+
+        keys.into_iter().all(|k| {
+            let want = heap.get_resource(k);
+            want == array.get_resource(k)
+                && want == restored.get_resource(k)
+                && want == array_restored.get_resource(k)
+        })
+    }
+
+    #[test]
+    fn test_snapshot_capacity_mismatch() {
+        let heap: AnchorHash<usize, _, _> = Builder::default()
+            .with_resources(vec!["A".to_string()])
+            .build(4);
+
+        let err = ArrayAnchorHash::<usize, _, _, 8>::from_snapshot(
+            heap.to_snapshot(),
+            RandomState::new(),
+        )
+        .expect_err("capacity mismatch");
+        assert_eq!(err, Error::InvalidSnapshot("capacity mismatch"));
+    }
+}
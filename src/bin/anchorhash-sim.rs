@@ -0,0 +1,518 @@
+//! Simulate resource churn against an [`AnchorHash`] instance to inform
+//! capacity planning.
+//!
+//! A scenario describes the initial resources and capacity, a sequence of
+//! resource additions and removals (or a number of random churn steps), and
+//! the number and popularity distribution of keys. After each step the
+//! simulator reports the load on each resource, the load imbalance, the
+//! fraction of keys moved compared with the theoretical minimum, and the
+//! lookup path length, as JSON or CSV.
+//!
+//! Run with `--help` for usage.
+
+use std::{env, fmt::Write, process};
+
+use anchorhash::{AnchorHash, Builder};
+use fnv::FnvBuildHasher;
+
+const USAGE: &str = "\
+Simulate resource churn against an AnchorHash instance.
+
+USAGE:
+    anchorhash-sim [OPTIONS]
+
+OPTIONS:
+    --capacity <N>        maximum number of resources [default: 100]
+    --resources <N>       number of initial resources [default: 10]
+    --keys <N>            number of keys to place [default: 100000]
+    --key-dist <DIST>     key popularity, either \"uniform\" or \"zipf:<S>\"
+                          with exponent S [default: uniform]
+    --ops <OPS>           comma separated steps to apply, each one of \"add\",
+                          \"remove\" (a random resource) or \"remove:<ID>\"
+    --churn <N>           apply N random add / remove steps after --ops
+                          [default: 0]
+    --seed <N>            seed for key generation and random steps [default: 0]
+    --format <FORMAT>     \"json\", \"csv\" (one row per step), or \"csv-load\"
+                          (one row per resource per step) [default: json]
+    -h, --help            print this message
+";
+
+#[derive(Debug)]
+enum Distribution {
+    Uniform,
+    Zipf(f64),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Add,
+    Remove(Option<u32>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Format {
+    Json,
+    Csv,
+    CsvLoad,
+}
+
+#[derive(Debug)]
+struct Config {
+    capacity: u16,
+    resources: u32,
+    keys: usize,
+    dist: Distribution,
+    ops: Vec<Op>,
+    churn: usize,
+    seed: u64,
+    format: Format,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            capacity: 100,
+            resources: 10,
+            keys: 100_000,
+            dist: Distribution::Uniform,
+            ops: Vec::new(),
+            churn: 0,
+            seed: 0,
+            format: Format::Json,
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Config>, String> {
+    let mut c = Config::default();
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Ok(None);
+        }
+
+        let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--capacity" => c.capacity = parse_num(&value()?)?,
+            "--resources" => c.resources = parse_num(&value()?)?,
+            "--keys" => c.keys = parse_num(&value()?)?,
+            "--churn" => c.churn = parse_num(&value()?)?,
+            "--seed" => c.seed = parse_num(&value()?)?,
+            "--key-dist" => {
+                let v = value()?;
+                c.dist = match v.split_once(':') {
+                    None if v == "uniform" => Distribution::Uniform,
+                    Some(("zipf", s)) => Distribution::Zipf(parse_num(s)?),
+                    _ => return Err(format!("invalid key distribution {:?}", v)),
+                }
+            }
+            "--ops" => {
+                c.ops = value()?
+                    .split(',')
+                    .filter(|v| !v.is_empty())
+                    .map(|v| match v.split_once(':') {
+                        None if v == "add" => Ok(Op::Add),
+                        None if v == "remove" => Ok(Op::Remove(None)),
+                        Some(("remove", id)) => Ok(Op::Remove(Some(parse_num(id)?))),
+                        _ => Err(format!("invalid op {:?}", v)),
+                    })
+                    .collect::<Result<_, _>>()?
+            }
+            "--format" => {
+                c.format = match value()?.as_str() {
+                    "json" => Format::Json,
+                    "csv" => Format::Csv,
+                    "csv-load" => Format::CsvLoad,
+                    v => return Err(format!("invalid format {:?}", v)),
+                }
+            }
+            _ => return Err(format!("unknown argument {:?}", arg)),
+        }
+    }
+
+    if c.resources == 0 || c.resources > c.capacity as u32 {
+        return Err(format!(
+            "--resources must be between 1 and the capacity ({})",
+            c.capacity
+        ));
+    }
+
+    Ok(Some(c))
+}
+
+fn parse_num<T: std::str::FromStr>(v: &str) -> Result<T, String> {
+    v.parse().map_err(|_| format!("invalid number {:?}", v))
+}
+
+/// A SplitMix64 PRNG, making runs reproducible for a given seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// The state of the simulation after a single step.
+struct Step {
+    op: &'static str,
+    resource: Option<u32>,
+
+    /// The fraction of keys (by count) that changed resource in this step.
+    moved: f64,
+
+    /// The minimum fraction of keys that must move for the load to remain
+    /// evenly balanced.
+    optimal_moved: f64,
+
+    /// The number of keys, and fraction of the key weight, for each resource.
+    loads: Vec<(u32, usize, f64)>,
+
+    /// The number of additional buckets examined for each key lookup, sorted.
+    hops: Vec<usize>,
+}
+
+impl Step {
+    fn load_ratio(&self, f: impl Fn(f64, f64) -> f64, init: f64) -> f64 {
+        let mean = 1.0 / self.loads.len() as f64;
+        self.loads.iter().map(|l| l.2).fold(init, f) / mean
+    }
+
+    fn max_load_ratio(&self) -> f64 {
+        self.load_ratio(f64::max, 0.0)
+    }
+
+    fn min_load_ratio(&self) -> f64 {
+        self.load_ratio(f64::min, f64::INFINITY)
+    }
+
+    /// The coefficient of variation of the resource load.
+    fn load_cv(&self) -> f64 {
+        let n = self.loads.len() as f64;
+        let mean = 1.0 / n;
+        let var = self.loads.iter().map(|l| (l.2 - mean).powi(2)).sum::<f64>() / n;
+        var.sqrt() / mean
+    }
+
+    fn mean_hops(&self) -> f64 {
+        self.hops.iter().sum::<usize>() as f64 / self.hops.len().max(1) as f64
+    }
+
+    fn p99_hops(&self) -> usize {
+        let i = (self.hops.len() * 99 / 100).min(self.hops.len().saturating_sub(1));
+        self.hops.get(i).copied().unwrap_or_default()
+    }
+
+    fn max_hops(&self) -> usize {
+        self.hops.last().copied().unwrap_or_default()
+    }
+}
+
+struct Simulation {
+    anchor: AnchorHash<u64, u32, FnvBuildHasher>,
+    live: Vec<u32>,
+    next_id: u32,
+    keys: Vec<u64>,
+    weights: Vec<f64>,
+    owners: Vec<u32>,
+}
+
+impl Simulation {
+    fn new(c: &Config, rng: &mut Rng) -> Self {
+        let anchor = Builder::with_hasher(FnvBuildHasher::default())
+            .with_resources(0..c.resources)
+            .build(c.capacity);
+
+        let keys = (0..c.keys).map(|_| rng.next_u64()).collect::<Vec<_>>();
+
+        // Keys are generated randomly, so assigning popularity by index does
+        // not correlate with placement.
+        let mut weights = match c.dist {
+            Distribution::Uniform => vec![1.0; c.keys],
+            Distribution::Zipf(s) => (0..c.keys)
+                .map(|i| 1.0 / ((i + 1) as f64).powf(s))
+                .collect(),
+        };
+        let total = weights.iter().sum::<f64>();
+        weights.iter_mut().for_each(|w| *w /= total);
+
+        Self {
+            anchor,
+            live: (0..c.resources).collect(),
+            next_id: c.resources,
+            keys,
+            weights,
+            owners: Vec::new(),
+        }
+    }
+
+    fn apply(&mut self, op: Op, rng: &mut Rng) -> Result<Step, String> {
+        let before = self.live.len() as f64;
+
+        let (name, id, optimal) = match op {
+            Op::Add => {
+                let id = self.next_id;
+                self.anchor
+                    .add_resource(id)
+                    .map_err(|e| format!("cannot add resource: {}", e))?;
+                self.next_id += 1;
+                self.live.push(id);
+                ("add", id, 1.0 / (before + 1.0))
+            }
+            Op::Remove(id) => {
+                if self.live.len() == 1 {
+                    return Err("cannot remove the last resource".to_string());
+                }
+                let id = id.unwrap_or_else(|| self.live[rng.below(self.live.len())]);
+                self.anchor
+                    .remove_resource(&id)
+                    .map_err(|e| format!("cannot remove resource {}: {}", id, e))?;
+                self.live.retain(|&v| v != id);
+                ("remove", id, 1.0 / before)
+            }
+        };
+
+        let mut step = self.evaluate();
+        step.op = name;
+        step.resource = Some(id);
+        step.optimal_moved = optimal;
+        Ok(step)
+    }
+
+    /// Resolve every key, recording the load and path lengths, and the keys
+    /// that moved since the previous evaluation.
+    fn evaluate(&mut self) -> Step {
+        let mut loads = self.live.iter().map(|&id| (id, 0, 0.0)).collect::<Vec<_>>();
+        loads.sort_unstable_by_key(|l| l.0);
+
+        let mut hops = Vec::with_capacity(self.keys.len());
+        let mut moved = 0;
+        let first = self.owners.is_empty();
+
+        for (i, k) in self.keys.iter().enumerate() {
+            let path = self.anchor.explain(k).expect("at least one resource");
+            let owner = *path.resource();
+            hops.push(path.hops());
+
+            let l = loads.binary_search_by_key(&owner, |l| l.0).unwrap();
+            loads[l].1 += 1;
+            loads[l].2 += self.weights[i];
+
+            if first {
+                self.owners.push(owner);
+            } else if self.owners[i] != owner {
+                self.owners[i] = owner;
+                moved += 1;
+            }
+        }
+        hops.sort_unstable();
+
+        Step {
+            op: "init",
+            resource: None,
+            moved: moved as f64 / self.keys.len().max(1) as f64,
+            optimal_moved: 0.0,
+            loads,
+            hops,
+        }
+    }
+}
+
+fn run(c: &Config) -> Result<String, String> {
+    let mut rng = Rng(c.seed);
+    let mut sim = Simulation::new(c, &mut rng);
+
+    let mut steps = vec![sim.evaluate()];
+    for &op in &c.ops {
+        steps.push(sim.apply(op, &mut rng)?);
+    }
+    for _ in 0..c.churn {
+        let n = sim.live.len();
+        let op = if n == c.capacity as usize || (n > 1 && rng.below(2) == 0) {
+            Op::Remove(None)
+        } else {
+            Op::Add
+        };
+        steps.push(sim.apply(op, &mut rng)?);
+    }
+
+    Ok(match c.format {
+        Format::Json => json(c, &steps),
+        Format::Csv => csv(&steps),
+        Format::CsvLoad => csv_load(&steps),
+    })
+}
+
+fn json(c: &Config, steps: &[Step]) -> String {
+    let dist = match c.dist {
+        Distribution::Uniform => "\"uniform\"".to_string(),
+        Distribution::Zipf(s) => format!("\"zipf:{}\"", s),
+    };
+
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "{{\"config\":{{\"capacity\":{},\"resources\":{},\"keys\":{},\"key_dist\":{},\"seed\":{}}},\"steps\":[",
+        c.capacity, c.resources, c.keys, dist, c.seed
+    );
+
+    for (i, s) in steps.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let resource = s
+            .resource
+            .map_or_else(|| "null".to_string(), |r| r.to_string());
+        let _ = write!(
+            out,
+            "{{\"step\":{},\"op\":\"{}\",\"resource\":{},\"resources\":{},\"moved\":{:.6},\"optimal_moved\":{:.6},\
+             \"max_load_ratio\":{:.6},\"min_load_ratio\":{:.6},\"load_cv\":{:.6},\
+             \"mean_hops\":{:.6},\"p99_hops\":{},\"max_hops\":{},\"loads\":[",
+            i,
+            s.op,
+            resource,
+            s.loads.len(),
+            s.moved,
+            s.optimal_moved,
+            s.max_load_ratio(),
+            s.min_load_ratio(),
+            s.load_cv(),
+            s.mean_hops(),
+            s.p99_hops(),
+            s.max_hops(),
+        );
+        for (j, (id, keys, load)) in s.loads.iter().enumerate() {
+            if j > 0 {
+                out.push(',');
+            }
+            let _ = write!(
+                out,
+                "{{\"resource\":{},\"keys\":{},\"load\":{:.6}}}",
+                id, keys, load
+            );
+        }
+        out.push_str("]}");
+    }
+
+    out.push_str("]}\n");
+    out
+}
+
+fn csv(steps: &[Step]) -> String {
+    let mut out = String::from(
+        "step,op,resource,resources,moved,optimal_moved,max_load_ratio,min_load_ratio,load_cv,mean_hops,p99_hops,max_hops\n",
+    );
+    for (i, s) in steps.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{:.6},{:.6},{:.6},{:.6},{:.6},{:.6},{},{}",
+            i,
+            s.op,
+            s.resource.map(|r| r.to_string()).unwrap_or_default(),
+            s.loads.len(),
+            s.moved,
+            s.optimal_moved,
+            s.max_load_ratio(),
+            s.min_load_ratio(),
+            s.load_cv(),
+            s.mean_hops(),
+            s.p99_hops(),
+            s.max_hops(),
+        );
+    }
+    out
+}
+
+fn csv_load(steps: &[Step]) -> String {
+    let mut out = String::from("step,resource,keys,load\n");
+    for (i, s) in steps.iter().enumerate() {
+        for (id, keys, load) in &s.loads {
+            let _ = writeln!(out, "{},{},{},{:.6}", i, id, keys, load);
+        }
+    }
+    out
+}
+
+fn main() {
+    let config = match parse_args(env::args().skip(1)) {
+        Ok(Some(c)) => c,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    match run(&config) {
+        Ok(out) => print!("{}", out),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(args: &str) -> Result<Option<Config>, String> {
+        parse_args(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let c = config("--capacity 20 --resources 5 --ops add,remove:3,remove --key-dist zipf:1.5")
+            .unwrap()
+            .unwrap();
+        assert_eq!(c.capacity, 20);
+        assert_eq!(c.resources, 5);
+        assert_eq!(c.ops.len(), 3);
+        assert!(matches!(c.ops[1], Op::Remove(Some(3))));
+        assert!(matches!(c.dist, Distribution::Zipf(s) if s == 1.5));
+
+        assert!(config("--help").unwrap().is_none());
+        assert!(config("--capacity").is_err());
+        assert!(config("--ops nope").is_err());
+        assert!(config("--resources 20 --capacity 10").is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let c = config("--capacity 10 --resources 4 --keys 10000 --ops add,remove:0 --churn 20")
+            .unwrap()
+            .unwrap();
+
+        let out = run(&Config {
+            format: Format::Csv,
+            ..c
+        })
+        .unwrap();
+        assert_eq!(out.lines().count(), 1 + 1 + 2 + 20);
+
+        // AnchorHash moves close to the minimum number of keys at each step.
+        for row in out.lines().skip(2) {
+            let cols = row.split(',').collect::<Vec<_>>();
+            let moved: f64 = cols[4].parse().unwrap();
+            let optimal: f64 = cols[5].parse().unwrap();
+            assert!((moved - optimal).abs() < 0.05, "row {}", row);
+        }
+    }
+
+    #[test]
+    fn test_remove_unknown() {
+        let c = config("--ops remove:42").unwrap().unwrap();
+        assert!(run(&c).is_err());
+    }
+}
@@ -0,0 +1,463 @@
+//! Query and edit a persisted [`AnchorHash`] routing state.
+//!
+//! The state file is an encoded [`Snapshot`] of string resources, readable by
+//! the library with [`Snapshot::decode()`]. Keys are hashed with the same
+//! hasher configuration as the library, so a state loaded with the same hasher
+//! and key type in production maps every key to the same resource.
+//!
+//! Run with `--help` for usage.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    env, fs,
+    hash::{BuildHasher, BuildHasherDefault, Hasher},
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+use anchorhash::{write_atomic, AnchorHash, AnyKey, Builder, DuplicatePolicy, Snapshot};
+use fnv::FnvBuildHasher;
+
+const USAGE: &str = "\
+Query and edit a persisted AnchorHash routing state.
+
+USAGE:
+    anchorhash [OPTIONS] <COMMAND>
+
+COMMANDS:
+    init <CAPACITY> [RESOURCE]...   create a new state file
+    lookup [KEY]...                 print the resource each key maps to, reading
+                                    keys from stdin (one per line) if none are
+                                    given or KEY is \"-\"
+    explain <KEY>                   print the buckets visited to resolve KEY
+    add <RESOURCE>                  add a resource
+    remove <RESOURCE>               remove a resource
+    list                            print each bucket and its resource
+    fingerprint                     print the state fingerprint
+    diff <STATE-A> <STATE-B>        print the buckets that differ between two
+                                    state files, exiting 1 if any differ
+
+OPTIONS:
+    --state <PATH>       the state file [default: $ANCHORHASH_STATE, or
+                         anchorhash.state]
+    --hasher <HASHER>    the hasher used in production, either \"fnv\"
+                         (fnv::FnvBuildHasher) or \"sip\"
+                         (BuildHasherDefault<DefaultHasher>) [default: fnv]
+    --key-type <TYPE>    the key type used in production, either \"str\" (&str
+                         or String keys) or \"u64\" [default: str]
+    --force              allow init to overwrite an existing state file
+    -h, --help           print this message
+";
+
+/// The production hasher configurations supported by the CLI.
+///
+/// Only hashers with a deterministic initial state can be reproduced - a
+/// randomly seeded hasher such as `RandomState` maps keys differently in every
+/// process.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum HasherKind {
+    Fnv,
+    Sip,
+}
+
+impl BuildHasher for HasherKind {
+    type Hasher = Box<dyn Hasher>;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        match self {
+            Self::Fnv => Box::new(FnvBuildHasher::default().build_hasher()),
+            Self::Sip => Box::new(BuildHasherDefault::<DefaultHasher>::default().build_hasher()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum KeyType {
+    Str,
+    U64,
+}
+
+type State = AnchorHash<AnyKey, String, HasherKind>;
+
+#[derive(Debug)]
+struct Options {
+    state: PathBuf,
+    hasher: HasherKind,
+    key_type: KeyType,
+    force: bool,
+    command: Vec<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut o = Options {
+        state: env::var_os("ANCHORHASH_STATE")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("anchorhash.state")),
+        hasher: HasherKind::Fnv,
+        key_type: KeyType::Str,
+        force: false,
+        command: Vec::new(),
+    };
+
+    while let Some(arg) = args.next() {
+        // Everything following the command name belongs to the command.
+        if !o.command.is_empty() {
+            o.command.push(arg);
+            continue;
+        }
+
+        let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--state" => o.state = PathBuf::from(value()?),
+            "--hasher" => {
+                o.hasher = match value()?.as_str() {
+                    "fnv" => HasherKind::Fnv,
+                    "sip" => HasherKind::Sip,
+                    v => return Err(format!("invalid hasher {:?}", v)),
+                }
+            }
+            "--key-type" => {
+                o.key_type = match value()?.as_str() {
+                    "str" => KeyType::Str,
+                    "u64" => KeyType::U64,
+                    v => return Err(format!("invalid key type {:?}", v)),
+                }
+            }
+            "--force" => o.force = true,
+            v if v.starts_with('-') => return Err(format!("unknown option {:?}", v)),
+            _ => o.command.push(arg),
+        }
+    }
+
+    if o.command.is_empty() {
+        return Err("no command given".to_string());
+    }
+
+    Ok(Some(o))
+}
+
+fn load(path: &Path, hasher: HasherKind) -> Result<State, String> {
+    let buf = fs::read(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let snapshot = Snapshot::decode(&buf, |v| String::from_utf8(v.to_vec()).ok())
+        .map_err(|e| format!("cannot load {}: {}", path.display(), e))?;
+
+    let mut state = AnchorHash::from_snapshot(snapshot, hasher)
+        .map_err(|e| format!("cannot load {}: {}", path.display(), e))?;
+
+    // Operators editing the state by hand are the most likely source of
+    // duplicate resources.
+    state.set_duplicate_policy(DuplicatePolicy::Reject);
+    Ok(state)
+}
+
+/// Atomically replace the state file at `path` with `state`.
+fn save(path: &Path, state: &State) -> Result<(), String> {
+    let buf = state
+        .to_snapshot()
+        .encode(|r, buf| buf.extend_from_slice(r.as_bytes()));
+
+    write_atomic(path, &buf).map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+/// Resolve `key` using the configured key type.
+fn lookup<'a>(
+    state: &'a State,
+    key_type: KeyType,
+    key: &str,
+) -> Result<Option<&'a String>, String> {
+    Ok(match key_type {
+        KeyType::Str => state.lookup(key),
+        KeyType::U64 => state.lookup(&parse_u64(key)?),
+    })
+}
+
+fn parse_u64(key: &str) -> Result<u64, String> {
+    key.parse()
+        .map_err(|_| format!("key {:?} is not a valid u64", key))
+}
+
+/// Execute the command in `o`, writing output to `out` and reading keys from
+/// `input`, returning the process exit code.
+fn run(o: &Options, input: impl BufRead, out: &mut impl Write) -> Result<i32, String> {
+    let (cmd, args) = o.command.split_first().expect("command is present");
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let w = |e: io::Error| e.to_string();
+
+    match (cmd.as_str(), args.as_slice()) {
+        ("init", [capacity, resources @ ..]) => {
+            if o.state.exists() && !o.force {
+                return Err(format!(
+                    "{} already exists, use --force to overwrite",
+                    o.state.display()
+                ));
+            }
+            let capacity = capacity
+                .parse()
+                .map_err(|_| format!("invalid capacity {:?}", capacity))?;
+            let state: State = Builder::with_hasher(o.hasher)
+                .with_duplicate_policy(DuplicatePolicy::Reject)
+                .with_resources(resources.iter().map(|r| r.to_string()))
+                .try_build(capacity)
+                .map_err(|e| e.to_string())?;
+            save(&o.state, &state)?;
+        }
+        ("lookup", keys) => {
+            let state = load(&o.state, o.hasher)?;
+            let mut resolve = |key: &str| -> Result<(), String> {
+                let r = lookup(&state, o.key_type, key)?;
+                writeln!(out, "{}\t{}", key, r.map_or("-", String::as_str)).map_err(w)
+            };
+
+            if keys.is_empty() || keys == ["-"] {
+                for line in input.lines() {
+                    resolve(&line.map_err(w)?)?;
+                }
+            } else {
+                keys.iter().try_for_each(|k| resolve(k))?;
+            }
+        }
+        ("explain", [key]) => {
+            let state = load(&o.state, o.hasher)?;
+            let path = match o.key_type {
+                KeyType::Str => state.explain(*key),
+                KeyType::U64 => state.explain(&parse_u64(key)?),
+            };
+            let path = path.ok_or("state has no resources")?;
+
+            writeln!(out, "key\t{}", key).map_err(w)?;
+            writeln!(out, "hash\t{:#018x}", path.hash()).map_err(w)?;
+            let buckets = path
+                .buckets()
+                .iter()
+                .map(|b| b.to_string())
+                .collect::<Vec<_>>();
+            writeln!(out, "buckets\t{}", buckets.join(" -> ")).map_err(w)?;
+            writeln!(out, "bucket\t{}", path.bucket()).map_err(w)?;
+            writeln!(out, "resource\t{}", path.resource()).map_err(w)?;
+        }
+        ("add", [resource]) => {
+            let mut state = load(&o.state, o.hasher)?;
+            state
+                .add_resource(resource.to_string())
+                .map_err(|e| format!("cannot add {:?}: {}", resource, e))?;
+            save(&o.state, &state)?;
+        }
+        ("remove", [resource]) => {
+            let mut state = load(&o.state, o.hasher)?;
+            state
+                .remove_resource(&resource.to_string())
+                .map_err(|e| format!("cannot remove {:?}: {}", resource, e))?;
+            save(&o.state, &state)?;
+        }
+        ("list", []) => {
+            let snapshot = load(&o.state, o.hasher)?.to_snapshot();
+            for (b, r) in snapshot.assignments() {
+                writeln!(out, "{}\t{}", b, r).map_err(w)?;
+            }
+        }
+        ("fingerprint", []) => {
+            let state = load(&o.state, o.hasher)?;
+            writeln!(out, "{:016x}", state.fingerprint()).map_err(w)?;
+        }
+        ("diff", [a, b]) => {
+            let a = load(Path::new(a), o.hasher)?;
+            let b = load(Path::new(b), o.hasher)?;
+
+            let diff = a.diff(&b);
+            for d in &diff {
+                let side = |s: Option<&anchorhash::BucketState<'_, String>>| match s {
+                    None => "absent".to_string(),
+                    Some(s) => format!(
+                        "{} {} A={} K={}",
+                        if s.is_working() { "working" } else { "removed" },
+                        s.resource().map_or("-", String::as_str),
+                        s.a(),
+                        s.k()
+                    ),
+                };
+                writeln!(
+                    out,
+                    "bucket {}\t{}\t{}",
+                    d.bucket(),
+                    side(d.left()),
+                    side(d.right())
+                )
+                .map_err(w)?;
+            }
+            if !diff.is_empty() {
+                return Ok(1);
+            }
+        }
+        _ => return Err(format!("invalid command {:?}", o.command.join(" "))),
+    }
+
+    Ok(0)
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(Some(o)) => o,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    match run(&options, stdin.lock(), &mut stdout.lock()) {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let p = env::temp_dir().join(format!("anchorhash-cli-{}-{}", name, process::id()));
+            fs::create_dir_all(&p).unwrap();
+            Self(p)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn exec(state: &Path, args: &str, input: &str) -> Result<(i32, String), String> {
+        let args = vec!["--state".to_string(), state.to_str().unwrap().to_string()]
+            .into_iter()
+            .chain(args.split_whitespace().map(String::from));
+        let o = parse_args(args)?.unwrap();
+
+        let mut out = Vec::new();
+        let code = run(&o, input.as_bytes(), &mut out)?;
+        Ok((code, String::from_utf8(out).unwrap()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let o = parse_args(
+            "--hasher sip --key-type u64 lookup --not-an-option"
+                .split_whitespace()
+                .map(String::from),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(o.hasher, HasherKind::Sip);
+        assert_eq!(o.key_type, KeyType::U64);
+        assert_eq!(o.command, ["lookup", "--not-an-option"]);
+
+        assert!(parse_args(std::iter::empty()).is_err());
+        assert!(parse_args(vec!["--hasher".to_string(), "nope".to_string()].into_iter()).is_err());
+    }
+
+    /// The CLI resolves keys identically to an equivalent library instance.
+    #[test]
+    fn test_matches_library() {
+        let dir = TempDir::new("matches");
+        let path = dir.0.join("state");
+
+        exec(&path, "init 20 a b c d", "").unwrap();
+        exec(&path, "remove b", "").unwrap();
+        exec(&path, "add e", "").unwrap();
+
+        let mut want: AnchorHash<&str, _, _> = Builder::with_hasher(FnvBuildHasher::default())
+            .with_resources(vec!["a", "b", "c", "d"])
+            .build(20);
+        want.remove_resource(&"b").unwrap();
+        want.add_resource("e").unwrap();
+
+        let keys = (0..100).map(|k| format!("key-{}\n", k)).collect::<String>();
+        let (code, out) = exec(&path, "lookup", &keys).unwrap();
+        assert_eq!(code, 0);
+        for line in out.lines() {
+            let (k, r) = line.split_once('\t').unwrap();
+            assert_eq!(Some(&r), want.get_resource(k));
+        }
+        assert_eq!(out.lines().count(), 100);
+
+        let (_, out) = exec(&path, "list", "").unwrap();
+        assert_eq!(out, "0\ta\n1\te\n2\tc\n3\td\n");
+
+        let (_, out) = exec(&path, "explain key-1", "").unwrap();
+        assert!(out.contains(&format!(
+            "resource\t{}",
+            want.get_resource("key-1").unwrap()
+        )));
+    }
+
+    #[test]
+    fn test_u64_keys() {
+        let dir = TempDir::new("u64");
+        let path = dir.0.join("state");
+        exec(&path, "--hasher sip init 10 a b c", "").unwrap();
+
+        let want: AnchorHash<u64, _, _> =
+            Builder::with_hasher(BuildHasherDefault::<DefaultHasher>::default())
+                .with_resources(vec!["a", "b", "c"])
+                .build(10);
+
+        let (_, out) = exec(&path, "--hasher sip --key-type u64 lookup 1 2 3", "").unwrap();
+        for line in out.lines() {
+            let (k, r) = line.split_once('\t').unwrap();
+            assert_eq!(Some(&r), want.get_resource(k.parse().unwrap()));
+        }
+
+        assert!(exec(&path, "--key-type u64 lookup nope", "").is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        let dir = TempDir::new("errors");
+        let path = dir.0.join("state");
+
+        assert!(exec(&path, "lookup a", "").is_err());
+        exec(&path, "init 2 a", "").unwrap();
+        assert!(exec(&path, "init 2 a", "").is_err());
+        exec(&path, "--force init 2 a", "").unwrap();
+
+        assert!(exec(&path, "add a", "").is_err(), "duplicate resource");
+        exec(&path, "add b", "").unwrap();
+        assert!(exec(&path, "add c", "").is_err(), "capacity reached");
+        assert!(exec(&path, "remove z", "").is_err());
+        assert!(exec(&path, "frobnicate", "").is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        let dir = TempDir::new("diff");
+        let a = dir.0.join("a");
+        let b = dir.0.join("b");
+        exec(&a, "init 5 a b c", "").unwrap();
+        exec(&b, "init 5 a b c", "").unwrap();
+
+        let args = format!("diff {} {}", a.display(), b.display());
+        assert_eq!(exec(&a, &args, "").unwrap(), (0, String::new()));
+
+        exec(&b, "remove c", "").unwrap();
+        let (code, out) = exec(&a, &args, "").unwrap();
+        assert_eq!(code, 1);
+        assert!(out.starts_with("bucket 2\tworking c"), "{}", out);
+
+        let (_, fa) = exec(&a, "fingerprint", "").unwrap();
+        let (_, fb) = exec(&b, "fingerprint", "").unwrap();
+        assert_ne!(fa, fb);
+    }
+}
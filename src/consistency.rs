@@ -0,0 +1,173 @@
+use core::hash::{Hash, Hasher};
+
+use alloc::vec::Vec;
+
+use fnv::FnvHasher;
+
+use crate::anchor::{Anchor, Storage};
+
+/// The version of the fingerprint digest, included in the digest so that any
+/// future change to its construction produces distinct fingerprints.
+const FINGERPRINT_VERSION: u8 = 1;
+
+/// Compute a stable digest of `anchor` and the bucket to resource assignment
+/// in `resources`, which MUST be ordered by bucket.
+pub(crate) fn fingerprint<'a, S, R, I>(anchor: &Anchor<S>, resources: I) -> u64
+where
+    S: Storage,
+    R: Hash + 'a,
+    I: IntoIterator<Item = (u16, &'a R)>,
+{
+    // FNV has no per-process seed, so the digest is comparable across nodes.
+    // Integers are written little-endian to be independent of the platform.
+    let mut h = FnvHasher::default();
+    h.write(&[FINGERPRINT_VERSION]);
+    h.write(&anchor.capacity().to_le_bytes());
+    h.write(&anchor.working().to_le_bytes());
+
+    // The anchor state is fully determined by the stack of removed buckets.
+    for b in anchor.removed() {
+        h.write(&b.to_le_bytes());
+    }
+
+    for (b, r) in resources {
+        h.write(&b.to_le_bytes());
+        r.hash(&mut h);
+    }
+
+    h.finish()
+}
+
+/// The state of a single bucket, as reported by [`BucketDiff`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct BucketState<'a, R> {
+    resource: Option<&'a R>,
+    working: bool,
+    a: u16,
+    k: u16,
+}
+
+// Implemented manually as only a reference to `R` is held, so `R` does not
+// need to implement `Clone`.
+impl<R> Clone for BucketState<'_, R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R> Copy for BucketState<'_, R> {}
+
+impl<'a, R> BucketState<'a, R> {
+    /// Returns the resource assigned to the bucket, if any.
+    pub fn resource(&self) -> Option<&'a R> {
+        self.resource
+    }
+
+    /// Returns true if the bucket is working, or false if it has been removed.
+    pub fn is_working(&self) -> bool {
+        self.working
+    }
+
+    /// Returns `A[b]`, the number of working buckets immediately after this
+    /// bucket was removed (or 0 for a working bucket).
+    pub fn a(&self) -> u16 {
+        self.a
+    }
+
+    /// Returns `K[b]`, the bucket that replaced this bucket when it was
+    /// removed (or the bucket itself for a working bucket).
+    pub fn k(&self) -> u16 {
+        self.k
+    }
+}
+
+/// A bucket whose state differs between two [`AnchorHash`] instances, as
+/// returned by [`AnchorHash::diff()`].
+///
+/// A bucket that does not exist in one of the instances (because it has a
+/// smaller capacity) has no state on that side.
+///
+/// [`AnchorHash`]: crate::AnchorHash
+/// [`AnchorHash::diff()`]: crate::AnchorHash::diff
+#[derive(Debug, PartialEq, Eq)]
+pub struct BucketDiff<'a, R> {
+    bucket: u16,
+    left: Option<BucketState<'a, R>>,
+    right: Option<BucketState<'a, R>>,
+}
+
+impl<R> Clone for BucketDiff<'_, R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R> Copy for BucketDiff<'_, R> {}
+
+impl<'a, R> BucketDiff<'a, R> {
+    /// Returns the bucket that differs.
+    pub fn bucket(&self) -> u16 {
+        self.bucket
+    }
+
+    /// Returns the state of the bucket in the instance `diff()` was called on.
+    pub fn left(&self) -> Option<&BucketState<'a, R>> {
+        self.left.as_ref()
+    }
+
+    /// Returns the state of the bucket in the instance passed to `diff()`.
+    pub fn right(&self) -> Option<&BucketState<'a, R>> {
+        self.right.as_ref()
+    }
+}
+
+/// Read the state of bucket `b` of `anchor`, resolving its resource with
+/// `resource`.
+fn bucket_state<'a, S, R, F>(anchor: &Anchor<S>, b: u16, resource: F) -> Option<BucketState<'a, R>>
+where
+    S: Storage,
+    F: Fn(u16) -> Option<&'a R>,
+{
+    if b >= anchor.capacity() {
+        return None;
+    }
+
+    let slot = anchor.lookup_tables()[b as usize];
+    Some(BucketState {
+        resource: resource(b),
+        working: anchor.is_working(b),
+        a: slot.a,
+        k: slot.k,
+    })
+}
+
+/// Compare every bucket of the `left` and `right` anchors, returning those
+/// whose state differs.
+pub(crate) fn diff<'a, S, T, R, F, G>(
+    left: &Anchor<S>,
+    left_resource: F,
+    right: &Anchor<T>,
+    right_resource: G,
+) -> Vec<BucketDiff<'a, R>>
+where
+    S: Storage,
+    T: Storage,
+    R: PartialEq,
+    F: Fn(u16) -> Option<&'a R>,
+    G: Fn(u16) -> Option<&'a R>,
+{
+    (0..left.capacity().max(right.capacity()))
+        .filter_map(|b| {
+            let l = bucket_state(left, b, &left_resource);
+            let r = bucket_state(right, b, &right_resource);
+            if l == r {
+                return None;
+            }
+            Some(BucketDiff {
+                bucket: b,
+                left: l,
+                right: r,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,239 @@
+use core::hash::{BuildHasher, Hash};
+
+use crate::{error::Result, AnchorHash};
+
+/// A consistent hash mapping keys to a changing set of resources of type `R`.
+///
+/// `ConsistentHash` abstracts over the algorithms provided by this crate, so
+/// code that routes keys can switch algorithm by changing a type parameter -
+/// for example, to compare [`AnchorHash`] with [`JumpHash`] in an A/B test or
+/// benchmark:
+///
+/// ```rust
+/// use anchorhash::{ConsistentHash, JumpHash};
+///
+/// fn route<C: ConsistentHash<&'static str>>(c: &mut C) -> Option<&'static str> {
+///     c.add("cache1").unwrap();
+///     c.add("cache2").unwrap();
+///     c.lookup("user-A").copied()
+/// }
+///
+/// let mut anchor = anchorhash::Builder::default().build_any_key(10);
+/// assert!(route(&mut anchor).is_some());
+///
+/// let mut jump = JumpHash::new();
+/// assert!(route(&mut jump).is_some());
+/// ```
+///
+/// Implementations differ in the operations they support - [`JumpHash`] can
+/// only remove the most recently added resource, and [`AnchorHash`] can only
+/// hold as many resources as its configured capacity.
+///
+/// [`AnchorHash`]: crate::AnchorHash
+/// [`JumpHash`]: crate::JumpHash
+pub trait ConsistentHash<R> {
+    /// Consistently hash `key` to a resource, returning [`None`] when there
+    /// are no resources.
+    fn lookup<Q>(&self, key: &Q) -> Option<&R>
+    where
+        Q: Hash + ?Sized;
+
+    /// Add `resource`, moving a fair share of keys to it.
+    fn add(&mut self, resource: R) -> Result<()>;
+
+    /// Remove `resource`, moving the keys mapped to it to the remaining
+    /// resources.
+    fn remove(&mut self, resource: &R) -> Result<()>;
+
+    /// Returns the number of resources.
+    fn len(&self) -> usize;
+
+    /// Returns true if there are no resources.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, R, B> ConsistentHash<R> for AnchorHash<K, R, B>
+where
+    R: PartialEq,
+    B: BuildHasher,
+{
+    fn lookup<Q>(&self, key: &Q) -> Option<&R>
+    where
+        Q: Hash + ?Sized,
+    {
+        AnchorHash::lookup(self, key)
+    }
+
+    fn add(&mut self, resource: R) -> Result<()> {
+        self.add_resource(resource)
+    }
+
+    fn remove(&mut self, resource: &R) -> Result<()> {
+        self.remove_resource(resource)
+    }
+
+    fn len(&self) -> usize {
+        self.resources().len()
+    }
+}
+
+/// Disruption and balance tests run against every [`ConsistentHash`]
+/// implementation.
+#[cfg(test)]
+mod tests {
+    use std::{collections::hash_map::DefaultHasher, hash::BuildHasherDefault};
+
+    use hashbrown::HashMap;
+
+    use super::*;
+    use crate::{analysis::tolerance, Builder, JumpHash, MementoHash, Rendezvous};
+
+    type Hasher = BuildHasherDefault<DefaultHasher>;
+
+    const RESOURCES: u32 = 10;
+    const KEYS: u64 = 50_000;
+
+    fn anchor() -> AnchorHash<u64, u32, Hasher> {
+        Builder::with_hasher(Hasher::default())
+            .with_resources(0..RESOURCES)
+            .build(100)
+    }
+
+    fn memento() -> MementoHash<u64, u32, Hasher> {
+        Builder::with_hasher(Hasher::default())
+            .with_resources(0..RESOURCES)
+            .build_unbounded()
+    }
+
+    fn jump() -> JumpHash<u32, Hasher> {
+        JumpHash::with_hasher(Hasher::default()).with_resources(0..RESOURCES)
+    }
+
+    fn rendezvous() -> Rendezvous<u32, Hasher> {
+        let mut r = Rendezvous::with_hasher(Hasher::default());
+        (0..RESOURCES).for_each(|v| r.add_resource(v).unwrap());
+        r
+    }
+
+    fn owners<C: ConsistentHash<u32>>(c: &C) -> Vec<u32> {
+        (0..KEYS).map(|k| *c.lookup(&k).unwrap()).collect()
+    }
+
+    /// Assert every resource holds within the [`tolerance()`] of the mean load,
+    /// with either range mapping.
+    fn assert_balanced<C: ConsistentHash<u32>>(c: &C) {
+        let mut load = HashMap::new();
+        for r in owners(c) {
+            *load.entry(r).or_insert(0) += 1;
+        }
+        assert_eq!(load.len(), c.len());
+
+        let mean = KEYS as f64 / c.len() as f64;
+        let tolerance = tolerance(mean);
+        for (r, n) in load {
+            assert!(
+                (n as f64 - mean).abs() <= tolerance,
+                "resource {} has {} keys, want {} +/- {}",
+                r,
+                n,
+                mean,
+                tolerance
+            );
+        }
+    }
+
+    /// Assert that only the keys of `changed` move between `before` and
+    /// `after`, and that the fraction moved is close to `optimal`.
+    fn assert_minimal_disruption(before: &[u32], after: &[u32], changed: u32, optimal: f64) {
+        let mut moved = 0;
+        for (k, (old, new)) in before.iter().zip(after).enumerate() {
+            if old != new {
+                assert!(
+                    *old == changed || *new == changed,
+                    "key {} moved from {} to {}",
+                    k,
+                    old,
+                    new
+                );
+                moved += 1;
+            }
+        }
+
+        let optimal = optimal * KEYS as f64;
+        assert!(
+            (moved as f64 - optimal).abs() <= tolerance(optimal),
+            "moved {} keys, optimal {}",
+            moved,
+            optimal
+        );
+    }
+
+    fn check<C: ConsistentHash<u32>>(mut c: C) {
+        assert_eq!(c.len(), RESOURCES as usize);
+        assert!(!c.is_empty());
+        assert_balanced(&c);
+
+        // Adding a resource moves only the keys it takes.
+        let before = owners(&c);
+        c.add(RESOURCES).unwrap();
+        assert_eq!(c.len(), RESOURCES as usize + 1);
+        let after = owners(&c);
+        assert_minimal_disruption(&before, &after, RESOURCES, 1.0 / f64::from(RESOURCES + 1));
+        assert_balanced(&c);
+
+        // Removing it restores the original mapping.
+        c.remove(&RESOURCES).unwrap();
+        assert_eq!(owners(&c), before);
+
+        // Removing the most recently added resource (supported by all
+        // implementations) moves only its keys.
+        c.remove(&(RESOURCES - 1)).unwrap();
+        let after = owners(&c);
+        assert_minimal_disruption(&before, &after, RESOURCES - 1, 1.0 / f64::from(RESOURCES));
+        assert_balanced(&c);
+
+        assert!(c.remove(&42).is_err());
+    }
+
+    #[test]
+    fn test_anchorhash() {
+        check(anchor());
+    }
+
+    #[test]
+    fn test_memento() {
+        check(memento());
+    }
+
+    #[test]
+    fn test_jump() {
+        check(jump());
+    }
+
+    #[test]
+    fn test_rendezvous() {
+        check(rendezvous());
+    }
+
+    /// Removing every resource leaves no mapping, for every implementation.
+    #[test]
+    fn test_remove_all() {
+        fn drain<C: ConsistentHash<u32>>(mut c: C) {
+            for r in (0..RESOURCES).rev() {
+                c.remove(&r).unwrap();
+            }
+            assert!(c.is_empty());
+            assert_eq!(c.lookup(&42_u64), None);
+
+            c.add(1).unwrap();
+            assert_eq!(c.lookup(&42_u64), Some(&1));
+        }
+
+        drain(anchor());
+        drain(memento());
+        drain(jump());
+        drain(rendezvous());
+    }
+}
@@ -0,0 +1,614 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hash},
+    sync::{
+        mpsc::{channel, SendError, Sender},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use hashbrown::HashMap;
+use thiserror::Error;
+
+use crate::{AnchorHash, Builder, Result};
+
+/// An opaque identifier of a worker thread in a [`KeyedDispatcher`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WorkerId(u64);
+
+/// Errors returned by [`KeyedDispatcher::dispatch()`], returning the key and
+/// item that could not be dispatched.
+#[derive(Debug, Error)]
+pub enum DispatchError<K, T> {
+    /// The dispatcher has no workers to process the item.
+    #[error("no worker available")]
+    NoWorker(K, T),
+
+    /// The worker the key maps to has stopped, after its handler panicked.
+    #[error("worker stopped")]
+    WorkerStopped(K, T),
+}
+
+impl<K, T> DispatchError<K, T> {
+    /// Returns the key and item that could not be dispatched.
+    pub fn into_inner(self) -> (K, T) {
+        match self {
+            Self::NoWorker(k, t) | Self::WorkerStopped(k, t) => (k, t),
+        }
+    }
+}
+
+type Handler<K, T> = Arc<dyn Fn(&K, T) + Send + Sync>;
+
+/// The state of an [`InFlight`], guarded by a single lock.
+#[derive(Debug)]
+struct Keys<K> {
+    /// The worker processing the items for each key, and the number of items.
+    owners: HashMap<K, (WorkerId, usize)>,
+    /// The workers stopped by a panicking handler.
+    stopped: Vec<WorkerId>,
+}
+
+/// The number of items in flight for each key, and the worker processing
+/// them.
+#[derive(Debug)]
+struct InFlight<K> {
+    keys: Mutex<Keys<K>>,
+    drained: Condvar,
+}
+
+impl<K> InFlight<K>
+where
+    K: Hash + Eq,
+{
+    /// Record an item for `key` as queued on `worker`, waiting for any items
+    /// in flight for `key` on a different worker to complete first.
+    ///
+    /// Returns false if `worker` has stopped.
+    fn acquire(&self, key: &K, worker: WorkerId) -> bool
+    where
+        K: Clone,
+    {
+        let mut keys = self.keys.lock().unwrap();
+        loop {
+            if keys.stopped.contains(&worker) {
+                return false;
+            }
+
+            match keys.owners.get_mut(key) {
+                Some((owner, n)) if *owner == worker => {
+                    *n += 1;
+                    return true;
+                }
+                Some(_) => keys = self.drained.wait(keys).unwrap(),
+                None => {
+                    keys.owners.insert(key.clone(), (worker, 1));
+                    return true;
+                }
+            }
+        }
+    }
+
+    /// Record an item for `key` queued on `worker` as complete.
+    fn release(&self, key: &K, worker: WorkerId) {
+        let mut keys = self.keys.lock().unwrap();
+        let n = match keys.owners.get_mut(key) {
+            Some((owner, n)) if *owner == worker => n,
+            // The items were discarded when the worker stopped.
+            _ => return,
+        };
+
+        *n -= 1;
+        if *n == 0 {
+            keys.owners.remove(key);
+            self.drained.notify_all();
+        }
+    }
+
+    /// Record `worker` as stopped, discarding the items still queued on it so
+    /// their keys can move to another worker.
+    fn stop(&self, worker: WorkerId) {
+        let mut keys = self.keys.lock().unwrap();
+        keys.stopped.push(worker);
+        keys.owners.retain(|_, (owner, _)| *owner != worker);
+        self.drained.notify_all();
+    }
+}
+
+/// Releases an in-flight item when dropped, including when the handler
+/// panics.
+struct Release<'a, K>
+where
+    K: Hash + Eq,
+{
+    in_flight: &'a InFlight<K>,
+    key: &'a K,
+    worker: WorkerId,
+}
+
+impl<K> Drop for Release<'_, K>
+where
+    K: Hash + Eq,
+{
+    fn drop(&mut self) {
+        self.in_flight.release(self.key, self.worker);
+    }
+}
+
+/// Stops a worker when its thread unwinds after the handler panics, releasing
+/// the items queued behind the panicking item.
+struct Stop<'a, K>
+where
+    K: Hash + Eq,
+{
+    in_flight: &'a InFlight<K>,
+    worker: WorkerId,
+}
+
+impl<K> Drop for Stop<'_, K>
+where
+    K: Hash + Eq,
+{
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.in_flight.stop(self.worker);
+        }
+    }
+}
+
+struct Worker<K, T> {
+    tx: Sender<(K, T)>,
+    handle: JoinHandle<()>,
+}
+
+/// Dispatches items to a set of worker threads by key, running all the items
+/// for a key in order on a single worker.
+///
+/// Keys are mapped to workers with an [`AnchorHash`], so adding or removing a
+/// worker moves only the keys mapped to the changed worker. When a key moves,
+/// [`dispatch()`] blocks until the items in flight for the key on the old
+/// worker have been processed before queueing it on the new worker, so the
+/// items for each key are always processed in the order they were
+/// dispatched:
+///
+/// ```rust
+/// use anchorhash::KeyedDispatcher;
+///
+/// // Process (user, job) pairs, with up to 8 worker threads.
+/// let mut dispatcher = KeyedDispatcher::new(8, |user: &String, job: u32| {
+///     println!("running job {} for {}", job, user);
+/// });
+///
+/// let a = dispatcher.add_worker().unwrap();
+/// dispatcher.add_worker().unwrap();
+///
+/// dispatcher.dispatch("user-A".to_string(), 1).unwrap();
+/// dispatcher.dispatch("user-A".to_string(), 2).unwrap();
+///
+/// // Jobs for user-A still run in order, even if they move to another worker.
+/// dispatcher.remove_worker(a).unwrap();
+/// dispatcher.dispatch("user-A".to_string(), 3).unwrap();
+///
+/// // Wait for all queued jobs to complete.
+/// dispatcher.shutdown().unwrap();
+/// ```
+///
+/// Each worker is a [`std::thread`] processing items from an unbounded
+/// channel. Dropping the dispatcher waits for all queued items to be
+/// processed.
+///
+/// [`dispatch()`]: Self::dispatch
+pub struct KeyedDispatcher<K, T, B = RandomState>
+where
+    B: BuildHasher,
+{
+    anchor: AnchorHash<K, WorkerId, B>,
+    workers: HashMap<WorkerId, Worker<K, T>>,
+    retired: Vec<JoinHandle<()>>,
+    in_flight: Arc<InFlight<K>>,
+    handler: Handler<K, T>,
+    next_id: u64,
+}
+
+impl<K, T> KeyedDispatcher<K, T, RandomState>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    T: Send + 'static,
+{
+    /// Initialise a dispatcher with no workers and support for up to
+    /// `capacity` workers, processing each item with `handler`.
+    pub fn new<F>(capacity: u16, handler: F) -> Self
+    where
+        F: Fn(&K, T) + Send + Sync + 'static,
+    {
+        Self::with_hasher(capacity, RandomState::default(), handler)
+    }
+}
+
+impl<K, T, B> KeyedDispatcher<K, T, B>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    T: Send + 'static,
+    B: BuildHasher,
+{
+    /// Initialise a dispatcher with no workers and support for up to
+    /// `capacity` workers, using `hasher` to map keys to workers.
+    pub fn with_hasher<F>(capacity: u16, hasher: B, handler: F) -> Self
+    where
+        F: Fn(&K, T) + Send + Sync + 'static,
+    {
+        Self {
+            anchor: Builder::with_hasher(hasher).build(capacity),
+            workers: HashMap::new(),
+            retired: Vec::new(),
+            in_flight: Arc::new(InFlight {
+                keys: Mutex::new(Keys {
+                    owners: HashMap::new(),
+                    stopped: Vec::new(),
+                }),
+                drained: Condvar::new(),
+            }),
+            handler: Arc::new(handler),
+            next_id: 0,
+        }
+    }
+
+    /// Start a new worker thread, moving a fair share of keys to it.
+    ///
+    /// Returns [`Error::CapacityLimitReached`] if the dispatcher has as many
+    /// workers as its capacity.
+    ///
+    /// [`Error::CapacityLimitReached`]: crate::Error::CapacityLimitReached
+    pub fn add_worker(&mut self) -> Result<WorkerId> {
+        let id = WorkerId(self.next_id);
+        self.anchor.add_resource(id)?;
+        self.next_id += 1;
+
+        let (tx, rx) = channel::<(K, T)>();
+        let in_flight = Arc::clone(&self.in_flight);
+        let handler = Arc::clone(&self.handler);
+        let handle = thread::spawn(move || {
+            let _stop = Stop {
+                in_flight: &in_flight,
+                worker: id,
+            };
+            for (key, item) in rx {
+                let _release = Release {
+                    in_flight: &in_flight,
+                    key: &key,
+                    worker: id,
+                };
+                handler(&key, item);
+            }
+        });
+
+        self.workers.insert(id, Worker { tx, handle });
+        Ok(id)
+    }
+
+    /// Stop the worker identified by `id`, moving its keys to the remaining
+    /// workers.
+    ///
+    /// The worker thread exits once the items already queued on it have been
+    /// processed - this method does not wait for it.
+    pub fn remove_worker(&mut self, id: WorkerId) -> Result<()> {
+        self.anchor.remove_resource(&id)?;
+
+        let worker = self
+            .workers
+            .remove(&id)
+            .expect("mapped worker must be present");
+
+        // Dropping the sender stops the worker once its queue is empty.
+        self.retired.retain(|h| !h.is_finished());
+        self.retired.push(worker.handle);
+        Ok(())
+    }
+
+    /// Queue `item` on the worker `key` maps to.
+    ///
+    /// If items for `key` are still in flight on a worker `key` no longer maps
+    /// to, this call blocks until they have been processed.
+    pub fn dispatch(&self, key: K, item: T) -> core::result::Result<(), DispatchError<K, T>> {
+        let id = match self.route(&key) {
+            Some(v) => v,
+            None => return Err(DispatchError::NoWorker(key, item)),
+        };
+
+        if !self.in_flight.acquire(&key, id) {
+            return Err(DispatchError::WorkerStopped(key, item));
+        }
+        self.workers[&id]
+            .tx
+            .send((key, item))
+            .map_err(|SendError((key, item))| {
+                self.in_flight.release(&key, id);
+                DispatchError::WorkerStopped(key, item)
+            })
+    }
+
+    /// Returns the [`WorkerId`] items with `key` are dispatched to.
+    pub fn route(&self, key: &K) -> Option<WorkerId> {
+        self.anchor.lookup(key).copied()
+    }
+
+    /// Returns the number of workers.
+    pub fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Returns true if the dispatcher has no workers.
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    /// Stop all workers, waiting for all queued items to be processed.
+    ///
+    /// Returns the panic payload of the first worker whose handler panicked,
+    /// if any.
+    pub fn shutdown(mut self) -> thread::Result<()> {
+        self.join()
+    }
+}
+
+impl<K, T, B> KeyedDispatcher<K, T, B>
+where
+    B: BuildHasher,
+{
+    fn join(&mut self) -> thread::Result<()> {
+        // Collecting the handles drops every sender, so each worker exits once
+        // its queue is empty.
+        let handles = self
+            .workers
+            .drain()
+            .map(|(_, w)| w.handle)
+            .chain(self.retired.drain(..))
+            .collect::<Vec<_>>();
+
+        // Join every worker, even after one has failed.
+        let mut ret = Ok(());
+        for h in handles {
+            if let (Err(e), Ok(())) = (h.join(), &ret) {
+                ret = Err(e);
+            }
+        }
+        ret
+    }
+}
+
+impl<K, T, B> Drop for KeyedDispatcher<K, T, B>
+where
+    B: BuildHasher,
+{
+    fn drop(&mut self) {
+        let _ = self.join();
+    }
+}
+
+impl<K, T, B> fmt::Debug for KeyedDispatcher<K, T, B>
+where
+    K: fmt::Debug,
+    B: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyedDispatcher")
+            .field("workers", &self.workers.keys().collect::<Vec<_>>())
+            .field("in_flight", &self.in_flight)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::hash_map::DefaultHasher, hash::BuildHasherDefault, sync::mpsc, time::Duration,
+    };
+
+    use super::*;
+    use crate::Error;
+
+    type Log = Arc<Mutex<Vec<(u64, u32)>>>;
+
+    fn dispatcher(log: &Log) -> KeyedDispatcher<u64, u32, BuildHasherDefault<DefaultHasher>> {
+        let log = Arc::clone(log);
+        KeyedDispatcher::with_hasher(10, BuildHasherDefault::default(), move |k: &u64, v| {
+            log.lock().unwrap().push((*k, v))
+        })
+    }
+
+    /// Assert the items for each key in `log` are in dispatch order.
+    fn assert_ordered(log: &Log, keys: u64, items: u32) {
+        let log = log.lock().unwrap();
+        for k in 0..keys {
+            let got = log
+                .iter()
+                .filter(|(key, _)| *key == k)
+                .map(|(_, v)| *v)
+                .collect::<Vec<_>>();
+            assert_eq!(got, (0..items).collect::<Vec<_>>(), "key {}", k);
+        }
+    }
+
+    #[test]
+    fn test_per_key_order() {
+        let log = Log::default();
+        let mut d = dispatcher(&log);
+        for _ in 0..4 {
+            d.add_worker().unwrap();
+        }
+        assert_eq!(d.len(), 4);
+
+        for v in 0..100 {
+            for k in 0..20 {
+                d.dispatch(k, v).unwrap();
+            }
+        }
+
+        d.shutdown().unwrap();
+        assert_ordered(&log, 20, 100);
+    }
+
+    #[test]
+    fn test_rebalance_order() {
+        let log = Log::default();
+        let mut d = dispatcher(&log);
+        let workers = (0..4).map(|_| d.add_worker().unwrap()).collect::<Vec<_>>();
+
+        // Interleave membership changes with dispatching.
+        for v in 0..100 {
+            for k in 0..20 {
+                d.dispatch(k, v).unwrap();
+            }
+            match v {
+                20 => d.remove_worker(workers[0]).unwrap(),
+                40 => drop(d.add_worker().unwrap()),
+                60 => d.remove_worker(workers[2]).unwrap(),
+                _ => {}
+            }
+        }
+
+        d.shutdown().unwrap();
+        assert_ordered(&log, 20, 100);
+    }
+
+    #[test]
+    fn test_moved_key_waits_for_old_worker() {
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let release_rx = Mutex::new(release_rx);
+        let log = Log::default();
+
+        let mut d = {
+            let log = Arc::clone(&log);
+            KeyedDispatcher::with_hasher(
+                10,
+                BuildHasherDefault::<DefaultHasher>::default(),
+                move |k: &u64, v| {
+                    // Block the first item until the test releases it.
+                    if v == 0 {
+                        release_rx.lock().unwrap().recv().unwrap();
+                    }
+                    log.lock().unwrap().push((*k, v));
+                },
+            )
+        };
+        let old = d.add_worker().unwrap();
+        d.add_worker().unwrap();
+
+        let key = (0..).find(|k| d.route(k) == Some(old)).unwrap();
+        d.dispatch(key, 0).unwrap();
+
+        // Move the key while its first item is still in flight.
+        d.remove_worker(old).unwrap();
+        assert_ne!(d.route(&key), Some(old));
+
+        thread::scope(|s| {
+            let second = s.spawn(|| d.dispatch(key, 1).unwrap());
+
+            thread::sleep(Duration::from_millis(50));
+            assert!(!second.is_finished());
+            assert!(log.lock().unwrap().is_empty());
+
+            release_tx.send(()).unwrap();
+        });
+
+        d.shutdown().unwrap();
+        assert_eq!(*log.lock().unwrap(), [(key, 0), (key, 1)]);
+    }
+
+    #[test]
+    fn test_add_worker_moves_only_its_keys() {
+        let log = Log::default();
+        let mut d = dispatcher(&log);
+        for _ in 0..4 {
+            d.add_worker().unwrap();
+        }
+
+        let before = (0..1_000).map(|k| d.route(&k).unwrap()).collect::<Vec<_>>();
+        let new = d.add_worker().unwrap();
+        for (k, old) in (0..1_000).zip(before) {
+            let got = d.route(&k).unwrap();
+            assert!(got == old || got == new);
+        }
+    }
+
+    #[test]
+    fn test_no_worker() {
+        let log = Log::default();
+        let mut d = dispatcher(&log);
+
+        let err = d.dispatch(1, 2).unwrap_err();
+        assert!(matches!(err, DispatchError::NoWorker(1, 2)));
+        assert_eq!(err.into_inner(), (1, 2));
+
+        let id = d.add_worker().unwrap();
+        d.remove_worker(id).unwrap();
+        assert_eq!(d.remove_worker(id), Err(Error::ResourceNotFound));
+        assert!(d.is_empty());
+    }
+
+    #[test]
+    fn test_handler_panic() {
+        let (fail_tx, fail_rx) = mpsc::channel::<()>();
+        let fail_rx = Mutex::new(fail_rx);
+
+        let mut d = KeyedDispatcher::with_hasher(
+            10,
+            BuildHasherDefault::<DefaultHasher>::default(),
+            move |_k: &u64, v: u32| {
+                // Block the first item until the test fails it.
+                if v == 0 {
+                    fail_rx.lock().unwrap().recv().unwrap();
+                    panic!("handler failure");
+                }
+            },
+        );
+        let old = d.add_worker().unwrap();
+
+        // Queue key 2 behind the panicking item for key 1.
+        d.dispatch(1, 0).unwrap();
+        d.dispatch(2, 1).unwrap();
+        fail_tx.send(()).unwrap();
+
+        // Both the panicking item and the item discarded behind it are
+        // released, so moving the keys does not block.
+        d.add_worker().unwrap();
+        d.remove_worker(old).unwrap();
+
+        let (done_tx, done_rx) = mpsc::channel();
+        thread::spawn(move || {
+            d.dispatch(1, 1).unwrap();
+            d.dispatch(2, 2).unwrap();
+            done_tx.send(d.shutdown().is_err()).unwrap();
+        });
+        assert!(done_rx.recv_timeout(Duration::from_secs(10)).unwrap());
+    }
+
+    #[test]
+    fn test_dispatch_to_stopped_worker() {
+        let mut d = KeyedDispatcher::with_hasher(
+            10,
+            BuildHasherDefault::<DefaultHasher>::default(),
+            |_k: &u64, _v: u32| panic!("handler failure"),
+        );
+        d.add_worker().unwrap();
+        d.dispatch(1, 0).unwrap();
+
+        // Wait for the worker to stop.
+        while !d
+            .in_flight
+            .keys
+            .lock()
+            .unwrap()
+            .stopped
+            .contains(&WorkerId(0))
+        {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let err = d.dispatch(2, 1).unwrap_err();
+        assert!(matches!(err, DispatchError::WorkerStopped(2, 1)));
+        assert!(d.shutdown().is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use thiserror::Error;
+
+/// Errors returned when constructing or operating on an [`AnchorHash`]
+/// instance.
+///
+/// [`AnchorHash`]: crate::AnchorHash
+#[derive(Debug, Error, PartialEq, Eq, Clone, Copy)]
+pub enum Error {
+    /// A new bucket cannot be added to the AnchorHash instance as it has
+    /// reached the configured capacity.
+    #[error("configured resource capacity reached")]
+    CapacityLimitReached,
+
+    /// The number of resources requested exceeds the capacity available.
+    ///
+    /// Returned when constructing an instance with more initial resources than
+    /// the configured capacity, or more than the maximum capacity of
+    /// [`u16::MAX`].
+    #[error("{requested} resources requested, but capacity is {capacity}")]
+    CapacityExceeded {
+        /// The number of resources requested.
+        requested: usize,
+        /// The available capacity.
+        capacity: u16,
+    },
+
+    /// The requested resource is not registered with the AnchorHash instance.
+    #[error("resource not found")]
+    ResourceNotFound,
+
+    /// The resource is equal to a resource already registered with the
+    /// AnchorHash instance, and the [`DuplicatePolicy`] rejects duplicates.
+    ///
+    /// [`DuplicatePolicy`]: crate::DuplicatePolicy
+    #[error("duplicate resource")]
+    DuplicateResource,
+
+    /// The operation is not supported by the [`ConsistentHash`]
+    /// implementation, such as removing a resource other than the most
+    /// recently added from a [`JumpHash`].
+    ///
+    /// [`ConsistentHash`]: crate::ConsistentHash
+    /// [`JumpHash`]: crate::JumpHash
+    #[error("unsupported operation: {0}")]
+    UnsupportedOperation(&'static str),
+
+    /// The weight of a resource is not a positive, finite number.
+    #[error("resource weight must be positive and finite")]
+    InvalidWeight,
+
+    /// The operation would leave the instance in an inconsistent state, such
+    /// as removing a bucket that is not in use.
+    #[error("invalid state: {0}")]
+    InvalidState(&'static str),
+
+    /// The [`Snapshot`] does not describe a valid AnchorHash state.
+    ///
+    /// [`Snapshot`]: crate::Snapshot
+    #[error("invalid snapshot: {0}")]
+    InvalidSnapshot(&'static str),
+
+    /// The bytes given to an [`ArchiveView`] are not a valid archive.
+    ///
+    /// [`ArchiveView`]: crate::ArchiveView
+    #[error("invalid archive: {0}")]
+    InvalidArchive(&'static str),
+}
+
+/// An invariant of the AnchorHash state that does not hold, returned by
+/// [`AnchorHash::validate()`].
+///
+/// Each variant describes the first violation found - a violation indicates a
+/// bug in this crate, or a corrupted instance.
+///
+/// [`AnchorHash::validate()`]: crate::AnchorHash::validate
+#[derive(Debug, Error, PartialEq, Eq, Clone, Copy)]
+pub enum InvariantViolation {
+    /// An internal array does not hold exactly one entry per bucket.
+    #[error("array holds {len} entries, but capacity is {capacity}")]
+    ArrayLength {
+        /// The length of the array.
+        len: usize,
+        /// The capacity of the instance.
+        capacity: u16,
+    },
+
+    /// There are more working buckets than the capacity allows.
+    #[error("{working} working buckets, but capacity is {capacity}")]
+    WorkingCount {
+        /// The number of working buckets.
+        working: u16,
+        /// The capacity of the instance.
+        capacity: u16,
+    },
+
+    /// A bucket in the working set `W` or removed stack `R` is out of range.
+    #[error("bucket {bucket} is out of range")]
+    BucketOutOfRange {
+        /// The out of range bucket.
+        bucket: u16,
+    },
+
+    /// The location of a working bucket does not point back to it, violating
+    /// `W[L[b]] == b`.
+    #[error("working bucket {bucket} is at position {position}, but L[{bucket}] is {location}")]
+    Location {
+        /// The working bucket.
+        bucket: u16,
+        /// The position of the bucket in `W`.
+        position: u16,
+        /// The location `L[b]` recorded for the bucket.
+        location: u16,
+    },
+
+    /// A working bucket has a non-zero size, violating `A[b] == 0`.
+    #[error("working bucket {bucket} has A[{bucket}] = {size}, want 0")]
+    WorkingSize {
+        /// The working bucket.
+        bucket: u16,
+        /// The size `A[b]` recorded for the bucket.
+        size: u16,
+    },
+
+    /// A removed bucket does not record the size of the working set
+    /// immediately after its removal, violating `A[b] == |W_b|`.
+    ///
+    /// This includes a bucket appearing in the removed stack more than once,
+    /// or in both the working set and the removed stack.
+    #[error("removed bucket {bucket} has A[{bucket}] = {size}, want {want}")]
+    RemovedSize {
+        /// The removed bucket.
+        bucket: u16,
+        /// The size `A[b]` recorded for the bucket.
+        size: u16,
+        /// The size of the working set immediately after the bucket was
+        /// removed.
+        want: u16,
+    },
+
+    /// The successor `K[b]` of a bucket is not the bucket itself for a
+    /// working bucket, or a bucket in `W_b` for a removed bucket.
+    #[error("bucket {bucket} has invalid successor K[{bucket}] = {successor}")]
+    Successor {
+        /// The bucket.
+        bucket: u16,
+        /// The successor `K[b]` recorded for the bucket.
+        successor: u16,
+    },
+
+    /// The most recent change recorded for a position of `W` does not move
+    /// the bucket at that position into it.
+    #[error("position {position} of W holds bucket {bucket}, but its history ends with bucket {occupant}")]
+    Occupant {
+        /// The position in `W`.
+        position: u16,
+        /// The working bucket at the position.
+        bucket: u16,
+        /// The bucket recorded as the most recent occupant of the position.
+        occupant: u16,
+    },
+
+    /// A working bucket has no resource assigned.
+    #[error("working bucket {bucket} has no resource")]
+    MissingResource {
+        /// The working bucket.
+        bucket: u16,
+    },
+
+    /// A resource is assigned to a bucket that is not working.
+    #[error("bucket {bucket} is not working, but has a resource")]
+    UnexpectedResource {
+        /// The bucket.
+        bucket: u16,
+    },
+}
+
+pub(crate) type Result<T, E = Error> = core::result::Result<T, E>;
@@ -11,7 +11,7 @@
 /// [Fowler–Noll–Vo hash]: http://www.isthe.com/chongo/tech/comp/fnv/index.html
 #[cfg(all(target_arch = "x86_64", target_feature = "sse4.2", feature = "simd"))]
 pub fn fasthash(k: u32, seed: u32) -> u32 {
-    unsafe { std::arch::x86_64::_mm_crc32_u32(seed, k) }
+    unsafe { core::arch::x86_64::_mm_crc32_u32(seed, k) }
 }
 
 /// A hash function producing a 32 bit hash for `k`, using `seed` as the initial
@@ -25,14 +25,22 @@ pub fn fasthash(k: u32, seed: u32) -> u32 {
 /// [Fowler–Noll–Vo hash]: http://www.isthe.com/chongo/tech/comp/fnv/index.html
 #[cfg(not(all(target_arch = "x86_64", target_feature = "sse4.2", feature = "simd")))]
 pub fn fasthash(k: u32, seed: u32) -> u32 {
+    use core::hash::Hasher;
     use fnv::FnvHasher;
-    use std::hash::Hasher;
 
     let mut h = FnvHasher::with_key(seed.into());
     h.write_u32(k);
     h.finish() as u32 // Truncate down to u32, discarding 32 bits
 }
 
+/// The SplitMix64 finaliser, a bijective function mixing every bit of `v` into
+/// every bit of the result.
+pub(crate) fn mix64(mut v: u64) -> u64 {
+    v = (v ^ (v >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    v = (v ^ (v >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    v ^ (v >> 31)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
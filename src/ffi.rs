@@ -0,0 +1,798 @@
+//! A C API for consistently hashing keys with [`AnchorHash`], allowing
+//! programs written in other languages to make exactly the same routing
+//! decisions as Rust programs using this crate.
+//!
+//! The API is declared in the `include/anchorhash.h` header, and the library
+//! is built as a shared or static library with:
+//!
+//! ```text
+//! cargo rustc --lib --release --features ffi --crate-type cdylib
+//! cargo rustc --lib --release --features ffi --crate-type staticlib
+//! ```
+//!
+//! An instance is created with [`anchorhash_new()`] using one of the hash
+//! functions below, and owns a set of resources that are either integer ids or
+//! byte strings. A key maps to the same resource as it does in a Rust
+//! [`AnchorHash`] configured with the same hasher, given the same history of
+//! resource additions and removals:
+//!
+//! | C constant              | Rust hasher                          |
+//! |-------------------------|--------------------------------------|
+//! | `ANCHORHASH_HASHER_FNV` | [`FnvBuildHasher`]                   |
+//! | `ANCHORHASH_HASHER_SIP` | `BuildHasherDefault<DefaultHasher>`  |
+//!
+//! Keys passed to [`anchorhash_lookup_bytes()`] hash identically to a Rust
+//! `&[u8]`, and keys passed to [`anchorhash_lookup_str()`] hash identically to
+//! a Rust `&str`. Hashing a slice includes its length as a `usize`, so a key
+//! only maps identically between programs running on platforms with the same
+//! pointer width and endianness.
+//!
+//! Keys are also only mapped identically by builds that map buckets the same
+//! way. Builds for 64-bit targets with the `fastmod` feature (enabled by
+//! default) map ranges with a multiply and shift, while builds without it, or
+//! for other targets, use a modulo. Builds with the `simd` feature for targets
+//! that enable SSE4.2 hash buckets with a CRC32 instruction in place of FNV. A
+//! Rust program built with `default-features = false` therefore maps keys
+//! differently from the C library built with the default features.
+//!
+//! Fallible functions return one of the `ANCHORHASH_*` status codes, and an
+//! instance MUST NOT be used concurrently with a call that modifies it.
+//!
+//! [`FnvBuildHasher`]: fnv::FnvBuildHasher
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    convert::TryFrom,
+    hash::{BuildHasher, Hasher},
+    os::raw::{c_char, c_int},
+    ptr, slice,
+};
+
+use fnv::FnvHasher;
+
+use crate::{AnchorHash, AnyKey, Builder, Error, Snapshot};
+
+/// The operation completed successfully.
+pub const ANCHORHASH_OK: c_int = 0;
+/// The instance has reached its capacity and cannot accept more resources.
+pub const ANCHORHASH_CAPACITY_LIMIT_REACHED: c_int = 1;
+/// The resource is not registered with the instance.
+pub const ANCHORHASH_RESOURCE_NOT_FOUND: c_int = 2;
+/// The instance has no resources to map a key to.
+pub const ANCHORHASH_NO_RESOURCES: c_int = 3;
+/// An argument is a NULL pointer, an unknown constant or invalid UTF-8.
+pub const ANCHORHASH_INVALID_ARGUMENT: c_int = 4;
+/// The buffer is not a valid encoded snapshot.
+pub const ANCHORHASH_INVALID_SNAPSHOT: c_int = 5;
+/// The operation failed for any other reason.
+pub const ANCHORHASH_ERROR: c_int = 6;
+
+/// Hash keys with FNV-1a, as [`FnvBuildHasher`] does.
+///
+/// [`FnvBuildHasher`]: fnv::FnvBuildHasher
+pub const ANCHORHASH_HASHER_FNV: u32 = 0;
+/// Hash keys with the standard library's SipHash with zero keys, as
+/// `BuildHasherDefault<DefaultHasher>` does.
+pub const ANCHORHASH_HASHER_SIP: u32 = 1;
+
+/// The resource is an integer id.
+pub const ANCHORHASH_RESOURCE_ID: u32 = 0;
+/// The resource is a byte string.
+pub const ANCHORHASH_RESOURCE_BYTES: u32 = 1;
+
+/// The hash functions available to C callers.
+#[derive(Debug, Clone, Copy)]
+enum HasherKind {
+    Fnv,
+    Sip,
+}
+
+impl HasherKind {
+    fn from_raw(v: u32) -> Option<Self> {
+        match v {
+            ANCHORHASH_HASHER_FNV => Some(Self::Fnv),
+            ANCHORHASH_HASHER_SIP => Some(Self::Sip),
+            _ => None,
+        }
+    }
+}
+
+impl BuildHasher for HasherKind {
+    type Hasher = KeyHasher;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        match self {
+            Self::Fnv => KeyHasher::Fnv(FnvHasher::default()),
+            Self::Sip => KeyHasher::Sip(DefaultHasher::new()),
+        }
+    }
+}
+
+/// A [`Hasher`] dispatching to the hash function selected by [`HasherKind`].
+///
+/// Every write is forwarded, rather than relying on the default
+/// implementations, so the hash is always identical to the wrapped hasher's.
+enum KeyHasher {
+    Fnv(FnvHasher),
+    Sip(DefaultHasher),
+}
+
+impl Hasher for KeyHasher {
+    fn finish(&self) -> u64 {
+        match self {
+            Self::Fnv(h) => h.finish(),
+            Self::Sip(h) => h.finish(),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        match self {
+            Self::Fnv(h) => h.write(bytes),
+            Self::Sip(h) => h.write(bytes),
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        match self {
+            Self::Fnv(h) => h.write_u8(i),
+            Self::Sip(h) => h.write_u8(i),
+        }
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        match self {
+            Self::Fnv(h) => h.write_usize(i),
+            Self::Sip(h) => h.write_usize(i),
+        }
+    }
+}
+
+/// A resource registered through the C API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Resource {
+    Id(u64),
+    Bytes(Box<[u8]>),
+}
+
+/// An opaque [`AnchorHash`] instance, created with [`anchorhash_new()`] or
+/// [`anchorhash_snapshot_load()`] and released with [`anchorhash_free()`].
+#[derive(Debug)]
+pub struct FfiAnchorHash {
+    anchor: AnchorHash<AnyKey, Resource, HasherKind>,
+}
+
+/// The resource a key maps to, populated by the lookup functions.
+///
+/// When `kind` is [`ANCHORHASH_RESOURCE_ID`], `id` holds the resource and
+/// `bytes` is NULL. When `kind` is [`ANCHORHASH_RESOURCE_BYTES`], `bytes` and
+/// `len` describe the resource and `id` is zero - the bytes are owned by the
+/// instance, and remain valid until the instance is next modified or freed.
+#[repr(C)]
+#[derive(Debug)]
+pub struct FfiResource {
+    /// The type of the resource, one of the `ANCHORHASH_RESOURCE_*` constants.
+    pub kind: u32,
+    /// The integer id of the resource.
+    pub id: u64,
+    /// A pointer to the bytes of the resource.
+    pub bytes: *const u8,
+    /// The number of bytes of the resource.
+    pub len: usize,
+}
+
+impl From<&Resource> for FfiResource {
+    fn from(r: &Resource) -> Self {
+        match r {
+            Resource::Id(id) => Self {
+                kind: ANCHORHASH_RESOURCE_ID,
+                id: *id,
+                bytes: ptr::null(),
+                len: 0,
+            },
+            Resource::Bytes(b) => Self {
+                kind: ANCHORHASH_RESOURCE_BYTES,
+                id: 0,
+                bytes: b.as_ptr(),
+                len: b.len(),
+            },
+        }
+    }
+}
+
+fn status(err: Error) -> c_int {
+    match err {
+        Error::CapacityLimitReached => ANCHORHASH_CAPACITY_LIMIT_REACHED,
+        Error::ResourceNotFound => ANCHORHASH_RESOURCE_NOT_FOUND,
+        Error::InvalidSnapshot(_) => ANCHORHASH_INVALID_SNAPSHOT,
+        _ => ANCHORHASH_ERROR,
+    }
+}
+
+fn result(r: crate::Result<()>) -> c_int {
+    r.map_or_else(status, |()| ANCHORHASH_OK)
+}
+
+/// Borrow `len` bytes from `ptr`, which may be NULL only when `len` is zero.
+///
+/// # Safety
+///
+/// A non-NULL `ptr` must be valid for reads of `len` bytes for `'a`.
+unsafe fn bytes<'a>(ptr: *const u8, len: usize) -> Option<&'a [u8]> {
+    if ptr.is_null() {
+        return if len == 0 { Some(&[]) } else { None };
+    }
+    Some(slice::from_raw_parts(ptr, len))
+}
+
+/// Create an instance with room for `capacity` resources, hashing keys with
+/// `hasher` - one of the `ANCHORHASH_HASHER_*` constants.
+///
+/// Returns NULL if `hasher` is unknown.
+#[no_mangle]
+pub extern "C" fn anchorhash_new(capacity: u16, hasher: u32) -> *mut FfiAnchorHash {
+    let hasher = match HasherKind::from_raw(hasher) {
+        Some(v) => v,
+        None => return ptr::null_mut(),
+    };
+
+    let anchor = Builder::with_hasher(hasher).build_any_key(capacity);
+    Box::into_raw(Box::new(FfiAnchorHash { anchor }))
+}
+
+/// Free an instance. Passing NULL is a no-op.
+///
+/// # Safety
+///
+/// `anchor` must be NULL or a pointer returned by [`anchorhash_new()`] or
+/// [`anchorhash_snapshot_load()`] that has not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn anchorhash_free(anchor: *mut FfiAnchorHash) {
+    if !anchor.is_null() {
+        drop(Box::from_raw(anchor));
+    }
+}
+
+/// Add the integer resource `id`.
+///
+/// # Safety
+///
+/// `anchor` must be a valid instance.
+#[no_mangle]
+pub unsafe extern "C" fn anchorhash_add_id(anchor: *mut FfiAnchorHash, id: u64) -> c_int {
+    match anchor.as_mut() {
+        Some(a) => result(a.anchor.add_resource(Resource::Id(id))),
+        None => ANCHORHASH_INVALID_ARGUMENT,
+    }
+}
+
+/// Add the byte string resource of `len` bytes at `resource`, which is copied
+/// into the instance.
+///
+/// # Safety
+///
+/// `anchor` must be a valid instance, and `resource` must be valid for reads
+/// of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn anchorhash_add_bytes(
+    anchor: *mut FfiAnchorHash,
+    resource: *const u8,
+    len: usize,
+) -> c_int {
+    match (anchor.as_mut(), bytes(resource, len)) {
+        (Some(a), Some(r)) => result(a.anchor.add_resource(Resource::Bytes(r.into()))),
+        _ => ANCHORHASH_INVALID_ARGUMENT,
+    }
+}
+
+/// Remove the integer resource `id`.
+///
+/// # Safety
+///
+/// `anchor` must be a valid instance.
+#[no_mangle]
+pub unsafe extern "C" fn anchorhash_remove_id(anchor: *mut FfiAnchorHash, id: u64) -> c_int {
+    match anchor.as_mut() {
+        Some(a) => result(a.anchor.remove_resource(&Resource::Id(id))),
+        None => ANCHORHASH_INVALID_ARGUMENT,
+    }
+}
+
+/// Remove the byte string resource of `len` bytes at `resource`.
+///
+/// # Safety
+///
+/// `anchor` must be a valid instance, and `resource` must be valid for reads
+/// of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn anchorhash_remove_bytes(
+    anchor: *mut FfiAnchorHash,
+    resource: *const u8,
+    len: usize,
+) -> c_int {
+    match (anchor.as_mut(), bytes(resource, len)) {
+        (Some(a), Some(r)) => result(a.anchor.remove_resource(&Resource::Bytes(r.into()))),
+        _ => ANCHORHASH_INVALID_ARGUMENT,
+    }
+}
+
+/// Hash the `len` byte key at `key` as a Rust `&[u8]`, writing the hash to
+/// `out`.
+///
+/// # Safety
+///
+/// `anchor` must be a valid instance, `key` must be valid for reads of `len`
+/// bytes and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn anchorhash_hash_bytes(
+    anchor: *const FfiAnchorHash,
+    key: *const u8,
+    len: usize,
+    out: *mut u64,
+) -> c_int {
+    match (anchor.as_ref(), bytes(key, len), out.as_mut()) {
+        (Some(a), Some(key), Some(out)) => {
+            *out = a.anchor.hash_key(key);
+            ANCHORHASH_OK
+        }
+        _ => ANCHORHASH_INVALID_ARGUMENT,
+    }
+}
+
+/// Hash the `len` byte UTF-8 key at `key` as a Rust `&str`, writing the hash
+/// to `out`.
+///
+/// # Safety
+///
+/// `anchor` must be a valid instance, `key` must be valid for reads of `len`
+/// bytes and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn anchorhash_hash_str(
+    anchor: *const FfiAnchorHash,
+    key: *const u8,
+    len: usize,
+    out: *mut u64,
+) -> c_int {
+    let key = bytes(key, len).and_then(|v| std::str::from_utf8(v).ok());
+    match (anchor.as_ref(), key, out.as_mut()) {
+        (Some(a), Some(key), Some(out)) => {
+            *out = a.anchor.hash_key(key);
+            ANCHORHASH_OK
+        }
+        _ => ANCHORHASH_INVALID_ARGUMENT,
+    }
+}
+
+/// Map the key `hash`, as returned by [`anchorhash_hash_bytes()`] or
+/// [`anchorhash_hash_str()`], to a resource written to `out`.
+///
+/// Returns [`ANCHORHASH_NO_RESOURCES`] if the instance has no resources.
+///
+/// # Safety
+///
+/// `anchor` must be a valid instance and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn anchorhash_lookup_hash(
+    anchor: *const FfiAnchorHash,
+    hash: u64,
+    out: *mut FfiResource,
+) -> c_int {
+    match (anchor.as_ref(), out.as_mut()) {
+        (Some(a), Some(out)) => match a.anchor.lookup_hash(hash) {
+            Some(r) => {
+                *out = r.into();
+                ANCHORHASH_OK
+            }
+            None => ANCHORHASH_NO_RESOURCES,
+        },
+        _ => ANCHORHASH_INVALID_ARGUMENT,
+    }
+}
+
+/// Map the `len` byte key at `key`, hashed as a Rust `&[u8]`, to a resource
+/// written to `out`.
+///
+/// Returns [`ANCHORHASH_NO_RESOURCES`] if the instance has no resources.
+///
+/// # Safety
+///
+/// `anchor` must be a valid instance, `key` must be valid for reads of `len`
+/// bytes and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn anchorhash_lookup_bytes(
+    anchor: *const FfiAnchorHash,
+    key: *const u8,
+    len: usize,
+    out: *mut FfiResource,
+) -> c_int {
+    let mut hash = 0;
+    match anchorhash_hash_bytes(anchor, key, len, &mut hash) {
+        ANCHORHASH_OK => anchorhash_lookup_hash(anchor, hash, out),
+        v => v,
+    }
+}
+
+/// Map the `len` byte UTF-8 key at `key`, hashed as a Rust `&str`, to a
+/// resource written to `out`.
+///
+/// Returns [`ANCHORHASH_NO_RESOURCES`] if the instance has no resources.
+///
+/// # Safety
+///
+/// `anchor` must be a valid instance, `key` must be valid for reads of `len`
+/// bytes and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn anchorhash_lookup_str(
+    anchor: *const FfiAnchorHash,
+    key: *const u8,
+    len: usize,
+    out: *mut FfiResource,
+) -> c_int {
+    let mut hash = 0;
+    match anchorhash_hash_str(anchor, key, len, &mut hash) {
+        ANCHORHASH_OK => anchorhash_lookup_hash(anchor, hash, out),
+        v => v,
+    }
+}
+
+/// Encode the state of `anchor` as a [`Snapshot`], writing a pointer to the
+/// encoded bytes to `out` and their length to `out_len`.
+///
+/// Integer resources are encoded as 8 little-endian bytes, and byte string
+/// resources as-is. The buffer must be released with
+/// [`anchorhash_bytes_free()`].
+///
+/// # Safety
+///
+/// `anchor` must be a valid instance, and `out` and `out_len` must be valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn anchorhash_snapshot_save(
+    anchor: *const FfiAnchorHash,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    let (a, out, out_len) = match (anchor.as_ref(), out.as_mut(), out_len.as_mut()) {
+        (Some(a), Some(out), Some(out_len)) => (a, out, out_len),
+        _ => return ANCHORHASH_INVALID_ARGUMENT,
+    };
+
+    let buf = a
+        .anchor
+        .to_snapshot()
+        .encode(|r, buf| match r {
+            Resource::Id(id) => buf.extend_from_slice(&id.to_le_bytes()),
+            Resource::Bytes(b) => buf.extend_from_slice(b),
+        })
+        .into_boxed_slice();
+
+    *out_len = buf.len();
+    *out = Box::into_raw(buf).cast();
+    ANCHORHASH_OK
+}
+
+/// Free a buffer returned by [`anchorhash_snapshot_save()`]. Passing NULL is a
+/// no-op.
+///
+/// # Safety
+///
+/// `buf` and `len` must be a pointer and length returned by
+/// [`anchorhash_snapshot_save()`] that has not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn anchorhash_bytes_free(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buf, len)));
+    }
+}
+
+/// Restore an instance from the `len` byte encoded [`Snapshot`] at `buf`,
+/// hashing keys with `hasher`, and write a pointer to it to `out`.
+///
+/// Every resource in the snapshot is decoded as `resource_kind` - one of the
+/// `ANCHORHASH_RESOURCE_*` constants. Integer resources must be encoded as 8
+/// little-endian bytes, as [`anchorhash_snapshot_save()`] does, and a snapshot
+/// of an instance holding both kinds of resource restores only as byte
+/// strings.
+///
+/// Snapshots are interchangeable with those encoded by Rust programs using
+/// [`Snapshot::encode()`], provided the resources are encoded the same way.
+///
+/// # Safety
+///
+/// `buf` must be valid for reads of `len` bytes, and `out` must be valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn anchorhash_snapshot_load(
+    buf: *const u8,
+    len: usize,
+    hasher: u32,
+    resource_kind: u32,
+    out: *mut *mut FfiAnchorHash,
+) -> c_int {
+    let (buf, hasher, out) = match (bytes(buf, len), HasherKind::from_raw(hasher), out.as_mut()) {
+        (Some(buf), Some(hasher), Some(out)) => (buf, hasher, out),
+        _ => return ANCHORHASH_INVALID_ARGUMENT,
+    };
+
+    let snapshot = match resource_kind {
+        ANCHORHASH_RESOURCE_ID => Snapshot::decode(buf, |v| {
+            <[u8; 8]>::try_from(v)
+                .ok()
+                .map(|v| Resource::Id(u64::from_le_bytes(v)))
+        }),
+        ANCHORHASH_RESOURCE_BYTES => Snapshot::decode(buf, |v| Some(Resource::Bytes(v.into()))),
+        _ => return ANCHORHASH_INVALID_ARGUMENT,
+    };
+
+    match snapshot.and_then(|s| AnchorHash::from_snapshot(s, hasher)) {
+        Ok(anchor) => {
+            *out = Box::into_raw(Box::new(FfiAnchorHash { anchor }));
+            ANCHORHASH_OK
+        }
+        Err(e) => status(e),
+    }
+}
+
+/// Returns a static, NUL terminated description of the status code `status`.
+#[no_mangle]
+pub extern "C" fn anchorhash_status_str(status: c_int) -> *const c_char {
+    let s: &'static [u8] = match status {
+        ANCHORHASH_OK => b"ok\0",
+        ANCHORHASH_CAPACITY_LIMIT_REACHED => b"configured resource capacity reached\0",
+        ANCHORHASH_RESOURCE_NOT_FOUND => b"resource not found\0",
+        ANCHORHASH_NO_RESOURCES => b"no resources\0",
+        ANCHORHASH_INVALID_ARGUMENT => b"invalid argument\0",
+        ANCHORHASH_INVALID_SNAPSHOT => b"invalid snapshot\0",
+        ANCHORHASH_ERROR => b"error\0",
+        _ => b"unknown status\0",
+    };
+    s.as_ptr().cast()
+}
+
+#[cfg(test)]
+mod tests {
+    use fnv::FnvBuildHasher;
+
+    use super::*;
+
+    const HEADER: &str = include_str!("../include/anchorhash.h");
+
+    /// The golden vectors, generated by a 64-bit build with the `fastmod`
+    /// feature, hashing buckets with FNV.
+    #[cfg(all(
+        target_pointer_width = "64",
+        feature = "fastmod",
+        not(all(target_arch = "x86_64", target_feature = "sse4.2", feature = "simd"))
+    ))]
+    mod vectors {
+        use std::hash::BuildHasherDefault;
+
+        use super::*;
+
+        const VECTORS: &str = include_str!("../ffi/vectors.txt");
+
+        /// Apply the resource history the golden vectors are evaluated against.
+        fn build<B: BuildHasher>(hasher: B) -> AnchorHash<AnyKey, u64, B> {
+            let mut a = Builder::with_hasher(hasher).build_any_key(64);
+            for id in 1..=10 {
+                a.add_resource(id).unwrap();
+            }
+            a.remove_resource(&3).unwrap();
+            a.remove_resource(&7).unwrap();
+            a.add_resource(11).unwrap();
+            a
+        }
+
+        /// The same history as [`build()`], applied through the C API.
+        fn build_ffi(hasher: u32) -> *mut FfiAnchorHash {
+            let a = anchorhash_new(64, hasher);
+            assert!(!a.is_null());
+            unsafe {
+                for id in 1..=10 {
+                    assert_eq!(anchorhash_add_id(a, id), ANCHORHASH_OK);
+                }
+                assert_eq!(anchorhash_remove_id(a, 3), ANCHORHASH_OK);
+                assert_eq!(anchorhash_remove_id(a, 7), ANCHORHASH_OK);
+                assert_eq!(anchorhash_add_id(a, 11), ANCHORHASH_OK);
+            }
+            a
+        }
+
+        #[test]
+        fn test_vectors() {
+            let fnv = build(FnvBuildHasher::default());
+            let sip = build(BuildHasherDefault::<DefaultHasher>::default());
+            let fnv_ffi = build_ffi(ANCHORHASH_HASHER_FNV);
+            let sip_ffi = build_ffi(ANCHORHASH_HASHER_SIP);
+
+            let mut n = 0;
+            for line in VECTORS
+                .lines()
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            {
+                let fields = line.split_whitespace().collect::<Vec<_>>();
+                let (hasher, kind, key) = (fields[0], fields[1], fields[2]);
+                let hash = u64::from_str_radix(fields[3].trim_start_matches("0x"), 16).unwrap();
+                let id = fields[4].parse::<u64>().unwrap();
+
+                // The Rust implementation.
+                let (got_hash, got) = match (hasher, kind) {
+                    ("fnv", "bytes") => (fnv.hash_key(key.as_bytes()), fnv.lookup(key.as_bytes())),
+                    ("fnv", "str") => (fnv.hash_key(key), fnv.lookup(key)),
+                    ("sip", "bytes") => (sip.hash_key(key.as_bytes()), sip.lookup(key.as_bytes())),
+                    ("sip", "str") => (sip.hash_key(key), sip.lookup(key)),
+                    v => panic!("unknown vector {:?}", v),
+                };
+                assert_eq!(got_hash, hash, "{}", line);
+                assert_eq!(got, Some(&id), "{}", line);
+
+                // The C API.
+                let a = if hasher == "fnv" { fnv_ffi } else { sip_ffi };
+                let f = if kind == "str" {
+                    anchorhash_lookup_str
+                } else {
+                    anchorhash_lookup_bytes
+                };
+                let r = lookup(f, a, key.as_bytes()).unwrap();
+                assert_eq!((r.kind, r.id), (ANCHORHASH_RESOURCE_ID, id), "{}", line);
+
+                n += 1;
+            }
+            assert_eq!(n, 48);
+
+            unsafe {
+                anchorhash_free(fnv_ffi);
+                anchorhash_free(sip_ffi);
+            }
+        }
+    }
+
+    fn lookup(
+        f: unsafe extern "C" fn(*const FfiAnchorHash, *const u8, usize, *mut FfiResource) -> c_int,
+        a: *const FfiAnchorHash,
+        key: &[u8],
+    ) -> Result<FfiResource, c_int> {
+        let mut out = FfiResource::from(&Resource::Id(0));
+        match unsafe { f(a, key.as_ptr(), key.len(), &mut out) } {
+            ANCHORHASH_OK => Ok(out),
+            v => Err(v),
+        }
+    }
+
+    #[test]
+    fn test_snapshot_interop() {
+        // A snapshot encoded by a Rust program restores through the C API.
+        let mut anchor = Builder::with_hasher(FnvBuildHasher::default())
+            .with_resources(vec!["cache1".to_string(), "cache2".to_string()])
+            .build_any_key(8);
+        anchor.remove_resource(&"cache1".to_string()).unwrap();
+        anchor.add_resource("cache3".to_string()).unwrap();
+        let buf = anchor
+            .to_snapshot()
+            .encode(|r, buf| buf.extend_from_slice(r.as_bytes()));
+
+        let mut a = ptr::null_mut();
+        let status = unsafe {
+            anchorhash_snapshot_load(
+                buf.as_ptr(),
+                buf.len(),
+                ANCHORHASH_HASHER_FNV,
+                ANCHORHASH_RESOURCE_BYTES,
+                &mut a,
+            )
+        };
+        assert_eq!(status, ANCHORHASH_OK);
+
+        for key in ["user-A", "user-B", "user-C", "user-D"] {
+            let r = lookup(anchorhash_lookup_str, a, key.as_bytes()).unwrap();
+            let got = unsafe { slice::from_raw_parts(r.bytes, r.len) };
+            assert_eq!(Some(got), anchor.lookup(key).map(|v| v.as_bytes()));
+        }
+
+        // And the same state saved by the C API decodes in Rust.
+        let (mut out, mut out_len) = (ptr::null_mut(), 0);
+        assert_eq!(
+            unsafe { anchorhash_snapshot_save(a, &mut out, &mut out_len) },
+            ANCHORHASH_OK
+        );
+        let saved = unsafe { slice::from_raw_parts(out, out_len) };
+        assert_eq!(saved, &*buf);
+
+        unsafe {
+            anchorhash_bytes_free(out, out_len);
+            anchorhash_free(a);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(anchorhash_new(4, 42).is_null());
+
+        let a = anchorhash_new(1, ANCHORHASH_HASHER_SIP);
+        unsafe {
+            assert_eq!(
+                lookup(anchorhash_lookup_bytes, a, b"key").unwrap_err(),
+                ANCHORHASH_NO_RESOURCES
+            );
+            assert_eq!(anchorhash_add_id(a, 1), ANCHORHASH_OK);
+            assert_eq!(anchorhash_add_id(a, 2), ANCHORHASH_CAPACITY_LIMIT_REACHED);
+            assert_eq!(anchorhash_remove_id(a, 2), ANCHORHASH_RESOURCE_NOT_FOUND);
+            assert_eq!(
+                anchorhash_remove_bytes(a, ptr::null(), 1),
+                ANCHORHASH_INVALID_ARGUMENT
+            );
+            assert_eq!(
+                lookup(anchorhash_lookup_str, a, &[0xff, 0xfe]).unwrap_err(),
+                ANCHORHASH_INVALID_ARGUMENT
+            );
+            assert!(lookup(anchorhash_lookup_bytes, a, &[]).is_ok());
+
+            let mut restored = ptr::null_mut();
+            let buf = b"not a snapshot";
+            assert_eq!(
+                anchorhash_snapshot_load(
+                    buf.as_ptr(),
+                    buf.len(),
+                    ANCHORHASH_HASHER_SIP,
+                    ANCHORHASH_RESOURCE_ID,
+                    &mut restored
+                ),
+                ANCHORHASH_INVALID_SNAPSHOT
+            );
+            assert!(restored.is_null());
+
+            anchorhash_free(a);
+        }
+    }
+
+    #[test]
+    fn test_header_declares_api() {
+        let src = include_str!("ffi.rs").split("#[cfg(test)]").next().unwrap();
+
+        // Every exported function is declared in the header.
+        let mut n = 0;
+        for name in src
+            .split("#[no_mangle]")
+            .skip(1)
+            .filter_map(|s| s.split("fn ").nth(1)?.split('(').next())
+        {
+            assert!(
+                HEADER.contains(&format!(" {}(", name)) || HEADER.contains(&format!("*{}(", name)),
+                "{} is not declared in the header",
+                name
+            );
+            n += 1;
+        }
+        assert_eq!(n, 15);
+
+        // Every constant is defined in the header with the same value.
+        for (name, value) in [
+            ("ANCHORHASH_OK", ANCHORHASH_OK as u32),
+            (
+                "ANCHORHASH_CAPACITY_LIMIT_REACHED",
+                ANCHORHASH_CAPACITY_LIMIT_REACHED as u32,
+            ),
+            (
+                "ANCHORHASH_RESOURCE_NOT_FOUND",
+                ANCHORHASH_RESOURCE_NOT_FOUND as u32,
+            ),
+            ("ANCHORHASH_NO_RESOURCES", ANCHORHASH_NO_RESOURCES as u32),
+            (
+                "ANCHORHASH_INVALID_ARGUMENT",
+                ANCHORHASH_INVALID_ARGUMENT as u32,
+            ),
+            (
+                "ANCHORHASH_INVALID_SNAPSHOT",
+                ANCHORHASH_INVALID_SNAPSHOT as u32,
+            ),
+            ("ANCHORHASH_ERROR", ANCHORHASH_ERROR as u32),
+            ("ANCHORHASH_HASHER_FNV", ANCHORHASH_HASHER_FNV),
+            ("ANCHORHASH_HASHER_SIP", ANCHORHASH_HASHER_SIP),
+            ("ANCHORHASH_RESOURCE_ID", ANCHORHASH_RESOURCE_ID),
+            ("ANCHORHASH_RESOURCE_BYTES", ANCHORHASH_RESOURCE_BYTES),
+        ] {
+            let define = format!("#define {} {}\n", name, value);
+            assert!(HEADER.contains(&define), "missing {:?}", define);
+        }
+        assert_eq!(src.matches("\npub const ANCHORHASH_").count(), 11);
+    }
+}
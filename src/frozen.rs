@@ -0,0 +1,297 @@
+use core::{
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+};
+
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::anchor::{lookup_bucket_history, Anchor, Change, HistorySlices};
+
+/// The lookup state of a single bucket.
+#[derive(Debug, Clone, Copy)]
+#[repr(C, align(4))]
+struct Entry {
+    /// `A[b]` - zero for working buckets.
+    a: u16,
+    /// `K[b]` for a removed bucket, or the index of the resource assigned to a
+    /// working bucket.
+    next: u16,
+}
+
+/// The lookup table of a [`FrozenAnchorHash`].
+///
+/// This is kept separate from the generic `FrozenAnchorHash` so lookups are
+/// compiled once, within this crate, as [`Anchor::get_bucket()`] is.
+#[derive(Debug, Clone)]
+struct Table {
+    capacity: u16,
+    entries: Box<[Entry]>,
+
+    /// The history of the anchor, searched when a successor chain is long.
+    top: Box<[u16]>,
+    changes: Box<[Change]>,
+}
+
+impl Table {
+    /// Resolve the hash `k` to a working bucket, returning the index of its
+    /// resource.
+    fn get_resource(&self, k: u32) -> usize {
+        let b = lookup_bucket_history(
+            self.capacity,
+            k,
+            |b| self.entries[b].a,
+            |b| self.entries[b].next,
+            HistorySlices {
+                top: &self.top,
+                changes: &self.changes,
+            },
+            |_| {},
+        );
+        usize::from(self.entries[usize::from(b)].next)
+    }
+}
+
+/// An immutable snapshot of an [`AnchorHash`] with a layout optimised for
+/// lookups, created by [`AnchorHash::freeze()`].
+///
+/// A `FrozenAnchorHash` maps every key to the same resource as the instance it
+/// was frozen from, and is intended for read-heavy paths where the lookup table
+/// is rebuilt once per change to the resource set and then only read:
+///
+/// ```rust
+/// let mut anchor = anchorhash::Builder::default()
+///     .with_resources(vec!["cache1", "cache2", "cache3"])
+///     .build_any_key(20);
+/// anchor.remove_resource(&"cache2").unwrap();
+///
+/// let frozen = anchor.freeze();
+/// assert_eq!(frozen.lookup("user-A"), anchor.lookup("user-A"));
+/// ```
+///
+/// The lookup table differs from the live instance in two ways:
+///
+/// * The successor `K[b]` of a working bucket is always `b` itself and is
+///   never read by a lookup, so it is replaced by the index of the resource
+///   assigned to `b`. A key that maps to a working bucket is resolved with a
+///   single table read, followed by a read of the resource.
+/// * The resources are stored contiguously in bucket order, with no space
+///   reserved for removed buckets - at high removal ratios they occupy a
+///   fraction of the memory, and are more likely to be cached.
+///
+/// The successor paths of removed buckets are kept as they are, along with the
+/// history of the working set searched in place of long successor paths - the
+/// path from a bucket `h` records every bucket to have occupied position `h` of
+/// the working set, and where a lookup stops along it depends on the removed
+/// bucket the lookup arrived from, so it cannot be shortened without changing
+/// where keys map.
+///
+/// Lookups of a frozen instance do not emit the metrics or trace events of
+/// the live instance.
+///
+/// Freezing runs in linear time w.r.t the capacity.
+///
+/// [`AnchorHash`]: crate::AnchorHash
+/// [`AnchorHash::freeze()`]: crate::AnchorHash::freeze
+#[derive(Debug)]
+pub struct FrozenAnchorHash<K, R, B>
+where
+    B: BuildHasher,
+{
+    table: Table,
+    resources: Box<[R]>,
+    hasher: B,
+
+    _key_type: PhantomData<K>,
+}
+
+/// Implement `Clone` when both the resource type (`R`) and the hash builder
+/// (`B`) implement clone.
+///
+/// Note the key type (`K`) does NOT have to implement `Clone`.
+impl<K, R, B> Clone for FrozenAnchorHash<K, R, B>
+where
+    B: BuildHasher + Clone,
+    R: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            table: self.table.clone(),
+            resources: self.resources.clone(),
+            hasher: self.hasher.clone(),
+            _key_type: PhantomData,
+        }
+    }
+}
+
+impl<K, R, B> FrozenAnchorHash<K, R, B>
+where
+    B: BuildHasher,
+{
+    /// Build the lookup table of `anchor`, given the resource assigned to each
+    /// working bucket in bucket order.
+    pub(crate) fn new<'a, I>(anchor: &Anchor, assignments: I, hasher: B) -> Self
+    where
+        I: IntoIterator<Item = (u16, &'a R)>,
+        R: Clone + 'a,
+    {
+        let capacity = anchor.capacity();
+        let history = anchor.history();
+
+        let mut entries = anchor
+            .lookup_tables()
+            .iter()
+            .map(|s| Entry { a: s.a, next: s.k })
+            .collect::<Vec<_>>();
+
+        let mut resources = Vec::with_capacity(usize::from(anchor.working()));
+        for (b, r) in assignments {
+            entries[usize::from(b)].next = resources.len() as u16;
+            resources.push(r.clone());
+        }
+
+        Self {
+            table: Table {
+                capacity,
+                entries: entries.into_boxed_slice(),
+                top: history.top.into(),
+                changes: history.changes.into(),
+            },
+            resources: resources.into_boxed_slice(),
+            hasher,
+            _key_type: PhantomData,
+        }
+    }
+
+    /// Consistently hash `key` to a resource, as the [`AnchorHash`] this
+    /// instance was frozen from does.
+    ///
+    /// This method will return [`None`] when `self` contains no resources.
+    ///
+    /// [`AnchorHash`]: crate::AnchorHash
+    pub fn lookup<Q>(&self, key: &Q) -> Option<&R>
+    where
+        Q: Hash + ?Sized,
+    {
+        if self.resources.is_empty() {
+            return None;
+        }
+
+        let k = self.hasher.hash_one(key) as u32;
+        Some(&self.resources[self.table.get_resource(k)])
+    }
+
+    /// Returns the resources of this instance, in bucket order.
+    pub fn resources(&self) -> &[R] {
+        &self.resources
+    }
+
+    /// Returns the maximum number of resources of the instance this was frozen
+    /// from.
+    pub fn capacity(&self) -> u16 {
+        self.table.capacity
+    }
+}
+
+impl<K, R, B> FrozenAnchorHash<K, R, B>
+where
+    K: Hash,
+    B: BuildHasher,
+{
+    /// Consistently hash `key` to a resource.
+    ///
+    /// This method will return [`None`] when `self` contains no resources.
+    ///
+    /// This is equivalent to calling [`lookup()`] with a reference to `key`.
+    ///
+    /// [`lookup()`]: Self::lookup
+    pub fn get_resource(&self, key: K) -> Option<&R> {
+        self.lookup(&key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{AnchorHash, Builder};
+    use fnv::FnvBuildHasher;
+    use quickcheck_macros::quickcheck;
+
+    fn assert_same_mapping(a: &AnchorHash<u64, u16, FnvBuildHasher>) {
+        let frozen = a.freeze();
+        for key in 0..2_000 {
+            assert_eq!(frozen.get_resource(key), a.get_resource(key), "key {}", key);
+        }
+        assert_eq!(
+            frozen.resources(),
+            a.resources().copied().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_freeze_empty() {
+        let a: AnchorHash<u64, u16, _> = Builder::with_hasher(FnvBuildHasher::default()).build(0);
+        let frozen = a.freeze();
+        assert!(frozen.get_resource(42).is_none());
+        assert_eq!(frozen.capacity(), 0);
+
+        let mut a: AnchorHash<u64, u16, _> = Builder::with_hasher(FnvBuildHasher::default())
+            .with_resources(vec![1])
+            .build(10);
+        a.remove_resource(&1).unwrap();
+        assert!(a.freeze().get_resource(42).is_none());
+    }
+
+    #[test]
+    fn test_freeze_matches_churned() {
+        let mut a: AnchorHash<u64, u16, _> = Builder::with_hasher(FnvBuildHasher::default())
+            .with_resources(0..1_000)
+            .build(1_000);
+        assert_same_mapping(&a);
+
+        // Remove 90% of the resources in a scattered order.
+        for r in (0..1_000).map(|i| (i * 7) % 1_000).filter(|r| r % 10 != 0) {
+            a.remove_resource(&r).unwrap();
+        }
+        assert_same_mapping(&a);
+
+        // Re-add some, reusing the most recently removed buckets.
+        for r in 1_000..1_300 {
+            a.add_resource(r).unwrap();
+        }
+        assert_same_mapping(&a);
+    }
+
+    #[test]
+    fn test_freeze_long_chain() {
+        // Removing the bucket at position 0 repeatedly builds a successor
+        // chain of every bucket.
+        let mut a: AnchorHash<u64, u16, _> = Builder::with_hasher(FnvBuildHasher::default())
+            .with_resources(0..1_000)
+            .build(1_000);
+        a.remove_resource(&0).unwrap();
+        for r in (2..1_000).rev() {
+            a.remove_resource(&r).unwrap();
+        }
+        assert_same_mapping(&a);
+    }
+
+    #[quickcheck]
+    fn test_freeze_matches_live(capacity: u8, ops: Vec<(bool, u8)>) {
+        let capacity = u16::from(capacity);
+        let mut a: AnchorHash<u64, u16, _> = Builder::with_hasher(FnvBuildHasher::default())
+            .with_resources(0..capacity / 2)
+            .build(capacity);
+
+        let mut next = capacity;
+        for (add, r) in ops {
+            if add {
+                let _ = a.add_resource(next);
+                next += 1;
+            } else {
+                let _ = a.remove_resource(&u16::from(r));
+            }
+            assert_same_mapping(&a);
+        }
+    }
+}
@@ -1,17 +1,23 @@
-use std::iter::FusedIterator;
+use core::iter::FusedIterator;
 
-use hashbrown::hash_map::{Values, ValuesMut};
-
-/// An iterator yielding resources assigned to an [`AnchorHash`] instance in
-/// an arbitrary order.
+/// An iterator yielding resources assigned to an [`AnchorHash`] or
+/// [`ArrayAnchorHash`] instance in bucket order.
 ///
 /// [`AnchorHash`]: crate::AnchorHash  
+/// [`ArrayAnchorHash`]: crate::ArrayAnchorHash  
 #[derive(Debug, Clone)]
-pub struct ResourceIterator<'a, R>(Values<'a, u16, R>);
+pub struct ResourceIterator<'a, R> {
+    slots: core::slice::Iter<'a, Option<R>>,
+    remaining: usize,
+}
 
-impl<'a, R> From<Values<'a, u16, R>> for ResourceIterator<'a, R> {
-    fn from(v: Values<'a, u16, R>) -> Self {
-        Self(v)
+impl<'a, R> ResourceIterator<'a, R> {
+    /// Iterate over the `len` occupied slots in `slots`.
+    pub(crate) fn new(slots: &'a [Option<R>], len: usize) -> Self {
+        Self {
+            slots: slots.iter(),
+            remaining: len,
+        }
     }
 }
 
@@ -19,11 +25,13 @@ impl<'a, R> Iterator for ResourceIterator<'a, R> {
     type Item = &'a R;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.next()
+        let v = self.slots.by_ref().flatten().next()?;
+        self.remaining -= 1;
+        Some(v)
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        self.0.size_hint()
+        (self.remaining, Some(self.remaining))
     }
 }
 
@@ -31,15 +39,23 @@ impl<'a, R> ExactSizeIterator for ResourceIterator<'a, R> {}
 impl<'a, R> FusedIterator for ResourceIterator<'a, R> {}
 
 /// An iterator yielding mutable references to the resources assigned to an
-/// [`AnchorHash`] instance in an arbitrary order.
+/// [`AnchorHash`] or [`ArrayAnchorHash`] instance in bucket order.
 ///
 /// [`AnchorHash`]: crate::AnchorHash  
+/// [`ArrayAnchorHash`]: crate::ArrayAnchorHash  
 #[derive(Debug)]
-pub struct ResourceMutIterator<'a, R>(ValuesMut<'a, u16, R>);
+pub struct ResourceMutIterator<'a, R> {
+    slots: core::slice::IterMut<'a, Option<R>>,
+    remaining: usize,
+}
 
-impl<'a, R> From<ValuesMut<'a, u16, R>> for ResourceMutIterator<'a, R> {
-    fn from(v: ValuesMut<'a, u16, R>) -> Self {
-        Self(v)
+impl<'a, R> ResourceMutIterator<'a, R> {
+    /// Iterate over the `len` occupied slots in `slots`.
+    pub(crate) fn new(slots: &'a mut [Option<R>], len: usize) -> Self {
+        Self {
+            slots: slots.iter_mut(),
+            remaining: len,
+        }
     }
 }
 
@@ -47,17 +63,88 @@ impl<'a, R> Iterator for ResourceMutIterator<'a, R> {
     type Item = &'a mut R;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.next()
+        let v = self.slots.by_ref().flatten().next()?;
+        self.remaining -= 1;
+        Some(v)
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        self.0.size_hint()
+        (self.remaining, Some(self.remaining))
     }
 }
 
 impl<'a, R> ExactSizeIterator for ResourceMutIterator<'a, R> {}
 impl<'a, R> FusedIterator for ResourceMutIterator<'a, R> {}
 
+/// An iterator yielding resources assigned to an [`ArrayAnchorHash`] instance
+/// in bucket order - the same as [`ResourceIterator`].
+///
+/// [`ArrayAnchorHash`]: crate::ArrayAnchorHash  
+pub type SlotIterator<'a, R> = ResourceIterator<'a, R>;
+
+/// An iterator yielding mutable references to the resources assigned to an
+/// [`ArrayAnchorHash`] instance in bucket order - the same as
+/// [`ResourceMutIterator`].
+///
+/// [`ArrayAnchorHash`]: crate::ArrayAnchorHash  
+pub type SlotMutIterator<'a, R> = ResourceMutIterator<'a, R>;
+
+/// An iterator yielding resources assigned to a [`MementoHash`] instance in
+/// bucket order.
+///
+/// [`MementoHash`]: crate::MementoHash  
+#[derive(Debug, Clone)]
+pub struct MementoResourceIterator<'a, R>(ResourceIterator<'a, R>);
+
+impl<'a, R> From<ResourceIterator<'a, R>> for MementoResourceIterator<'a, R> {
+    fn from(v: ResourceIterator<'a, R>) -> Self {
+        Self(v)
+    }
+}
+
+impl<'a, R> Iterator for MementoResourceIterator<'a, R> {
+    type Item = &'a R;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a, R> ExactSizeIterator for MementoResourceIterator<'a, R> {}
+impl<'a, R> FusedIterator for MementoResourceIterator<'a, R> {}
+
+/// An iterator yielding mutable references to the resources assigned to a
+/// [`MementoHash`] instance in bucket order.
+///
+/// [`MementoHash`]: crate::MementoHash  
+#[derive(Debug)]
+pub struct MementoResourceMutIterator<'a, R>(ResourceMutIterator<'a, R>);
+
+impl<'a, R> From<ResourceMutIterator<'a, R>> for MementoResourceMutIterator<'a, R> {
+    fn from(v: ResourceMutIterator<'a, R>) -> Self {
+        Self(v)
+    }
+}
+
+impl<'a, R> Iterator for MementoResourceMutIterator<'a, R> {
+    type Item = &'a mut R;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a, R> ExactSizeIterator for MementoResourceMutIterator<'a, R> {}
+impl<'a, R> FusedIterator for MementoResourceMutIterator<'a, R> {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -71,7 +158,13 @@ mod tests {
             .collect::<crate::AnchorHash<usize, _, _>>();
 
         assert_fused_impl(a.resources());
-        assert_fused_impl(a.resources().0);
+
+        let m = crate::Builder::default()
+            .with_resources(vec!["A", "B", "C", "D"])
+            .build_unbounded::<usize>();
+
+        assert_fused_impl(m.resources());
+        assert_fused_impl(m.resources().0);
     }
 
     #[test]
@@ -81,7 +174,13 @@ mod tests {
             .collect::<crate::AnchorHash<usize, _, _>>();
 
         assert_fused_impl(a.resources_mut());
-        assert_fused_impl(a.resources_mut().0);
+
+        let mut m = crate::Builder::default()
+            .with_resources(vec!["A", "B", "C", "D"])
+            .build_unbounded::<usize>();
+
+        assert_fused_impl(m.resources_mut());
+        assert_fused_impl(m.resources_mut().0);
     }
 
     #[test]
@@ -0,0 +1,204 @@
+use core::hash::{BuildHasher, Hash};
+
+#[cfg(feature = "std")]
+use std::collections::hash_map::RandomState;
+
+use alloc::vec::Vec;
+
+use crate::{error::Result, ConsistentHash, Error};
+
+/// A [Jump consistent hash] mapping keys to resources, for comparison with
+/// [`AnchorHash`].
+///
+/// Jump consistent hash requires no lookup tables, computing the resource for
+/// a key in `O(ln n)` time for `n` resources, but resources are numbered
+/// sequentially and only the most recently added resource can be removed -
+/// removing any other resource returns [`Error::UnsupportedOperation`].
+///
+/// ```rust
+/// use anchorhash::JumpHash;
+///
+/// let mut jump = JumpHash::new().with_resources(vec!["cache1", "cache2"]);
+/// jump.add_resource("cache3");
+///
+/// let owner = jump.lookup("user-A").unwrap();
+/// ```
+///
+/// [Jump consistent hash]: https://arxiv.org/abs/1406.2294
+/// [`AnchorHash`]: crate::AnchorHash
+#[derive(Debug, Clone)]
+pub struct JumpHash<R, B> {
+    resources: Vec<R>,
+    hasher: B,
+}
+
+#[cfg(feature = "std")]
+impl<R> JumpHash<R, RandomState> {
+    /// Initialise an empty [`JumpHash`] using [`RandomState`] to hash keys.
+    ///
+    /// [`RandomState`]: std::collections::hash_map::RandomState
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::default())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R> Default for JumpHash<R, RandomState> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R, B> JumpHash<R, B>
+where
+    B: BuildHasher,
+{
+    /// Initialise an empty [`JumpHash`] using `hasher` to hash keys.
+    pub fn with_hasher(hasher: B) -> Self {
+        Self {
+            resources: Vec::new(),
+            hasher,
+        }
+    }
+
+    /// Append `resources`, in order.
+    pub fn with_resources(mut self, resources: impl IntoIterator<Item = R>) -> Self {
+        self.resources.extend(resources);
+        self
+    }
+
+    /// Consistently hash `key` to a resource.
+    ///
+    /// This method will return [`None`] when `self` contains no resources.
+    pub fn lookup<Q>(&self, key: &Q) -> Option<&R>
+    where
+        Q: Hash + ?Sized,
+    {
+        if self.resources.is_empty() {
+            return None;
+        }
+
+        let b = jump(self.hasher.hash_one(key), self.resources.len());
+        self.resources.get(b)
+    }
+
+    /// Add `resource`, numbered after all existing resources.
+    pub fn add_resource(&mut self, resource: R) {
+        self.resources.push(resource);
+    }
+
+    /// Remove `resource`, which MUST be the most recently added resource.
+    ///
+    /// Returns [`Error::UnsupportedOperation`] if `resource` is not the most
+    /// recently added resource, or [`Error::ResourceNotFound`] if it is not
+    /// present at all.
+    pub fn remove_resource(&mut self, resource: &R) -> Result<()>
+    where
+        R: PartialEq,
+    {
+        match self.resources.iter().rposition(|r| r == resource) {
+            Some(i) if i == self.resources.len() - 1 => {
+                self.resources.pop();
+                Ok(())
+            }
+            Some(_) => Err(Error::UnsupportedOperation(
+                "jump consistent hash can only remove the most recently added resource",
+            )),
+            None => Err(Error::ResourceNotFound),
+        }
+    }
+
+    /// Returns an iterator yielding references to the resources, in the order
+    /// they were added.
+    pub fn resources(&self) -> core::slice::Iter<'_, R> {
+        self.resources.iter()
+    }
+}
+
+impl<R, B> ConsistentHash<R> for JumpHash<R, B>
+where
+    R: PartialEq,
+    B: BuildHasher,
+{
+    fn lookup<Q>(&self, key: &Q) -> Option<&R>
+    where
+        Q: Hash + ?Sized,
+    {
+        JumpHash::lookup(self, key)
+    }
+
+    fn add(&mut self, resource: R) -> Result<()> {
+        self.add_resource(resource);
+        Ok(())
+    }
+
+    fn remove(&mut self, resource: &R) -> Result<()> {
+        self.remove_resource(resource)
+    }
+
+    fn len(&self) -> usize {
+        self.resources.len()
+    }
+}
+
+/// Map `key` to a bucket in the range `[0, buckets)`, as described in "A Fast,
+/// Minimal Memory, Consistent Hash Algorithm" (Lamping & Veach).
+///
+/// `buckets` MUST be non-zero.
+pub(crate) fn jump(mut key: u64, buckets: usize) -> usize {
+    let mut b: i64 = -1;
+    let mut j: i64 = 0;
+
+    while j < buckets as i64 {
+        b = j;
+        key = key.wrapping_mul(2_862_933_555_777_941_757).wrapping_add(1);
+        j = ((b + 1) as f64 * ((1_u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+
+    b as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jump() {
+        // A single bucket always maps to bucket 0.
+        assert!((0..1_000).all(|k| jump(k, 1) == 0));
+
+        // Keys only move to the new bucket as buckets are added.
+        for k in 0..1_000 {
+            for n in 1..100 {
+                let (old, new) = (jump(k, n), jump(k, n + 1));
+                assert!(old < n);
+                assert!(old == new || new == n);
+            }
+        }
+    }
+
+    #[test]
+    fn test_remove_resource() {
+        let mut j = JumpHash::new().with_resources(vec!["a", "b", "c"]);
+
+        assert_eq!(
+            j.remove_resource(&"a"),
+            Err(Error::UnsupportedOperation(
+                "jump consistent hash can only remove the most recently added resource"
+            ))
+        );
+        assert_eq!(j.remove_resource(&"z"), Err(Error::ResourceNotFound));
+
+        j.remove_resource(&"c").unwrap();
+        assert_eq!(j.resources().collect::<Vec<_>>(), [&"a", &"b"]);
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut j = JumpHash::default();
+        assert_eq!(j.lookup("key"), None);
+
+        j.add_resource(1);
+        assert_eq!(j.lookup("key"), Some(&1));
+    }
+}
@@ -30,6 +30,14 @@
 //! println!("user mapped to: {}", backend);
 //! ```
 //!
+//! When a capacity cannot be chosen up front, [`MementoHash`] provides the same
+//! behaviour with no capacity limit, built with
+//! [`Builder::build_unbounded()`].
+//!
+//! For lookup-heavy paths where the resource set changes rarely,
+//! [`AnchorHash::freeze()`] builds an immutable [`FrozenAnchorHash`] with a
+//! lookup table optimised for reads.
+//!
 //! # Features
 //!
 //! This crate has several compile-time features:
@@ -38,10 +46,54 @@
 //!   `x86_64` platforms with support for SSE4.2)
 //! * `fastmod`: efficient range mapping from [Fast Random Integer Generation in
 //!   an Interval] (enabled by default on 64-bit platforms)
+//! * `std`: use the standard library (enabled by default)
+//! * `mmap`: memory map archived lookup tables from disk with
+//!   [`MappedArchive`]
+//! * `metrics`: emit lookup and membership metrics through the [`metrics`]
+//!   facade
+//! * `tracing`: emit [`tracing`] spans and events when resources are added
+//!   and removed
+//! * `trace-lookups`: additionally trace every lookup at the `trace` level
+//! * `tower`: route requests across [`tower`] services with [`KeyedRouter`]
+//! * `ffi`: expose a C API for building a shared or static library, see
+//!   [`ffi`]
+//!
+//! # `no_std` Support
+//!
+//! Disabling the default `std` feature builds this crate with `no_std` and a
+//! dependency on `alloc`. In this mode there is no [`RandomState`] to fall back
+//! on, so an explicit [`BuildHasher`] must be provided when constructing an
+//! [`AnchorHash`] with [`Builder::with_hasher`]:
+//!
+//! ```rust
+//! use fnv::FnvBuildHasher;
+//!
+//! let mut anchor = anchorhash::Builder::with_hasher(FnvBuildHasher::default())
+//!     .with_resources(vec!["cache1", "cache2"])
+//!     .build(10);
+//! # anchor.get_resource("user-A").unwrap();
+//! ```
+//!
+//! For environments without a heap, [`ArrayAnchorHash`] provides a fixed
+//! capacity instance that stores all of its state inline.
 //!
 //! [AnchorHash: A Scalable Consistent Hash]: https://arxiv.org/abs/1812.09674  
 //! [`AnchorHash`]: crate::AnchorHash
 //! [Fast Random Integer Generation in an Interval]: https://arxiv.org/abs/1805.10941  
+//! [`RandomState`]: std::collections::hash_map::RandomState  
+//! [`BuildHasher`]: core::hash::BuildHasher  
+//! [`Builder::with_hasher`]: crate::Builder::with_hasher  
+//! [`ArrayAnchorHash`]: crate::ArrayAnchorHash  
+//! [`AnchorHash::freeze()`]: crate::AnchorHash::freeze  
+//! [`FrozenAnchorHash`]: crate::FrozenAnchorHash  
+//! [`MementoHash`]: crate::MementoHash  
+//! [`Builder::build_unbounded()`]: crate::Builder::build_unbounded  
+//! [`MappedArchive`]: https://docs.rs/anchorhash/latest/anchorhash/struct.MappedArchive.html  
+//! [`tower`]: https://docs.rs/tower  
+//! [`metrics`]: https://docs.rs/metrics  
+//! [`tracing`]: https://docs.rs/tracing  
+//! [`KeyedRouter`]: https://docs.rs/anchorhash/latest/anchorhash/struct.KeyedRouter.html  
+//! [`ffi`]: https://docs.rs/anchorhash/latest/anchorhash/ffi/index.html  
 
 //   Copyright 2021 Dominic Dwyer (dom@itsallbroken.com)
 //
@@ -57,6 +109,7 @@
 //   See the License for the specific language governing permissions and
 //   limitations under the License.
 
+#![cfg_attr(not(feature = "std"), no_std)]
 #![deny(rust_2018_idioms, missing_debug_implementations, unreachable_pub)]
 #![warn(
     missing_docs,
@@ -66,8 +119,13 @@
 )]
 #![allow(clippy::missing_docs_in_private_items)]
 
+extern crate alloc;
+
 mod anchor;
 
+mod error;
+pub use error::*;
+
 mod anchor_hash;
 pub use anchor_hash::*;
 
@@ -77,5 +135,68 @@ pub use range_map::*;
 mod fasthash;
 pub use fasthash::*;
 
+mod array_anchor_hash;
+pub use array_anchor_hash::*;
+
+mod frozen;
+pub use frozen::*;
+
+mod snapshot;
+pub use snapshot::*;
+
+mod archive;
+pub use archive::*;
+
+mod consistency;
+pub use consistency::{BucketDiff, BucketState};
+
+mod transition;
+pub use transition::*;
+
+mod path;
+pub use path::*;
+
 mod iter;
 pub use iter::*;
+
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+mod telemetry;
+
+pub mod analysis;
+
+mod consistent_hash;
+pub use consistent_hash::*;
+
+mod jump;
+pub use jump::*;
+
+mod memento;
+pub use memento::*;
+
+#[cfg(feature = "std")]
+mod rendezvous;
+#[cfg(feature = "std")]
+pub use rendezvous::*;
+
+#[cfg(feature = "std")]
+mod dispatcher;
+#[cfg(feature = "std")]
+pub use dispatcher::*;
+
+#[cfg(feature = "std")]
+mod anchor_map;
+#[cfg(feature = "std")]
+pub use anchor_map::*;
+
+#[cfg(feature = "std")]
+mod load_tracker;
+#[cfg(feature = "std")]
+pub use load_tracker::*;
+
+#[cfg(feature = "tower")]
+mod router;
+#[cfg(feature = "tower")]
+pub use router::*;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
@@ -0,0 +1,373 @@
+use std::{
+    hash::{BuildHasher, Hash},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::AnchorHash;
+
+/// A source of monotonically increasing time for a [`LoadTracker`].
+///
+/// Any `Fn() -> Duration` closure is a clock, allowing tests to control the
+/// passage of time.
+pub trait Clock {
+    /// Returns the time elapsed since an arbitrary, fixed origin.
+    fn now(&self) -> Duration;
+}
+
+impl<F> Clock for F
+where
+    F: Fn() -> Duration,
+{
+    fn now(&self) -> Duration {
+        self()
+    }
+}
+
+/// A [`Clock`] reading the time elapsed since it was created from
+/// [`Instant`].
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock(Instant);
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self(Instant::now())
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+#[derive(Debug)]
+struct Rates {
+    /// The decayed lookup rate of each bucket, in lookups per second.
+    rates: Vec<f64>,
+    /// The time the rates were last updated.
+    updated: Duration,
+}
+
+/// Tracks the rate of lookups served by each resource of an [`AnchorHash`],
+/// to find the resources receiving the most traffic.
+///
+/// A uniform spread of keys does not imply a uniform spread of traffic - a
+/// handful of hot keys can overload the resource they map to. Lookups made
+/// through [`LoadTracker::lookup()`] are counted per bucket with a relaxed
+/// atomic increment, cheap enough to leave enabled in production, and
+/// [`report()`] converts the counts into exponentially decayed rates:
+///
+/// ```rust
+/// use std::time::Duration;
+/// use anchorhash::LoadTracker;
+///
+/// let anchor = anchorhash::Builder::default()
+///     .with_resources(vec!["cache1", "cache2", "cache3"])
+///     .build_any_key(20);
+///
+/// // Rates decay by half every 10 seconds.
+/// let tracker = LoadTracker::new(&anchor, Duration::from_secs(10));
+///
+/// let backend = tracker.lookup(&anchor, "user-A").unwrap();
+///
+/// let report = tracker.report(&anchor);
+/// for (resource, rate) in report.top(3) {
+///     println!("{} serves {:.1} lookups/s", resource, rate);
+/// }
+/// ```
+///
+/// Counts are kept per bucket, and a bucket keeps its decaying rate when it
+/// is removed and later assigned to a new resource.
+///
+/// [`report()`]: Self::report
+#[derive(Debug)]
+pub struct LoadTracker<C = MonotonicClock> {
+    counts: Vec<AtomicU64>,
+    rates: Mutex<Rates>,
+    /// The time constant of the decay, derived from the half-life.
+    tau: f64,
+    clock: C,
+}
+
+impl LoadTracker<MonotonicClock> {
+    /// Initialise a tracker for the buckets of `anchor`, decaying rates by
+    /// half every `half_life`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `half_life` is zero.
+    pub fn new<K, R, B>(anchor: &AnchorHash<K, R, B>, half_life: Duration) -> Self
+    where
+        B: BuildHasher,
+    {
+        Self::with_clock(anchor, half_life, MonotonicClock::default())
+    }
+}
+
+impl<C> LoadTracker<C>
+where
+    C: Clock,
+{
+    /// Initialise a tracker for the buckets of `anchor`, decaying rates by
+    /// half every `half_life` as measured by `clock`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `half_life` is zero.
+    pub fn with_clock<K, R, B>(anchor: &AnchorHash<K, R, B>, half_life: Duration, clock: C) -> Self
+    where
+        B: BuildHasher,
+    {
+        assert!(!half_life.is_zero(), "half-life must be non-zero");
+
+        let capacity = usize::from(anchor.capacity());
+        Self {
+            counts: (0..capacity).map(|_| AtomicU64::new(0)).collect(),
+            rates: Mutex::new(Rates {
+                rates: vec![0.0; capacity],
+                updated: clock.now(),
+            }),
+            tau: half_life.as_secs_f64() / std::f64::consts::LN_2,
+            clock,
+        }
+    }
+
+    /// Consistently hash `key` to a resource of `anchor` as
+    /// [`AnchorHash::lookup()`] does, counting the lookup against the resource.
+    ///
+    /// `anchor` MUST be the instance (or a clone of the instance) this tracker
+    /// was created for.
+    pub fn lookup<'a, K, R, B, Q>(&self, anchor: &'a AnchorHash<K, R, B>, key: &Q) -> Option<&'a R>
+    where
+        B: BuildHasher,
+        Q: Hash + ?Sized,
+    {
+        let b = anchor.lookup_hash_bucket(anchor.hash_key(key))?;
+        self.counts[usize::from(b)].fetch_add(1, Ordering::Relaxed);
+        anchor.bucket_resource(b)
+    }
+
+    /// Fold the lookups counted since the last report into the decayed rates,
+    /// and return the rate of each resource of `anchor`.
+    pub fn report<'a, K, R, B>(&self, anchor: &'a AnchorHash<K, R, B>) -> LoadReport<'a, R>
+    where
+        B: BuildHasher,
+    {
+        let mut state = self.rates.lock().unwrap();
+
+        let now = self.clock.now();
+        let dt = now.saturating_sub(state.updated).as_secs_f64();
+        if dt > 0.0 {
+            // Blend the rate observed over the interval into the decayed rate,
+            // weighting the previous rate by how much it decayed.
+            let w = (-dt / self.tau).exp();
+            for (rate, count) in state.rates.iter_mut().zip(&self.counts) {
+                let observed = count.swap(0, Ordering::Relaxed) as f64 / dt;
+                *rate = *rate * w + observed * (1.0 - w);
+            }
+            state.updated = now;
+        }
+
+        let rates = anchor
+            .assignments()
+            .map(|(b, r)| (b, r, state.rates[usize::from(b)]))
+            .collect::<Vec<_>>();
+
+        LoadReport { rates }
+    }
+}
+
+/// The decayed lookup rate of each resource, returned by
+/// [`LoadTracker::report()`].
+#[derive(Debug, Clone)]
+pub struct LoadReport<'a, R> {
+    /// The bucket, resource and rate in lookups per second, ordered by bucket.
+    rates: Vec<(u16, &'a R, f64)>,
+}
+
+impl<'a, R> LoadReport<'a, R> {
+    /// Returns an iterator yielding each resource and its rate in lookups per
+    /// second.
+    ///
+    /// Resources are yielded in bucket order. A duplicate resource is yielded
+    /// once for each copy.
+    pub fn rates(&self) -> impl Iterator<Item = (&'a R, f64)> + '_ {
+        self.rates.iter().map(|(_b, r, rate)| (*r, *rate))
+    }
+
+    /// Returns up to `k` resources with the highest rates, hottest first.
+    pub fn top(&self, k: usize) -> Vec<(&'a R, f64)> {
+        let mut rates = self.rates().collect::<Vec<_>>();
+        rates.sort_by(|a, b| b.1.total_cmp(&a.1));
+        rates.truncate(k);
+        rates
+    }
+
+    /// Returns the ratio of the highest resource rate to the mean rate, where
+    /// `1.0` is a perfectly even spread of traffic.
+    ///
+    /// Returns [`None`] when there are no resources, or no lookups have been
+    /// observed.
+    pub fn imbalance(&self) -> Option<f64> {
+        let total = self.rates().map(|(_r, rate)| rate).sum::<f64>();
+        if total <= 0.0 {
+            return None;
+        }
+
+        let mean = total / self.rates.len() as f64;
+        self.rates()
+            .map(|(_r, rate)| rate)
+            .max_by(f64::total_cmp)
+            .map(|max| max / mean)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::hash_map::DefaultHasher,
+        hash::BuildHasherDefault,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use super::*;
+    use crate::Builder;
+
+    type Hasher = BuildHasherDefault<DefaultHasher>;
+
+    fn anchor() -> AnchorHash<u64, &'static str, Hasher> {
+        Builder::with_hasher(Hasher::default())
+            .with_resources(vec!["a", "b", "c", "d"])
+            .build(10)
+    }
+
+    /// A clock advanced by the test.
+    fn clock() -> (Arc<Mutex<Duration>>, impl Fn() -> Duration) {
+        let now = Arc::new(Mutex::new(Duration::ZERO));
+        let c = Arc::clone(&now);
+        (now, move || *c.lock().unwrap())
+    }
+
+    fn assert_close(got: f64, want: f64) {
+        assert!((got - want).abs() < 1e-9, "got {}, want {}", got, want);
+    }
+
+    #[test]
+    fn test_lookup() {
+        let a = anchor();
+        let t = LoadTracker::new(&a, Duration::from_secs(1));
+        for k in 0..100_u64 {
+            assert_eq!(t.lookup(&a, &k), a.lookup(&k));
+        }
+        assert_eq!(
+            t.counts
+                .iter()
+                .map(|c| c.load(Ordering::Relaxed))
+                .sum::<u64>(),
+            100
+        );
+    }
+
+    #[test]
+    fn test_decay() {
+        let a = anchor();
+        let (now, clock) = clock();
+        let t = LoadTracker::with_clock(&a, Duration::from_secs(10), clock);
+
+        // A key that maps to resource "a".
+        let key = (0..).find(|k| a.lookup(k) == Some(&"a")).unwrap();
+
+        // A constant 100 lookups per second converges on the observed rate.
+        for _ in 0..100 {
+            for _ in 0..100 {
+                t.lookup(&a, &key);
+            }
+            *now.lock().unwrap() += Duration::from_secs(1);
+            t.report(&a);
+        }
+        let rate = t.report(&a).rates().next().unwrap().1;
+        assert!((rate - 100.0).abs() < 0.1, "rate {}", rate);
+
+        // With no traffic, the rate halves every half-life.
+        *now.lock().unwrap() += Duration::from_secs(10);
+        let halved = t.report(&a).rates().next().unwrap().1;
+        assert_close(halved, rate / 2.0);
+
+        *now.lock().unwrap() += Duration::from_secs(20);
+        assert_close(t.report(&a).rates().next().unwrap().1, rate / 8.0);
+    }
+
+    #[test]
+    fn test_report_without_elapsed_time() {
+        let a = anchor();
+        let (_now, clock) = clock();
+        let t = LoadTracker::with_clock(&a, Duration::from_secs(1), clock);
+
+        t.lookup(&a, &42_u64);
+        let report = t.report(&a);
+        assert!(report.rates().all(|(_r, rate)| rate == 0.0));
+        assert_eq!(report.imbalance(), None);
+        assert_eq!(report.rates().count(), 4);
+    }
+
+    #[test]
+    fn test_hot_spot() {
+        let a = anchor();
+        let (now, clock) = clock();
+        let t = LoadTracker::with_clock(&a, Duration::from_secs(1), clock);
+
+        let key_for = |r| (0_u64..).find(|k| a.lookup(k) == Some(&r)).unwrap();
+        for (r, n) in [("a", 10), ("b", 10), ("c", 10), ("d", 50)] {
+            let key = key_for(r);
+            for _ in 0..n {
+                t.lookup(&a, &key).unwrap();
+            }
+        }
+
+        *now.lock().unwrap() += Duration::from_secs(1);
+        let report = t.report(&a);
+
+        let top = report.top(2);
+        assert_eq!(top.len(), 2);
+        assert_eq!(*top[0].0, "d");
+        assert!(top[0].1 > top[1].1 * 4.9);
+
+        // The hottest resource receives 50 of the 80 lookups, against a mean
+        // of 20.
+        assert_close(report.imbalance().unwrap(), 2.5);
+        assert_eq!(report.top(10).len(), 4);
+    }
+
+    #[test]
+    fn test_concurrent_lookups() {
+        let a = anchor();
+        let (now, clock) = clock();
+        let t = LoadTracker::with_clock(&a, Duration::from_secs(1), clock);
+
+        thread::scope(|s| {
+            for i in 0..4_u64 {
+                let (a, t) = (&a, &t);
+                s.spawn(move || {
+                    for k in 0..1_000 {
+                        t.lookup(a, &(i * 1_000 + k)).unwrap();
+                    }
+                });
+            }
+        });
+
+        *now.lock().unwrap() += Duration::from_secs(1);
+        let total = t.report(&a).rates().map(|(_r, rate)| rate).sum::<f64>();
+        assert_close(total, 4_000.0 * (1.0 - 0.5));
+    }
+
+    #[test]
+    #[should_panic(expected = "half-life")]
+    fn test_zero_half_life() {
+        LoadTracker::new(&anchor(), Duration::ZERO);
+    }
+}
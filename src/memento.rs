@@ -0,0 +1,560 @@
+use core::{
+    convert::TryFrom,
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+};
+
+use alloc::vec::Vec;
+
+use hashbrown::HashMap;
+
+use crate::{
+    error::Result, fasthash::mix64, jump::jump, range_map, ConsistentHash, DuplicatePolicy, Error,
+    MementoResourceIterator, MementoResourceMutIterator, ResourceIterator, ResourceMutIterator,
+};
+
+/// The bucket state of a [`MementoHash`], as described in [MementoHash: A
+/// Stateful, Minimal Memory, Best Performing Consistent Hash Algorithm].
+///
+/// Keys are first mapped by Jump consistent hash onto `n` buckets. Only the
+/// removed buckets are stored, each recording the number of working buckets
+/// after it was removed (the "replacer" - the bucket that takes its place),
+/// and the bucket removed before it.
+///
+/// [MementoHash: A Stateful, Minimal Memory, Best Performing Consistent Hash Algorithm]: https://arxiv.org/abs/2306.09783
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Memento {
+    /// The size of the bucket array - one more than the highest bucket.
+    n: u32,
+
+    /// The replacer and previously removed bucket of each removed bucket.
+    removed: HashMap<u32, (u32, u32)>,
+
+    /// The most recently removed bucket, or `n` if no bucket is removed.
+    last_removed: u32,
+}
+
+impl Memento {
+    fn new(working: u32) -> Self {
+        Self {
+            n: working,
+            removed: HashMap::new(),
+            last_removed: working,
+        }
+    }
+
+    /// Returns the number of working buckets.
+    fn working(&self) -> u32 {
+        self.n - self.removed.len() as u32
+    }
+
+    fn replacer(&self, b: u32) -> Option<u32> {
+        self.removed.get(&b).map(|&(r, _)| r)
+    }
+
+    /// Restore the most recently removed bucket, or extend the bucket array
+    /// if no bucket is removed, returning the new working bucket.
+    fn add_bucket(&mut self) -> Option<u32> {
+        let b = self.last_removed;
+        match self.removed.remove(&b) {
+            Some((_, prev)) => self.last_removed = prev,
+            None => {
+                self.n = self.n.checked_add(1)?;
+                self.last_removed = self.n;
+            }
+        }
+        Some(b)
+    }
+
+    fn remove_bucket(&mut self, b: u32) -> Result<()> {
+        if b >= self.n || self.removed.contains_key(&b) {
+            return Err(Error::InvalidState("bucket is not working"));
+        }
+
+        // Removing the last bucket of the array while no other buckets are
+        // removed shrinks the array instead of recording the removal.
+        if self.removed.is_empty() && b == self.n - 1 {
+            self.n = b;
+            self.last_removed = b;
+            return Ok(());
+        }
+
+        let replacer = self.working() - 1;
+        self.removed.insert(b, (replacer, self.last_removed));
+        self.last_removed = b;
+        Ok(())
+    }
+
+    /// Map the (already hashed) key `k` to a working bucket.
+    ///
+    /// There MUST be at least one working bucket.
+    fn get_bucket(&self, k: u64) -> u32 {
+        let mut b = jump(k, self.n as usize) as u32;
+
+        // While b is removed, rehash the key into the buckets that were working
+        // when b was removed.
+        let mut r = self.replacer(b);
+        while let Some(size) = r {
+            let h = mix64(k ^ mix64(u64::from(b)));
+            b = range_map((h >> 32) as u32, size);
+
+            // A bucket removed before b (when more buckets were working) was
+            // replaced by its replacer.
+            let mut next = self.replacer(b);
+            while let Some(replacer) = next.filter(|&v| v >= size) {
+                b = replacer;
+                next = self.replacer(b);
+            }
+            r = next;
+        }
+
+        b
+    }
+}
+
+/// A [`MementoHash`] consistently maps keys of type `K` to resources of type
+/// `R` like an [`AnchorHash`], but with no capacity limit.
+///
+/// MementoHash preserves the balance and minimal disruption of AnchorHash,
+/// mapping keys with Jump consistent hash and storing only the removed
+/// buckets, so memory use is proportional to the number of resources and
+/// removals rather than to a capacity chosen up front:
+///
+/// ```rust
+/// let mut memento = anchorhash::Builder::default()
+///     .with_resources(vec!["cache1", "cache2", "cache3"])
+///     .build_unbounded();
+///
+/// memento.remove_resource(&"cache2").unwrap();
+/// for i in 0..1_000 {
+///     memento.add_resource("another cache").unwrap();
+/// }
+///
+/// let backend = memento.get_resource("user-A").unwrap();
+/// ```
+///
+/// Lookups are fastest when few resources are removed and not re-added - each
+/// removal that has not been restored by an addition may add a hop to some
+/// lookups. As with AnchorHash, additions restore the most recently removed
+/// bucket first.
+///
+/// A `MementoHash` is constructed with [`Builder::build_unbounded()`], and
+/// otherwise behaves as an [`AnchorHash`], including its [`DuplicatePolicy`].
+///
+/// [`AnchorHash`]: crate::AnchorHash
+/// [`Builder::build_unbounded()`]: crate::Builder::build_unbounded
+#[derive(Debug)]
+pub struct MementoHash<K, R, B>
+where
+    B: BuildHasher,
+{
+    memento: Memento,
+    hasher: B,
+    // The resource assigned to each bucket of the bucket array, indexed by
+    // bucket, or None for a removed bucket.
+    resources: Vec<Option<R>>,
+    duplicates: DuplicatePolicy,
+
+    _key_type: PhantomData<K>,
+}
+
+/// Implement `Clone` when both the resource type (`R`) and the hash builder
+/// (`B`) implement clone.
+///
+/// Note the key type (`K`) does NOT have to implement `Clone`.
+impl<K, R, B> Clone for MementoHash<K, R, B>
+where
+    B: BuildHasher + Clone,
+    R: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            memento: self.memento.clone(),
+            hasher: self.hasher.clone(),
+            resources: self.resources.clone(),
+            duplicates: self.duplicates,
+            _key_type: PhantomData,
+        }
+    }
+}
+
+impl<K, R, B> MementoHash<K, R, B>
+where
+    B: BuildHasher,
+{
+    /// Initialise an instance assigning `resources` to buckets `0..n` in
+    /// order.
+    pub(crate) fn new(resources: Vec<R>, hasher: B, duplicates: DuplicatePolicy) -> Result<Self> {
+        let working = u32::try_from(resources.len()).map_err(|_| Error::CapacityLimitReached)?;
+
+        Ok(Self {
+            memento: Memento::new(working),
+            hasher,
+            resources: resources.into_iter().map(Some).collect(),
+            duplicates,
+            _key_type: PhantomData,
+        })
+    }
+
+    /// Consistently hash `key` to a configured resource.
+    ///
+    /// As with [`AnchorHash::lookup()`], any key type that implements [`Hash`]
+    /// can be used.
+    ///
+    /// This method will return [`None`] when `self` contains no resources.
+    ///
+    /// [`AnchorHash::lookup()`]: crate::AnchorHash::lookup
+    pub fn lookup<Q>(&self, key: &Q) -> Option<&R>
+    where
+        Q: Hash + ?Sized,
+    {
+        if self.memento.working() == 0 {
+            return None;
+        }
+
+        let b = self.memento.get_bucket(self.hasher.hash_one(key));
+        self.resources.get(b as usize)?.as_ref()
+    }
+
+    /// Returns an iterator yielding references to the configured resources in
+    /// bucket order.
+    pub fn resources(&self) -> MementoResourceIterator<'_, R> {
+        ResourceIterator::new(&self.resources, self.len()).into()
+    }
+
+    /// Returns an iterator yielding mutable references to the configured
+    /// resources in bucket order.
+    pub fn resources_mut(&mut self) -> MementoResourceMutIterator<'_, R> {
+        let len = self.len();
+        ResourceMutIterator::new(&mut self.resources, len).into()
+    }
+
+    /// Returns the number of configured resources.
+    fn len(&self) -> usize {
+        self.memento.working() as usize
+    }
+
+    /// Returns the [`DuplicatePolicy`] applied when adding resources.
+    pub fn duplicate_policy(&self) -> DuplicatePolicy {
+        self.duplicates
+    }
+}
+
+impl<K, R, B> MementoHash<K, R, B>
+where
+    K: Hash,
+    B: BuildHasher,
+{
+    /// Consistently hash `key` to a configured resource.
+    ///
+    /// This method will return [`None`] when `self` contains no resources.
+    ///
+    /// This is equivalent to calling [`lookup()`] with a reference to `key`.
+    ///
+    /// [`lookup()`]: Self::lookup
+    pub fn get_resource(&self, key: K) -> Option<&R> {
+        self.lookup(&key)
+    }
+}
+
+impl<K, R, B> MementoHash<K, R, B>
+where
+    B: BuildHasher,
+    R: PartialEq,
+{
+    /// Change the [`DuplicatePolicy`] applied when adding resources.
+    ///
+    /// The new policy only affects subsequent additions and removals - any
+    /// duplicates already present are retained.
+    pub fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicates = policy;
+    }
+
+    /// Add `resource`, allowing keys to map to it.
+    ///
+    /// A subset of keys from each resource is mapped to the new resource
+    /// ensuring minimal disruption with optimal load sharing.
+    ///
+    /// If `resource` is equal to an existing resource and the
+    /// [`DuplicatePolicy`] is [`Reject`], [`Error::DuplicateResource`] is
+    /// returned and the instance is left unchanged.
+    ///
+    /// [`Reject`]: DuplicatePolicy::Reject
+    pub fn add_resource(&mut self, resource: R) -> Result<()> {
+        if self.duplicates == DuplicatePolicy::Reject
+            && self.resources.iter().flatten().any(|r| *r == resource)
+        {
+            return Err(Error::DuplicateResource);
+        }
+
+        let b = self
+            .memento
+            .add_bucket()
+            .ok_or(Error::CapacityLimitReached)?;
+
+        // The bucket MUST NOT already be in use
+        match self.resources.get_mut(b as usize) {
+            Some(slot) => assert!(slot.replace(resource).is_none()),
+            None => {
+                debug_assert_eq!(b as usize, self.resources.len());
+                self.resources.push(Some(resource));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove the resource, preventing keys from mapping to `resource`.
+    ///
+    /// All the keys that previously mapped to `resource` are uniformly
+    /// distributed over the remaining resources, and all other keys continue
+    /// mapping to the same resource as before the removal.
+    ///
+    /// If `resource` was added more than once, the [`DuplicatePolicy`]
+    /// determines whether a single copy or all copies are removed.
+    ///
+    /// Removal runs in linear time w.r.t the number of resources.
+    pub fn remove_resource(&mut self, resource: &R) -> Result<()> {
+        // Collected in bucket order, so every instance removes the same
+        // buckets in the same order.
+        let mut buckets = (0..)
+            .zip(self.resources.iter())
+            .filter(|(_b, r)| r.as_ref() == Some(resource))
+            .map(|(b, _r)| b)
+            .collect::<Vec<u32>>();
+
+        if buckets.is_empty() {
+            return Err(Error::ResourceNotFound);
+        }
+
+        if self.duplicates != DuplicatePolicy::Weighted {
+            buckets.truncate(1);
+        }
+
+        for b in buckets {
+            self.memento.remove_bucket(b)?;
+            self.resources[b as usize] = None;
+        }
+
+        // The bucket array shrinks when its last bucket is removed.
+        self.resources.truncate(self.memento.n as usize);
+        Ok(())
+    }
+}
+
+impl<K, R, B> ConsistentHash<R> for MementoHash<K, R, B>
+where
+    R: PartialEq,
+    B: BuildHasher,
+{
+    fn lookup<Q>(&self, key: &Q) -> Option<&R>
+    where
+        Q: Hash + ?Sized,
+    {
+        MementoHash::lookup(self, key)
+    }
+
+    fn add(&mut self, resource: R) -> Result<()> {
+        self.add_resource(resource)
+    }
+
+    fn remove(&mut self, resource: &R) -> Result<()> {
+        self.remove_resource(resource)
+    }
+
+    fn len(&self) -> usize {
+        MementoHash::len(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::hash_map::DefaultHasher, hash::BuildHasherDefault};
+
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+    use crate::{analysis::tolerance, Builder};
+
+    type Hasher = BuildHasherDefault<DefaultHasher>;
+
+    fn memento(resources: impl IntoIterator<Item = u32>) -> MementoHash<u64, u32, Hasher> {
+        Builder::with_hasher(Hasher::default())
+            .with_resources(resources)
+            .build_unbounded()
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut m = memento(0..0);
+        assert_eq!(m.get_resource(42), None);
+
+        m.add_resource(1).unwrap();
+        assert_eq!(m.get_resource(42), Some(&1));
+
+        m.remove_resource(&1).unwrap();
+        assert_eq!(m.get_resource(42), None);
+        assert_eq!(m.remove_resource(&1), Err(Error::ResourceNotFound));
+    }
+
+    #[test]
+    fn test_memory_proportional_to_removals() {
+        let mut m = memento(0..1_000);
+        assert!(m.memento.removed.is_empty());
+
+        // Removing the last resource shrinks the bucket array.
+        m.remove_resource(&999).unwrap();
+        assert!(m.memento.removed.is_empty());
+        assert_eq!(m.memento.n, 999);
+
+        for r in [3, 500, 7] {
+            m.remove_resource(&r).unwrap();
+        }
+        assert_eq!(m.memento.removed.len(), 3);
+
+        // Additions restore the removed buckets in reverse order.
+        m.add_resource(42).unwrap();
+        assert_eq!(m.resources[7], Some(42));
+        assert_eq!(m.memento.removed.len(), 2);
+
+        m.add_resource(43).unwrap();
+        m.add_resource(44).unwrap();
+        assert!(m.memento.removed.is_empty());
+
+        // And then grow the array.
+        m.add_resource(45).unwrap();
+        assert_eq!(m.resources[999], Some(45));
+        assert_eq!(m.memento.n, 1_000);
+    }
+
+    /// Resources are yielded in bucket order, as by an [`AnchorHash`] with the
+    /// same history.
+    ///
+    /// [`AnchorHash`]: crate::AnchorHash
+    #[test]
+    fn test_resources_bucket_order() {
+        let mut m = memento(0..10);
+        let mut a = Builder::with_hasher(Hasher::default())
+            .with_resources(0..10)
+            .build::<u64>(20);
+
+        for r in [7, 2, 9] {
+            m.remove_resource(&r).unwrap();
+            a.remove_resource(&r).unwrap();
+        }
+        for r in [10, 11, 12, 13] {
+            m.add_resource(r).unwrap();
+            a.add_resource(r).unwrap();
+        }
+
+        let want = a.resources().collect::<Vec<_>>();
+        assert_eq!(want, [&0, &1, &11, &3, &4, &5, &6, &12, &8, &10, &13]);
+        assert_eq!(m.resources().collect::<Vec<_>>(), want);
+        assert_eq!(m.resources().len(), want.len());
+
+        for r in m.resources_mut() {
+            *r += 100;
+        }
+        assert_eq!(m.resources().next(), Some(&100));
+        assert_eq!(m.resources().last(), Some(&113));
+    }
+
+    #[test]
+    fn test_duplicate_policy() {
+        let err = Builder::default()
+            .with_duplicate_policy(DuplicatePolicy::Reject)
+            .with_resources(vec!["a", "b", "a"])
+            .try_build_unbounded::<u64>()
+            .unwrap_err();
+        assert_eq!(err, Error::DuplicateResource);
+
+        let mut m = Builder::default()
+            .with_duplicate_policy(DuplicatePolicy::Weighted)
+            .with_resources(vec!["a", "b", "a"])
+            .build_unbounded::<u64>();
+        m.remove_resource(&"a").unwrap();
+        assert_eq!(m.resources().collect::<Vec<_>>(), [&"b"]);
+
+        m.set_duplicate_policy(DuplicatePolicy::Reject);
+        assert_eq!(m.add_resource("b"), Err(Error::DuplicateResource));
+        assert_eq!(m.duplicate_policy(), DuplicatePolicy::Reject);
+    }
+
+    /// Keys remain balanced after removing resources from the middle of the
+    /// bucket array, which are recorded and replaced.
+    #[test]
+    fn test_balance_after_removals() {
+        const KEYS: u64 = 100_000;
+
+        let mut m = memento(0..100);
+        for r in (0..100).step_by(3) {
+            m.remove_resource(&r).unwrap();
+        }
+        assert_eq!(m.memento.removed.len(), 34);
+
+        let mut load = HashMap::new();
+        for k in 0..KEYS {
+            *load.entry(*m.lookup(&k).unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(load.len(), 66);
+
+        let mean = KEYS as f64 / 66.0;
+        let tolerance = tolerance(mean);
+        for (r, n) in load {
+            assert!(r % 3 != 0);
+            assert!(
+                (f64::from(n) - mean).abs() <= tolerance,
+                "resource {} has {} keys, want {} +/- {}",
+                r,
+                n,
+                mean,
+                tolerance
+            );
+        }
+    }
+
+    /// Every key maps to a working bucket, only the keys of a changed resource
+    /// move, and reversing a history restores the original mapping.
+    #[quickcheck]
+    fn test_history(initial: u8, removals: Vec<u16>, additions: u8) {
+        const KEYS: u64 = 1_000;
+
+        let initial = u32::from(initial) % 64 + 1;
+        let mut m = memento(0..initial);
+        let start = (0..KEYS).map(|k| m.lookup(&k).copied()).collect::<Vec<_>>();
+
+        let mut live = (0..initial).collect::<Vec<_>>();
+        let mut undo = Vec::new();
+        for r in removals.into_iter().take(16.min(live.len() - 1)) {
+            let r = live.remove(usize::from(r) % live.len());
+            let before = m.clone();
+            m.remove_resource(&r).unwrap();
+            undo.push(r);
+
+            for k in 0..KEYS {
+                let (old, new) = (before.lookup(&k), m.lookup(&k));
+                assert!(old == new || old == Some(&r), "key {} moved", k);
+                assert!(new.is_some_and(|v| live.contains(v)));
+            }
+        }
+
+        for next in initial..initial + u32::from(additions % 16) {
+            let before = m.clone();
+            m.add_resource(next).unwrap();
+            for k in 0..KEYS {
+                let (old, new) = (before.lookup(&k), m.lookup(&k));
+                assert!(old == new || new == Some(&next), "key {} moved", k);
+            }
+            m.remove_resource(&next).unwrap();
+        }
+
+        // Restoring the removed resources in reverse order restores the
+        // original mapping.
+        while let Some(r) = undo.pop() {
+            m.add_resource(r).unwrap();
+        }
+        let got = (0..KEYS).map(|k| m.lookup(&k).copied()).collect::<Vec<_>>();
+        assert_eq!(got, start);
+        assert!(m.memento.removed.is_empty());
+    }
+}
@@ -0,0 +1,55 @@
+use alloc::vec::Vec;
+
+/// The steps taken to resolve a key to a resource, as returned by
+/// [`AnchorHash::explain()`].
+///
+/// [`AnchorHash::explain()`]: crate::AnchorHash::explain
+#[derive(Debug, PartialEq, Eq)]
+pub struct LookupPath<'a, R> {
+    hash: u64,
+    buckets: Vec<u16>,
+    resource: &'a R,
+}
+
+impl<'a, R> LookupPath<'a, R> {
+    pub(crate) fn new(hash: u64, buckets: Vec<u16>, resource: &'a R) -> Self {
+        debug_assert!(!buckets.is_empty());
+        Self {
+            hash,
+            buckets,
+            resource,
+        }
+    }
+
+    /// Returns the hash of the key.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Returns every bucket examined while resolving the key, in order.
+    ///
+    /// The first bucket is the bucket the key initially maps to, and the last
+    /// is the working bucket the key resolved to. Each removed bucket is
+    /// followed by the bucket that replaced it, so a long successor chain
+    /// contributes a single bucket to the path.
+    pub fn buckets(&self) -> &[u16] {
+        &self.buckets
+    }
+
+    /// Returns the working bucket the key resolved to.
+    pub fn bucket(&self) -> u16 {
+        *self.buckets.last().expect("path always has a bucket")
+    }
+
+    /// Returns the number of additional buckets examined after the initial
+    /// bucket - 0 when the key initially maps to a working bucket. This is
+    /// not the length of the successor chains skipped along the way.
+    pub fn hops(&self) -> usize {
+        self.buckets.len() - 1
+    }
+
+    /// Returns the resource the key resolved to.
+    pub fn resource(&self) -> &'a R {
+        self.resource
+    }
+}
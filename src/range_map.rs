@@ -26,6 +26,29 @@ pub fn range_map(v: u32, max: u32) -> u32 {
     v % max
 }
 
+/// Returns the bucket hash `v` prepared for mapping into a range with
+/// [`range_map()`].
+///
+/// The multiply and shift mapping reads the high bits of `v`, which the bucket
+/// hash mixes well, so `v` is used as is.
+#[cfg(all(target_pointer_width = "64", feature = "fastmod"))]
+pub(crate) fn spread(v: u32) -> u32 {
+    v
+}
+
+/// Returns the bucket hash `v` prepared for mapping into a range with
+/// [`range_map()`].
+///
+/// The modulo mapping reads every bit of `v`, and the low bits of the bucket
+/// hash are close to a linear function of the key - mapping them directly
+/// correlates the bucket a key is rehashed to with the bucket it was first
+/// mapped to, unbalancing the load. `v` is mixed with the SplitMix64 finaliser
+/// first.
+#[cfg(not(all(target_pointer_width = "64", feature = "fastmod")))]
+pub(crate) fn spread(v: u32) -> u32 {
+    crate::fasthash::mix64(u64::from(v)) as u32
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
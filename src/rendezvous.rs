@@ -0,0 +1,225 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+};
+
+use crate::{error::Result, fasthash::mix64, ConsistentHash, Error};
+
+/// A weighted [rendezvous hash] (highest random weight) mapping keys to
+/// resources, for comparison with [`AnchorHash`].
+///
+/// Every resource is scored for each key, and the key maps to the resource
+/// with the highest score. Lookups run in `O(n)` time for `n` resources, but
+/// any resource can be added or removed, and each resource receives a share
+/// of the keys proportional to its weight:
+///
+/// ```rust
+/// use anchorhash::Rendezvous;
+///
+/// let mut r = Rendezvous::new();
+/// r.add_resource("cache1").unwrap();
+///
+/// // cache2 receives roughly twice as many keys as cache1.
+/// r.add_weighted_resource("cache2", 2.0).unwrap();
+///
+/// let owner = r.lookup("user-A").unwrap();
+/// ```
+///
+/// Resources are hashed with the configured hasher when added, so resources
+/// must implement [`Hash`], and equal resources have equal scores - when
+/// duplicates are present, the copy added first is selected.
+///
+/// [rendezvous hash]: https://en.wikipedia.org/wiki/Rendezvous_hashing
+/// [`AnchorHash`]: crate::AnchorHash
+#[derive(Debug, Clone)]
+pub struct Rendezvous<R, B> {
+    /// Each resource, the hash of the resource, and its weight.
+    resources: Vec<(R, u64, f64)>,
+    hasher: B,
+}
+
+impl<R> Rendezvous<R, RandomState> {
+    /// Initialise an empty [`Rendezvous`] using [`RandomState`] to hash keys.
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::default())
+    }
+}
+
+impl<R> Default for Rendezvous<R, RandomState> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R, B> Rendezvous<R, B>
+where
+    B: BuildHasher,
+{
+    /// Initialise an empty [`Rendezvous`] using `hasher` to hash keys and
+    /// resources.
+    pub fn with_hasher(hasher: B) -> Self {
+        Self {
+            resources: Vec::new(),
+            hasher,
+        }
+    }
+
+    /// Consistently hash `key` to a resource.
+    ///
+    /// This method will return [`None`] when `self` contains no resources.
+    pub fn lookup<Q>(&self, key: &Q) -> Option<&R>
+    where
+        Q: Hash + ?Sized,
+    {
+        let k = self.hasher.hash_one(key);
+
+        let mut best: Option<(&R, f64)> = None;
+        for (r, h, weight) in &self.resources {
+            let s = score(k, *h, *weight);
+            if best.is_none_or(|(_, b)| s > b) {
+                best = Some((r, s));
+            }
+        }
+
+        best.map(|(r, _)| r)
+    }
+
+    /// Add `resource` with a weight of `1.0`.
+    pub fn add_resource(&mut self, resource: R) -> Result<()>
+    where
+        R: Hash,
+    {
+        self.add_weighted_resource(resource, 1.0)
+    }
+
+    /// Add `resource` with the given `weight`, receiving a share of the keys
+    /// proportional to `weight` relative to the total weight.
+    ///
+    /// Returns [`Error::InvalidWeight`] if `weight` is not positive and
+    /// finite.
+    pub fn add_weighted_resource(&mut self, resource: R, weight: f64) -> Result<()>
+    where
+        R: Hash,
+    {
+        if !(weight > 0.0 && weight.is_finite()) {
+            return Err(Error::InvalidWeight);
+        }
+
+        let h = self.hasher.hash_one(&resource);
+        self.resources.push((resource, h, weight));
+        Ok(())
+    }
+
+    /// Remove the first copy of `resource`.
+    pub fn remove_resource(&mut self, resource: &R) -> Result<()>
+    where
+        R: PartialEq,
+    {
+        let i = self
+            .resources
+            .iter()
+            .position(|(r, _, _)| r == resource)
+            .ok_or(Error::ResourceNotFound)?;
+
+        // Retain the insertion order, so the copy added first continues to win
+        // ties between duplicates.
+        self.resources.remove(i);
+        Ok(())
+    }
+
+    /// Returns an iterator yielding each resource and its weight, in the order
+    /// they were added.
+    pub fn resources(&self) -> impl Iterator<Item = (&R, f64)> + '_ {
+        self.resources.iter().map(|(r, _, w)| (r, *w))
+    }
+}
+
+impl<R, B> ConsistentHash<R> for Rendezvous<R, B>
+where
+    R: Hash + PartialEq,
+    B: BuildHasher,
+{
+    fn lookup<Q>(&self, key: &Q) -> Option<&R>
+    where
+        Q: Hash + ?Sized,
+    {
+        Rendezvous::lookup(self, key)
+    }
+
+    fn add(&mut self, resource: R) -> Result<()> {
+        self.add_resource(resource)
+    }
+
+    fn remove(&mut self, resource: &R) -> Result<()> {
+        self.remove_resource(resource)
+    }
+
+    fn len(&self) -> usize {
+        self.resources.len()
+    }
+}
+
+/// Score the resource with hash `resource` for the key with hash `key`.
+///
+/// The hashes are mixed into a uniform value `u` in `(0, 1)`, scored as
+/// `-weight / ln(u)` so that each resource wins with a probability
+/// proportional to its weight (Schindelhauer & Schomaker).
+fn score(key: u64, resource: u64, weight: f64) -> f64 {
+    let h = mix64(key ^ mix64(resource));
+
+    // The top 53 bits form the mantissa, offset to exclude 0.0 and 1.0.
+    let u = ((h >> 11) as f64 + 0.5) / (1_u64 << 53) as f64;
+    -weight / u.ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::hash_map::DefaultHasher, hash::BuildHasherDefault};
+
+    use super::*;
+
+    #[test]
+    fn test_weights() {
+        let mut r = Rendezvous::with_hasher(BuildHasherDefault::<DefaultHasher>::default());
+        r.add_weighted_resource("a", 1.0).unwrap();
+        r.add_weighted_resource("b", 3.0).unwrap();
+
+        let n = 100_000;
+        let got = (0..n).filter(|k| r.lookup(k) == Some(&"b")).count();
+        let want = n * 3 / 4;
+        assert!(
+            (got as i64 - want as i64).abs() < (n / 100) as i64,
+            "got {} keys, want ~{}",
+            got,
+            want
+        );
+    }
+
+    #[test]
+    fn test_invalid_weight() {
+        let mut r = Rendezvous::new();
+        for w in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(r.add_weighted_resource("a", w), Err(Error::InvalidWeight));
+        }
+        assert_eq!(r.resources().count(), 0);
+    }
+
+    #[test]
+    fn test_remove_any_resource() {
+        let mut r = Rendezvous::new();
+        for v in ["a", "b", "c"] {
+            r.add_resource(v).unwrap();
+        }
+
+        let before = (0..1_000_u64)
+            .map(|k| *r.lookup(&k).unwrap())
+            .collect::<Vec<_>>();
+        r.remove_resource(&"a").unwrap();
+        assert_eq!(r.remove_resource(&"a"), Err(Error::ResourceNotFound));
+
+        for (k, old) in (0..1_000_u64).zip(before) {
+            let new = *r.lookup(&k).unwrap();
+            assert!(old == "a" || old == new);
+        }
+    }
+}
@@ -0,0 +1,433 @@
+use std::{
+    collections::hash_map::RandomState,
+    future::Future,
+    hash::{BuildHasher, Hash},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use hashbrown::{HashMap, HashSet};
+use pin_project_lite::pin_project;
+use thiserror::Error;
+use tower_service::Service;
+
+use crate::{AnchorHash, AnyKey, Builder, Result};
+
+/// An opaque identifier of a service added to a [`KeyedRouter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ServiceId(u64);
+
+/// Errors returned by a [`KeyedRouter`].
+#[derive(Debug, Error)]
+pub enum RouterError<E> {
+    /// The router has no services to route the request to.
+    #[error("no service available")]
+    NoService,
+
+    /// The selected inner service returned an error.
+    #[error("service error: {0}")]
+    Service(#[source] E),
+}
+
+/// A [`tower`] [`Service`] routing each request to one of a set of inner
+/// services using an [`AnchorHash`].
+///
+/// A key is extracted from each request by a user provided function, and the
+/// request is dispatched to the inner service the key maps to - requests with
+/// the same key are routed to the same service for as long as it remains in
+/// the router, and adding or removing a service moves the minimum number of
+/// keys:
+///
+/// ```rust
+/// use anchorhash::KeyedRouter;
+/// use tower_service::Service;
+/// # use std::{convert::Infallible, future::{ready, Ready}, task::{Context, Poll}};
+/// #
+/// # #[derive(Clone)]
+/// # struct Shard(&'static str);
+/// # impl Service<(String, u32)> for Shard {
+/// #     type Response = &'static str;
+/// #     type Error = Infallible;
+/// #     type Future = Ready<Result<Self::Response, Self::Error>>;
+/// #     fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+/// #         Poll::Ready(Ok(()))
+/// #     }
+/// #     fn call(&mut self, _req: (String, u32)) -> Self::Future {
+/// #         ready(Ok(self.0))
+/// #     }
+/// # }
+///
+/// // Route (user, payload) requests by the user.
+/// let mut router = KeyedRouter::new(16, |req: &(String, u32)| req.0.clone());
+///
+/// let shard_a = router.add_service(Shard("shard-a")).unwrap();
+/// let shard_b = router.add_service(Shard("shard-b")).unwrap();
+///
+/// # futures::executor::block_on(async {
+/// # futures::future::poll_fn(|cx| router.poll_ready(cx)).await.unwrap();
+/// let shard = router.call(("user-A".to_string(), 42)).await.unwrap();
+/// # });
+/// ```
+///
+/// # Readiness
+///
+/// The destination of a request is not known until [`call()`], so
+/// [`poll_ready()`] drives every inner service to readiness, and is ready
+/// only once all the inner services are ready. Each call consumes the
+/// readiness of the selected service only, so `poll_ready()` returns
+/// immediately for subsequent requests until the selected service is polled
+/// again.
+///
+/// Services added after `poll_ready()` returned are not ready -
+/// `poll_ready()` MUST be called again before the next request.
+///
+/// # Consistency
+///
+/// For multiple routers to map keys identically they must be built with the
+/// same [`BuildHasher`] (see [`with_hasher()`]) and apply the same sequence of
+/// additions and removals - [`new()`] uses a randomly seeded hasher.
+///
+/// [`tower`]: https://docs.rs/tower
+/// [`call()`]: Service::call
+/// [`poll_ready()`]: Service::poll_ready
+/// [`with_hasher()`]: Self::with_hasher
+/// [`new()`]: Self::new
+#[derive(Debug)]
+pub struct KeyedRouter<S, F, B = RandomState>
+where
+    B: BuildHasher,
+{
+    anchor: AnchorHash<AnyKey, ServiceId, B>,
+    services: HashMap<ServiceId, S>,
+    ready: HashSet<ServiceId>,
+    next_id: u64,
+    extract: F,
+}
+
+impl<S, F> KeyedRouter<S, F, RandomState> {
+    /// Initialise an empty router with support for up to `capacity` services,
+    /// routing requests by the key returned by `extract`.
+    pub fn new(capacity: u16, extract: F) -> Self {
+        Self::with_hasher(capacity, RandomState::default(), extract)
+    }
+}
+
+impl<S, F, B> KeyedRouter<S, F, B>
+where
+    B: BuildHasher,
+{
+    /// Initialise an empty router with support for up to `capacity` services,
+    /// using `hasher` to hash the key returned by `extract`.
+    pub fn with_hasher(capacity: u16, hasher: B, extract: F) -> Self {
+        Self {
+            anchor: Builder::with_hasher(hasher).build_any_key(capacity),
+            services: HashMap::new(),
+            ready: HashSet::new(),
+            next_id: 0,
+            extract,
+        }
+    }
+
+    /// Add `service` to the router, returning the [`ServiceId`] used to remove
+    /// it.
+    ///
+    /// Returns [`Error::CapacityLimitReached`] if the router holds as many
+    /// services as its capacity.
+    ///
+    /// [`Error::CapacityLimitReached`]: crate::Error::CapacityLimitReached
+    pub fn add_service(&mut self, service: S) -> Result<ServiceId> {
+        let id = ServiceId(self.next_id);
+        self.anchor.add_resource(id)?;
+
+        self.next_id += 1;
+        self.services.insert(id, service);
+        Ok(id)
+    }
+
+    /// Remove the service identified by `id`, returning it.
+    ///
+    /// Requests with keys that mapped to the removed service are routed to the
+    /// remaining services, while all other keys are unaffected.
+    pub fn remove_service(&mut self, id: ServiceId) -> Result<S> {
+        self.anchor.remove_resource(&id)?;
+        self.ready.remove(&id);
+        Ok(self
+            .services
+            .remove(&id)
+            .expect("routed service must be present"))
+    }
+
+    /// Returns a reference to the service identified by `id`.
+    pub fn service(&self, id: ServiceId) -> Option<&S> {
+        self.services.get(&id)
+    }
+
+    /// Returns the number of services in the router.
+    pub fn len(&self) -> usize {
+        self.services.len()
+    }
+
+    /// Returns true if the router has no services.
+    pub fn is_empty(&self) -> bool {
+        self.services.is_empty()
+    }
+
+    /// Returns the [`ServiceId`] a request with `key` is routed to.
+    pub fn route<Q>(&self, key: &Q) -> Option<ServiceId>
+    where
+        Q: Hash + ?Sized,
+    {
+        self.anchor.lookup(key).copied()
+    }
+}
+
+impl<S, F, B, Req, K> Service<Req> for KeyedRouter<S, F, B>
+where
+    S: Service<Req>,
+    F: Fn(&Req) -> K,
+    K: Hash,
+    B: BuildHasher,
+{
+    type Response = S::Response;
+    type Error = RouterError<S::Error>;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut pending = false;
+        for (id, svc) in self.services.iter_mut() {
+            if self.ready.contains(id) {
+                continue;
+            }
+            match svc.poll_ready(cx) {
+                Poll::Ready(Ok(())) => {
+                    self.ready.insert(*id);
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(RouterError::Service(e))),
+                Poll::Pending => pending = true,
+            }
+        }
+
+        if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        let id = match self.route(&(self.extract)(&req)) {
+            Some(v) => v,
+            None => return ResponseFuture { inner: None },
+        };
+
+        assert!(
+            self.ready.remove(&id),
+            "poll_ready must return ready before calling the router"
+        );
+
+        let svc = self
+            .services
+            .get_mut(&id)
+            .expect("routed service must be present");
+
+        ResponseFuture {
+            inner: Some(svc.call(req)),
+        }
+    }
+}
+
+pin_project! {
+    /// The response future of a [`KeyedRouter`].
+    #[derive(Debug)]
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: Option<F>,
+    }
+}
+
+impl<F, T, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = core::result::Result<T, E>>,
+{
+    type Output = core::result::Result<T, RouterError<E>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().inner.as_pin_mut() {
+            Some(f) => f.poll(cx).map_err(RouterError::Service),
+            None => Poll::Ready(Err(RouterError::NoService)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        collections::hash_map::DefaultHasher,
+        future::{ready, Ready},
+        hash::BuildHasherDefault,
+        rc::Rc,
+    };
+
+    use futures::{executor::block_on, future::poll_fn, task::noop_waker_ref};
+
+    use super::*;
+    use crate::Error;
+
+    /// A mock service that responds with its name, with readiness and
+    /// failures controlled by the test.
+    #[derive(Debug, Clone)]
+    struct Mock {
+        name: &'static str,
+        ready: Rc<Cell<bool>>,
+        fail: bool,
+        calls: Rc<Cell<usize>>,
+    }
+
+    impl Mock {
+        fn new(name: &'static str) -> Self {
+            Self {
+                name,
+                ready: Rc::new(Cell::new(true)),
+                fail: false,
+                calls: Rc::new(Cell::new(0)),
+            }
+        }
+    }
+
+    impl Service<u64> for Mock {
+        type Response = &'static str;
+        type Error = &'static str;
+        type Future = Ready<core::result::Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<core::result::Result<(), Self::Error>> {
+            match (self.ready.get(), self.fail) {
+                (_, true) => Poll::Ready(Err("broken")),
+                (true, _) => Poll::Ready(Ok(())),
+                (false, _) => Poll::Pending,
+            }
+        }
+
+        fn call(&mut self, _req: u64) -> Self::Future {
+            self.calls.set(self.calls.get() + 1);
+            ready(Ok(self.name))
+        }
+    }
+
+    type Router = KeyedRouter<Mock, fn(&u64) -> u64, BuildHasherDefault<DefaultHasher>>;
+
+    fn router() -> Router {
+        KeyedRouter::with_hasher(10, BuildHasherDefault::default(), |v: &u64| *v)
+    }
+
+    fn poll_ready(r: &mut Router) -> Poll<core::result::Result<(), RouterError<&'static str>>> {
+        r.poll_ready(&mut Context::from_waker(noop_waker_ref()))
+    }
+
+    fn send(
+        r: &mut Router,
+        req: u64,
+    ) -> core::result::Result<&'static str, RouterError<&'static str>> {
+        block_on(async {
+            poll_fn(|cx| r.poll_ready(cx)).await?;
+            r.call(req).await
+        })
+    }
+
+    #[test]
+    fn test_route() {
+        let mut r = router();
+        let a = r.add_service(Mock::new("a")).unwrap();
+        let b = r.add_service(Mock::new("b")).unwrap();
+        assert_eq!(r.len(), 2);
+
+        let mut seen = HashSet::new();
+        for k in 0..100 {
+            let got = send(&mut r, k).unwrap();
+            let want = r.service(r.route(&k).unwrap()).unwrap().name;
+            assert_eq!(got, want);
+            seen.insert(got);
+        }
+        assert_eq!(seen.len(), 2);
+
+        // Removing a service only moves its keys.
+        let before = (0..100).map(|k| r.route(&k).unwrap()).collect::<Vec<_>>();
+        assert_eq!(r.remove_service(b).unwrap().name, "b");
+        for (k, old) in (0..100).zip(before) {
+            assert!(old == b || r.route(&k) == Some(old));
+            assert_eq!(send(&mut r, k).unwrap(), "a");
+        }
+        assert_eq!(r.service(a).unwrap().name, "a");
+
+        assert_eq!(r.remove_service(b).unwrap_err(), Error::ResourceNotFound);
+    }
+
+    #[test]
+    fn test_no_service() {
+        let mut r = router();
+        assert!(r.is_empty());
+        assert!(matches!(send(&mut r, 42), Err(RouterError::NoService)));
+    }
+
+    #[test]
+    fn test_poll_ready_all_services() {
+        let mut r = router();
+        let a = Mock::new("a");
+        let b = Mock::new("b");
+        b.ready.set(false);
+        r.add_service(a.clone()).unwrap();
+        r.add_service(b.clone()).unwrap();
+
+        // Any service not ready holds the router not ready.
+        assert!(poll_ready(&mut r).is_pending());
+        b.ready.set(true);
+        assert!(matches!(poll_ready(&mut r), Poll::Ready(Ok(()))));
+
+        // A call consumes only the readiness of the selected service.
+        let k = (0..).find(|k| r.service(r.route(k).unwrap()).unwrap().name == "b");
+        let k = k.unwrap();
+        b.ready.set(false);
+        assert_eq!(block_on(r.call(k)).unwrap(), "b");
+        assert!(poll_ready(&mut r).is_pending());
+        assert_eq!(b.calls.get(), 1);
+        assert_eq!(a.calls.get(), 0);
+    }
+
+    #[test]
+    fn test_poll_ready_error() {
+        let mut r = router();
+        let mut m = Mock::new("a");
+        m.fail = true;
+        r.add_service(m).unwrap();
+
+        assert!(matches!(
+            poll_ready(&mut r),
+            Poll::Ready(Err(RouterError::Service("broken")))
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "poll_ready must return ready")]
+    fn test_call_without_poll_ready() {
+        let mut r = router();
+        r.add_service(Mock::new("a")).unwrap();
+        drop(r.call(42));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut r = KeyedRouter::with_hasher(
+            1,
+            BuildHasherDefault::<DefaultHasher>::default(),
+            |v: &u64| *v,
+        );
+        r.add_service(Mock::new("a")).unwrap();
+        assert_eq!(
+            r.add_service(Mock::new("b")).unwrap_err(),
+            Error::CapacityLimitReached
+        );
+    }
+}
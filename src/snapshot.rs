@@ -0,0 +1,341 @@
+use core::convert::TryFrom;
+
+use alloc::vec::Vec;
+
+use crate::{
+    anchor::{Anchor, Storage},
+    error::Result,
+    Error,
+};
+
+/// The magic bytes prefixing an encoded [`Snapshot`].
+const MAGIC: &[u8; 4] = b"ANCH";
+
+/// The version of the encoded [`Snapshot`] format.
+const VERSION: u8 = 1;
+
+/// A point-in-time copy of the state of an [`AnchorHash`] or
+/// [`ArrayAnchorHash`] instance.
+///
+/// A snapshot captures everything needed to rebuild an instance that maps keys
+/// to exactly the same resources as the original - the capacity, the order in
+/// which buckets were removed, and the resource assigned to each working
+/// bucket. The format is shared by both the heap allocated [`AnchorHash`] and
+/// the fixed capacity [`ArrayAnchorHash`], so state can be moved freely
+/// between the two:
+///
+/// ```rust
+/// use anchorhash::{AnchorHash, ArrayAnchorHash, Builder};
+/// use fnv::FnvBuildHasher;
+///
+/// let mut anchor: AnchorHash<&str, _, _> = Builder::with_hasher(FnvBuildHasher::default())
+///     .with_resources(vec!["cache1", "cache2", "cache3"])
+///     .build(8);
+/// anchor.remove_resource(&"cache2").unwrap();
+///
+/// // Load the state into an inline, fixed capacity instance.
+/// let fixed: ArrayAnchorHash<&str, _, _, 8> =
+///     ArrayAnchorHash::from_snapshot(anchor.to_snapshot(), FnvBuildHasher::default()).unwrap();
+///
+/// assert_eq!(anchor.get_resource("user-A"), fixed.get_resource("user-A"));
+/// ```
+///
+/// A snapshot can be encoded to, and decoded from, a compact binary format
+/// with [`encode()`] and [`decode()`], using caller provided functions to
+/// (de)serialise the resources.
+///
+/// The hash function is NOT part of the snapshot - the restored instance MUST
+/// be configured with the same hasher as the original to map keys to the same
+/// resources.
+///
+/// [`AnchorHash`]: crate::AnchorHash
+/// [`ArrayAnchorHash`]: crate::ArrayAnchorHash
+/// [`encode()`]: Self::encode
+/// [`decode()`]: Self::decode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot<R> {
+    capacity: u16,
+    removed: Vec<u16>,
+    resources: Vec<(u16, R)>,
+}
+
+impl<R> Snapshot<R> {
+    /// Capture the state of `anchor` and the bucket to resource assignment in
+    /// `resources`.
+    pub(crate) fn new<S: Storage>(anchor: &Anchor<S>, resources: Vec<(u16, R)>) -> Self {
+        Self {
+            capacity: anchor.capacity(),
+            removed: anchor.removed().to_vec(),
+            resources,
+        }
+    }
+
+    /// Rebuild the [`Anchor`] described by this snapshot, returning it and the
+    /// bucket to resource assignment.
+    ///
+    /// Returns [`Error::InvalidSnapshot`] if the snapshot does not describe a
+    /// consistent state, such as a resource assigned to a removed bucket.
+    pub(crate) fn into_parts<S: Storage>(self) -> Result<Parts<S, R>> {
+        let anchor = Anchor::<S>::from_removed(self.capacity, &self.removed)
+            .ok_or(Error::InvalidSnapshot("invalid removed bucket"))?;
+
+        // Every working bucket must be assigned exactly one resource.
+        if self.resources.len() != anchor.working() as usize {
+            return Err(Error::InvalidSnapshot(
+                "resource count does not match working buckets",
+            ));
+        }
+        let mut seen = alloc::vec![false; self.capacity as usize];
+        for &(b, _) in &self.resources {
+            if b >= self.capacity || !anchor.is_working(b) {
+                return Err(Error::InvalidSnapshot(
+                    "resource assigned to removed bucket",
+                ));
+            }
+            if core::mem::replace(&mut seen[b as usize], true) {
+                return Err(Error::InvalidSnapshot("bucket assigned multiple resources"));
+            }
+        }
+
+        Ok((anchor, self.resources))
+    }
+
+    /// Returns the capacity of the instance captured in this snapshot.
+    pub fn capacity(&self) -> u16 {
+        self.capacity
+    }
+
+    /// Returns an iterator yielding references to the resources in this
+    /// snapshot, in bucket order.
+    pub fn resources(&self) -> impl Iterator<Item = &R> + '_ {
+        self.resources.iter().map(|(_b, r)| r)
+    }
+
+    /// Returns an iterator yielding each resource in this snapshot and the
+    /// bucket it is assigned to, in bucket order.
+    pub fn assignments(&self) -> impl Iterator<Item = (u16, &R)> + '_ {
+        self.resources.iter().map(|(b, r)| (*b, r))
+    }
+
+    /// Convert the resources in this snapshot using `f`, preserving the bucket
+    /// each resource is assigned to.
+    pub fn map<T, F>(self, mut f: F) -> Snapshot<T>
+    where
+        F: FnMut(R) -> T,
+    {
+        Snapshot {
+            capacity: self.capacity,
+            removed: self.removed,
+            resources: self.resources.into_iter().map(|(b, r)| (b, f(r))).collect(),
+        }
+    }
+
+    /// Encode this snapshot into a binary representation, calling
+    /// `encode_resource` to append the serialised form of each resource to the
+    /// provided buffer.
+    ///
+    /// All integers are encoded little-endian, and each encoded resource is
+    /// prefixed with its length.
+    pub fn encode<F>(&self, mut encode_resource: F) -> Vec<u8>
+    where
+        F: FnMut(&R, &mut Vec<u8>),
+    {
+        let mut buf = Vec::with_capacity(
+            MAGIC.len() + 1 + 2 * (3 + self.removed.len()) + 6 * self.resources.len(),
+        );
+
+        buf.extend_from_slice(MAGIC);
+        buf.push(VERSION);
+        buf.extend_from_slice(&self.capacity.to_le_bytes());
+
+        // The removed lengths are bounded by the capacity, which is a u16.
+        buf.extend_from_slice(&(self.removed.len() as u16).to_le_bytes());
+        for b in &self.removed {
+            buf.extend_from_slice(&b.to_le_bytes());
+        }
+
+        buf.extend_from_slice(&(self.resources.len() as u16).to_le_bytes());
+        for (b, r) in &self.resources {
+            buf.extend_from_slice(&b.to_le_bytes());
+
+            // Reserve space for the length prefix, and populate it once the
+            // resource has been encoded.
+            let len_at = buf.len();
+            buf.extend_from_slice(&[0; 4]);
+            encode_resource(r, &mut buf);
+
+            let len = u32::try_from(buf.len() - len_at - 4).expect("resource too large");
+            buf[len_at..len_at + 4].copy_from_slice(&len.to_le_bytes());
+        }
+
+        buf
+    }
+
+    /// Decode a snapshot previously produced by [`encode()`], calling
+    /// `decode_resource` to deserialise each resource.
+    ///
+    /// Returns [`Error::InvalidSnapshot`] if `buf` is not a valid encoded
+    /// snapshot, or `decode_resource` returns `None`.
+    ///
+    /// [`encode()`]: Self::encode
+    pub fn decode<F>(buf: &[u8], mut decode_resource: F) -> Result<Self>
+    where
+        F: FnMut(&[u8]) -> Option<R>,
+    {
+        let mut r = Reader(buf);
+
+        if r.take(MAGIC.len())? != MAGIC {
+            return Err(Error::InvalidSnapshot("bad magic"));
+        }
+        if r.take(1)?[0] != VERSION {
+            return Err(Error::InvalidSnapshot("unsupported version"));
+        }
+
+        let capacity = r.u16()?;
+
+        let n = r.u16()?;
+        let removed = (0..n).map(|_| r.u16()).collect::<Result<Vec<_>, _>>()?;
+
+        let n = r.u16()?;
+        let resources = (0..n)
+            .map(|_| {
+                let b = r.u16()?;
+                let len = r.u32()?;
+                let resource = decode_resource(r.take(len as usize)?)
+                    .ok_or(Error::InvalidSnapshot("invalid resource"))?;
+                Ok((b, resource))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if !r.0.is_empty() {
+            return Err(Error::InvalidSnapshot("trailing data"));
+        }
+
+        Ok(Self {
+            capacity,
+            removed,
+            resources,
+        })
+    }
+}
+
+/// An [`Anchor`] restored from a [`Snapshot`], and its bucket to resource
+/// assignment.
+type Parts<S, R> = (Anchor<S>, Vec<(u16, R)>);
+
+/// A cursor over an encoded [`Snapshot`].
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(Error::InvalidSnapshot("truncated"));
+        }
+        let (v, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(v)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let v = self.take(2)?;
+        Ok(u16::from_le_bytes([v[0], v[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let v = self.take(4)?;
+        Ok(u32::from_le_bytes([v[0], v[1], v[2], v[3]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AnchorHash, Builder};
+
+    fn encode_str(r: &String, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(r.as_bytes());
+    }
+
+    fn decode_str(buf: &[u8]) -> Option<String> {
+        String::from_utf8(buf.to_vec()).ok()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut a: AnchorHash<usize, _, _> = Builder::default()
+            .with_resources((0..10).map(|v| v.to_string()))
+            .build(20);
+        a.remove_resource(&"3".to_string()).unwrap();
+        a.remove_resource(&"7".to_string()).unwrap();
+
+        let snap = a.to_snapshot();
+        let buf = snap.encode(encode_str);
+        let got = Snapshot::decode(&buf, decode_str).unwrap();
+
+        assert_eq!(got, snap);
+        assert_eq!(got.capacity(), 20);
+        assert_eq!(got.resources().count(), 8);
+        assert!(got.assignments().all(|(b, r)| *r == b.to_string()));
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        let a: AnchorHash<usize, _, _> = Builder::default()
+            .with_resources(vec!["A".to_string(), "B".to_string()])
+            .build(4);
+        let buf = a.to_snapshot().encode(encode_str);
+
+        for n in 0..buf.len() {
+            Snapshot::decode(&buf[..n], decode_str).expect_err("truncated snapshot");
+        }
+
+        let mut bad = buf.clone();
+        bad[0] = b'X';
+        assert_eq!(
+            Snapshot::decode(&bad, decode_str),
+            Err(Error::InvalidSnapshot("bad magic"))
+        );
+
+        let mut bad = buf;
+        bad.push(42);
+        assert_eq!(
+            Snapshot::decode(&bad, decode_str),
+            Err(Error::InvalidSnapshot("trailing data"))
+        );
+    }
+
+    #[test]
+    fn test_into_parts_inconsistent() {
+        // A resource assigned to a removed bucket.
+        let snap = Snapshot {
+            capacity: 4,
+            removed: vec![3, 2],
+            resources: vec![(0, "A"), (3, "B")],
+        };
+        assert!(snap.into_parts::<Vec<u16>>().is_err());
+
+        // A bucket removed twice.
+        let snap = Snapshot {
+            capacity: 4,
+            removed: vec![3, 3],
+            resources: vec![(0, "A"), (1, "B")],
+        };
+        assert!(snap.into_parts::<Vec<u16>>().is_err());
+
+        // A working bucket with no resource.
+        let snap = Snapshot {
+            capacity: 4,
+            removed: vec![3],
+            resources: vec![(0, "A"), (1, "B")],
+        };
+        assert!(snap.into_parts::<Vec<u16>>().is_err());
+
+        // Two resources assigned to the same bucket.
+        let snap = Snapshot {
+            capacity: 4,
+            removed: vec![3, 2],
+            resources: vec![(0, "A"), (0, "B")],
+        };
+        assert!(snap.into_parts::<Vec<u16>>().is_err());
+    }
+}
@@ -0,0 +1,532 @@
+//! Metrics emitted by an [`AnchorHash`] through the [`metrics`] facade, and
+//! helpers for its [`tracing`] instrumentation.
+//!
+//! [`AnchorHash`]: crate::AnchorHash
+//! [`metrics`]: https://docs.rs/metrics
+//! [`tracing`]: https://docs.rs/tracing
+
+#[cfg(feature = "metrics")]
+use std::borrow::Cow;
+
+#[cfg(feature = "tracing")]
+use core::fmt;
+
+#[cfg(feature = "metrics")]
+use metrics::{counter, gauge, histogram, Counter, Gauge, Histogram};
+
+/// The [`Debug`] implementation of a resource type `R`, captured where `R` is
+/// known to implement it.
+///
+/// [`Debug`]: core::fmt::Debug
+#[cfg(feature = "tracing")]
+pub(crate) type DebugFn<R> = fn(&R, &mut fmt::Formatter<'_>) -> fmt::Result;
+
+/// Renders a resource with a captured [`DebugFn`].
+#[cfg(feature = "tracing")]
+pub(crate) struct TracedResource<'a, R> {
+    resource: &'a R,
+    debug: DebugFn<R>,
+}
+
+#[cfg(feature = "tracing")]
+impl<'a, R> TracedResource<'a, R> {
+    pub(crate) fn new(resource: &'a R, debug: DebugFn<R>) -> Self {
+        Self { resource, debug }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<R> fmt::Debug for TracedResource<'_, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (self.debug)(self.resource, f)
+    }
+}
+
+/// The label value used when no label has been configured.
+#[cfg(feature = "metrics")]
+pub(crate) const DEFAULT_LABEL: &str = "default";
+
+/// The label key identifying the instance that emitted a metric.
+#[cfg(feature = "metrics")]
+const LABEL_KEY: &str = "instance";
+
+/// Handles to the metrics of a single [`AnchorHash`] instance, registered
+/// once so recording a value does not look up the metric in the recorder.
+///
+/// [`AnchorHash`]: crate::AnchorHash
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone)]
+pub(crate) struct Metrics {
+    label: Cow<'static, str>,
+
+    lookups: Counter,
+    lookups_empty: Counter,
+    removed_buckets_walked: Histogram,
+    resources_added: Counter,
+    resources_removed: Counter,
+    capacity_limit_reached: Counter,
+    resource_not_found: Counter,
+    working: Gauge,
+    capacity: Gauge,
+}
+
+#[cfg(feature = "metrics")]
+impl Metrics {
+    /// Register the metrics of an instance labelled `label` with the current
+    /// recorder.
+    pub(crate) fn new(label: Cow<'static, str>) -> Self {
+        let l = || (LABEL_KEY, label.to_string());
+        Self {
+            lookups: counter!("anchorhash_lookups_total", &[l()]),
+            lookups_empty: counter!("anchorhash_lookups_empty_total", &[l()]),
+            removed_buckets_walked: histogram!("anchorhash_lookup_removed_buckets", &[l()]),
+            resources_added: counter!("anchorhash_resources_added_total", &[l()]),
+            resources_removed: counter!("anchorhash_resources_removed_total", &[l()]),
+            capacity_limit_reached: counter!(
+                "anchorhash_errors_total",
+                &[l(), ("error", "capacity_limit_reached".to_string())]
+            ),
+            resource_not_found: counter!(
+                "anchorhash_errors_total",
+                &[l(), ("error", "resource_not_found".to_string())]
+            ),
+            working: gauge!("anchorhash_working_buckets", &[l()]),
+            capacity: gauge!("anchorhash_capacity", &[l()]),
+            label,
+        }
+    }
+
+    /// Returns the label of the instance.
+    pub(crate) fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Record the current working set size and capacity.
+    pub(crate) fn set_size(&self, working: u16, capacity: u16) {
+        self.working.set(working);
+        self.capacity.set(capacity);
+    }
+
+    /// Record a lookup of an instance with no working buckets.
+    pub(crate) fn lookup_empty(&self) {
+        self.lookups.increment(1);
+        self.lookups_empty.increment(1);
+    }
+
+    /// Record a lookup that visited `removed` buckets before reaching a
+    /// working bucket.
+    ///
+    /// The visited buckets are those of [`LookupPath::buckets()`] - a long
+    /// successor chain is searched through the history of the working set
+    /// and counts once, not once per removed bucket in the chain.
+    ///
+    /// [`LookupPath::buckets()`]: crate::LookupPath::buckets
+    pub(crate) fn lookup(&self, removed: usize) {
+        self.lookups.increment(1);
+        self.removed_buckets_walked.record(removed as f64);
+    }
+
+    /// Record the addition of a resource.
+    pub(crate) fn added(&self) {
+        self.resources_added.increment(1);
+    }
+
+    /// Record the removal of `n` copies of a resource.
+    pub(crate) fn removed(&self, n: usize) {
+        self.resources_removed.increment(n as u64);
+    }
+
+    /// Record a failure to add a resource to a full instance.
+    pub(crate) fn capacity_limit_reached(&self) {
+        self.capacity_limit_reached.increment(1);
+    }
+
+    /// Record a failure to remove a resource that does not exist.
+    pub(crate) fn resource_not_found(&self) {
+        self.resource_not_found.increment(1);
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod metrics_tests {
+    use std::{
+        collections::hash_map::DefaultHasher,
+        hash::BuildHasherDefault,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use hashbrown::HashMap;
+    use metrics::{
+        with_local_recorder, HistogramFn, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+    };
+
+    use crate::{AnchorHash, Builder, Error};
+
+    /// Records every histogram value.
+    #[derive(Debug, Default)]
+    struct Values(Mutex<Vec<f64>>);
+
+    impl HistogramFn for Values {
+        fn record(&self, value: f64) {
+            self.0.lock().unwrap().push(value);
+        }
+    }
+
+    /// A recorder holding the value of every metric, keyed by the rendered
+    /// metric name and labels.
+    #[derive(Debug, Default)]
+    struct TestRecorder {
+        counters: Mutex<HashMap<String, Arc<AtomicU64>>>,
+        gauges: Mutex<HashMap<String, Arc<AtomicU64>>>,
+        histograms: Mutex<HashMap<String, Arc<Values>>>,
+    }
+
+    fn render(key: &Key) -> String {
+        let labels = key
+            .labels()
+            .map(|l| format!("{}={}", l.key(), l.value()))
+            .collect::<Vec<_>>();
+        format!("{}{{{}}}", key.name(), labels.join(","))
+    }
+
+    impl TestRecorder {
+        fn counter(&self, name: &str) -> u64 {
+            self.counters.lock().unwrap()[name].load(Ordering::Relaxed)
+        }
+
+        fn gauge(&self, name: &str) -> f64 {
+            f64::from_bits(self.gauges.lock().unwrap()[name].load(Ordering::Relaxed))
+        }
+
+        fn histogram(&self, name: &str) -> Vec<f64> {
+            self.histograms.lock().unwrap()[name]
+                .0
+                .lock()
+                .unwrap()
+                .clone()
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> metrics::Counter {
+            let mut c = self.counters.lock().unwrap();
+            metrics::Counter::from_arc(Arc::clone(c.entry(render(key)).or_default()))
+        }
+
+        fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> metrics::Gauge {
+            let mut g = self.gauges.lock().unwrap();
+            metrics::Gauge::from_arc(Arc::clone(g.entry(render(key)).or_default()))
+        }
+
+        fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> metrics::Histogram {
+            let mut h = self.histograms.lock().unwrap();
+            metrics::Histogram::from_arc(Arc::clone(h.entry(render(key)).or_default()))
+        }
+    }
+
+    type Hasher = BuildHasherDefault<DefaultHasher>;
+
+    #[test]
+    fn test_metrics() {
+        let rec = TestRecorder::default();
+
+        let mut anchor: AnchorHash<u64, u32, Hasher> = with_local_recorder(&rec, || {
+            Builder::with_hasher(Hasher::default())
+                .with_resources(0..3)
+                .with_metrics_label("bananas")
+                .build(4)
+        });
+        assert_eq!(anchor.metrics_label(), "bananas");
+
+        assert_eq!(rec.gauge("anchorhash_capacity{instance=bananas}"), 4.0);
+        assert_eq!(
+            rec.gauge("anchorhash_working_buckets{instance=bananas}"),
+            3.0
+        );
+
+        // Keys mapped to the unused bucket walk it before reaching a working
+        // bucket.
+        for k in 0..100 {
+            anchor.lookup(&k).unwrap();
+        }
+        assert_eq!(
+            rec.counter("anchorhash_lookups_total{instance=bananas}"),
+            100
+        );
+        let walked = rec.histogram("anchorhash_lookup_removed_buckets{instance=bananas}");
+        assert_eq!(walked.len(), 100);
+
+        // The number of buckets visited matches the lookup path.
+        let want = (0..100)
+            .map(|k| anchor.explain(&k).unwrap().buckets().len() as f64 - 1.0)
+            .collect::<Vec<_>>();
+        assert_eq!(walked, want);
+
+        anchor.add_resource(3).unwrap();
+        assert_eq!(anchor.add_resource(4), Err(Error::CapacityLimitReached));
+        anchor.remove_resource(&1).unwrap();
+        assert_eq!(anchor.remove_resource(&1), Err(Error::ResourceNotFound));
+
+        assert_eq!(
+            rec.counter("anchorhash_resources_added_total{instance=bananas}"),
+            1
+        );
+        assert_eq!(
+            rec.counter("anchorhash_resources_removed_total{instance=bananas}"),
+            1
+        );
+        assert_eq!(
+            rec.counter("anchorhash_errors_total{instance=bananas,error=capacity_limit_reached}"),
+            1
+        );
+        assert_eq!(
+            rec.counter("anchorhash_errors_total{instance=bananas,error=resource_not_found}"),
+            1
+        );
+        assert_eq!(
+            rec.gauge("anchorhash_working_buckets{instance=bananas}"),
+            3.0
+        );
+
+        // Keys mapped to the removed bucket now walk it.
+        for k in 0..100 {
+            anchor.lookup(&k).unwrap();
+        }
+        let walked = rec.histogram("anchorhash_lookup_removed_buckets{instance=bananas}");
+        assert!(walked[100..].iter().any(|&v| v > 0.0));
+    }
+
+    #[test]
+    fn test_lookup_empty() {
+        let rec = TestRecorder::default();
+
+        let mut anchor: AnchorHash<u64, u32, Hasher> =
+            with_local_recorder(&rec, || Builder::with_hasher(Hasher::default()).build(4));
+        assert_eq!(anchor.metrics_label(), super::DEFAULT_LABEL);
+
+        // Relabelling registers the metrics under the new label.
+        with_local_recorder(&rec, || anchor.set_metrics_label("platanos"));
+        assert_eq!(anchor.lookup(&42), None);
+        assert_eq!(
+            rec.counter("anchorhash_lookups_empty_total{instance=platanos}"),
+            1
+        );
+        assert_eq!(
+            rec.counter("anchorhash_lookups_total{instance=platanos}"),
+            1
+        );
+        assert_eq!(rec.gauge("anchorhash_capacity{instance=platanos}"), 4.0);
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tracing_tests {
+    use std::{
+        collections::hash_map::DefaultHasher,
+        fmt::Debug,
+        hash::BuildHasherDefault,
+        sync::{Arc, Mutex},
+    };
+
+    use hashbrown::HashMap;
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Event,
+    };
+    use tracing_subscriber::{
+        layer::{Context, SubscriberExt},
+        registry::LookupSpan,
+        Layer, Registry,
+    };
+
+    use crate::{AnchorHash, Builder, Error};
+
+    type Fields = HashMap<String, String>;
+
+    #[derive(Default)]
+    struct Visitor(Fields);
+
+    impl Visit for Visitor {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    /// An event, and the name and fields of the span it was emitted in.
+    #[derive(Debug, Clone)]
+    struct Captured {
+        fields: Fields,
+        span: Option<(&'static str, Fields)>,
+    }
+
+    impl Captured {
+        fn get(&self, field: &str) -> Option<&str> {
+            self.fields.get(field).map(String::as_str)
+        }
+    }
+
+    /// A layer capturing every event, and the fields of every span.
+    #[derive(Default, Clone)]
+    struct Capture {
+        spans: Arc<Mutex<HashMap<Id, Fields>>>,
+        events: Arc<Mutex<Vec<Captured>>>,
+    }
+
+    impl Capture {
+        /// Returns the events with the given message.
+        fn events(&self, message: &str) -> Vec<Captured> {
+            self.events
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|e| e.get("message") == Some(message))
+                .cloned()
+                .collect()
+        }
+    }
+
+    impl<S> Layer<S> for Capture
+    where
+        S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, _ctx: Context<'_, S>) {
+            let mut v = Visitor::default();
+            attrs.record(&mut v);
+            self.spans.lock().unwrap().insert(id.clone(), v.0);
+        }
+
+        fn on_record(&self, id: &Id, values: &Record<'_>, _ctx: Context<'_, S>) {
+            let mut v = Visitor::default();
+            values.record(&mut v);
+            if let Some(f) = self.spans.lock().unwrap().get_mut(id) {
+                f.extend(v.0);
+            }
+        }
+
+        fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+            let mut v = Visitor::default();
+            event.record(&mut v);
+
+            let span = ctx.event_span(event).map(|s| {
+                let fields = self.spans.lock().unwrap()[&s.id()].clone();
+                (s.name(), fields)
+            });
+
+            self.events
+                .lock()
+                .unwrap()
+                .push(Captured { fields: v.0, span });
+        }
+    }
+
+    /// Run `f` with a subscriber capturing its events.
+    fn capture<T>(f: impl FnOnce() -> T) -> (Capture, T) {
+        let c = Capture::default();
+        let subscriber = Registry::default().with(c.clone());
+        let ret = tracing::subscriber::with_default(subscriber, f);
+        (c, ret)
+    }
+
+    type Hasher = BuildHasherDefault<DefaultHasher>;
+
+    #[test]
+    fn test_membership_events() {
+        let (c, _) = capture(|| {
+            let mut a: AnchorHash<u64, &str, Hasher> = Builder::with_hasher(Hasher::default())
+                .with_resources(vec!["cache1", "cache2"])
+                .with_traced_resources()
+                .build(3);
+
+            a.remove_resource(&"cache1").unwrap();
+            a.add_resource("cache3").unwrap();
+            a.add_resource("cache4").unwrap();
+            assert_eq!(a.add_resource("cache5"), Err(Error::CapacityLimitReached));
+            assert_eq!(a.remove_resource(&"cache1"), Err(Error::ResourceNotFound));
+        });
+
+        let built = &c.events("anchorhash built")[0];
+        assert_eq!(built.get("working"), Some("2"));
+        assert_eq!(built.get("capacity"), Some("3"));
+        assert_eq!(built.get("removed_stack"), Some("1"));
+        let (name, fields) = built.span.as_ref().unwrap();
+        assert_eq!(*name, "build");
+        assert_eq!(fields["resources"], "2");
+
+        let removed = c.events("resource removed");
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].get("bucket"), Some("0"));
+        assert_eq!(removed[0].get("working_before"), Some("2"));
+        assert_eq!(removed[0].get("working_after"), Some("1"));
+        assert_eq!(removed[0].get("removed_stack"), Some("2"));
+        let (name, fields) = removed[0].span.as_ref().unwrap();
+        assert_eq!(*name, "remove_resource");
+        assert_eq!(fields["resource"], r#""cache1""#);
+
+        // Buckets are reused in the reverse order of removal.
+        let added = c.events("resource added");
+        assert_eq!(added.len(), 2);
+        assert_eq!(added[0].get("bucket"), Some("0"));
+        assert_eq!(added[1].get("bucket"), Some("2"));
+        assert_eq!(added[1].get("working_before"), Some("2"));
+        assert_eq!(added[1].get("working_after"), Some("3"));
+        assert_eq!(added[1].get("removed_stack"), Some("0"));
+        assert_eq!(added[1].span.as_ref().unwrap().1["resource"], r#""cache4""#);
+
+        let failed = c.events("failed to add resource");
+        assert_eq!(
+            failed[0].get("error"),
+            Some("configured resource capacity reached")
+        );
+        assert_eq!(
+            failed[0].span.as_ref().unwrap().1["resource"],
+            r#""cache5""#
+        );
+
+        let failed = c.events("failed to remove resource");
+        assert_eq!(failed[0].get("error"), Some("resource not found"));
+    }
+
+    #[test]
+    fn test_resources_not_traced_by_default() {
+        let (c, _) = capture(|| {
+            let mut a: AnchorHash<u64, &str, Hasher> =
+                Builder::with_hasher(Hasher::default()).build(3);
+            a.add_resource("secret").unwrap();
+        });
+
+        let added = &c.events("resource added")[0];
+        let (name, fields) = added.span.as_ref().unwrap();
+        assert_eq!(*name, "add_resource");
+        assert!(!fields.contains_key("resource"));
+    }
+
+    #[test]
+    fn test_lookups() {
+        let (c, a) = capture(|| {
+            let a: AnchorHash<u64, &str, Hasher> = Builder::with_hasher(Hasher::default())
+                .with_resources(vec!["cache1"])
+                .build(3);
+            a.lookup(&42_u64).unwrap();
+            a
+        });
+
+        let lookups = c.events("lookup");
+        if cfg!(feature = "trace-lookups") {
+            assert_eq!(lookups.len(), 1);
+            let hash = a.hash_key(&42_u64).to_string();
+            assert_eq!(lookups[0].get("hash"), Some(hash.as_str()));
+            assert_eq!(lookups[0].get("bucket"), Some("0"));
+        } else {
+            assert!(lookups.is_empty());
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use core::hash::{BuildHasher, Hash};
+
+use crate::{fasthash::mix64, AnchorHash};
+
+/// The seed used to derive the cutover rank of a key from its hash.
+const RANK_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// The threshold at which every key has cut over to its new owner (2^32).
+const ALL_KEYS: u64 = 1 << 32;
+
+/// A migration window between an old and a new [`AnchorHash`] instance.
+///
+/// When resources are added or removed, some keys move to a new resource that
+/// may not yet hold their data. A `Transition` holds both the old and new
+/// routing state, reporting both owners of a key to support dual-read and
+/// dual-write migration patterns:
+///
+/// ```rust
+/// use anchorhash::Transition;
+///
+/// let old = anchorhash::Builder::default()
+///     .with_resources(vec!["cache1", "cache2"])
+///     .build_any_key(10);
+///
+/// let mut new = old.clone();
+/// new.add_resource("cache3").unwrap();
+///
+/// let mut t = Transition::new(old, new);
+///
+/// let (old_owner, new_owner) = t.owners("user-A");
+/// let (old_owner, new_owner) = (old_owner.copied(), new_owner.copied());
+/// if old_owner != new_owner {
+///     // Read from the new owner, falling back to the old owner, or copy
+///     // the key to its new owner.
+/// }
+///
+/// // Nothing has cut over yet, so keys are routed to their old owner.
+/// assert_eq!(t.route("user-A").copied(), old_owner);
+///
+/// // Once all keys are routed to their new owner, the migration is complete.
+/// t.set_cutover(1.0);
+/// assert_eq!(t.route("user-A").copied(), new_owner);
+/// ```
+///
+/// # Gradual Cutover
+///
+/// The [`route()`] method resolves each key to exactly one owner, selecting the
+/// new owner for a configurable fraction of keys set with [`set_cutover()`].
+///
+/// Each key is assigned a deterministic rank derived from its hash, and keys
+/// cut over in rank order - raising the cutover fraction only ever moves more
+/// keys to their new owner, and all nodes with the same cutover fraction (and
+/// hasher) route each key identically.
+///
+/// Both instances MUST be configured with the same hasher.
+///
+/// [`route()`]: Self::route
+/// [`set_cutover()`]: Self::set_cutover
+#[derive(Debug, Clone)]
+pub struct Transition<K, R, B>
+where
+    B: BuildHasher,
+{
+    old: AnchorHash<K, R, B>,
+    new: AnchorHash<K, R, B>,
+
+    // Keys with a rank below this threshold have cut over to their new owner.
+    //
+    // Held as a u64 so that a threshold of 2^32 covers every u32 rank.
+    threshold: u64,
+}
+
+impl<K, R, B> Transition<K, R, B>
+where
+    B: BuildHasher,
+{
+    /// Initialise a transition from `old` to `new`, with no keys cut over.
+    pub fn new(old: AnchorHash<K, R, B>, new: AnchorHash<K, R, B>) -> Self {
+        Self {
+            old,
+            new,
+            threshold: 0,
+        }
+    }
+
+    /// Set the fraction of keys routed to their new owner by [`route()`].
+    ///
+    /// `fraction` is clamped to the range `[0.0, 1.0]`, where `0.0` routes all
+    /// keys to their old owner and `1.0` routes all keys to their new owner.
+    ///
+    /// [`route()`]: Self::route
+    pub fn set_cutover(&mut self, fraction: f64) {
+        let fraction = if fraction.is_nan() {
+            0.0
+        } else {
+            fraction.clamp(0.0, 1.0)
+        };
+        self.threshold = (fraction * ALL_KEYS as f64) as u64;
+    }
+
+    /// Returns the fraction of keys routed to their new owner.
+    pub fn cutover(&self) -> f64 {
+        self.threshold as f64 / ALL_KEYS as f64
+    }
+
+    /// Returns the owners of `key` in the old and new instance respectively.
+    ///
+    /// The owners are equal for keys that do not move.
+    pub fn owners<Q>(&self, key: &Q) -> (Option<&R>, Option<&R>)
+    where
+        Q: Hash + ?Sized,
+    {
+        (self.old.lookup(key), self.new.lookup(key))
+    }
+
+    /// Returns true if `key` is owned by a different resource in the new
+    /// instance.
+    pub fn is_moved<Q>(&self, key: &Q) -> bool
+    where
+        Q: Hash + ?Sized,
+        R: PartialEq,
+    {
+        let (old, new) = self.owners(key);
+        old != new
+    }
+
+    /// Returns true if `key` has cut over and is routed to its new owner.
+    pub fn is_cut_over<Q>(&self, key: &Q) -> bool
+    where
+        Q: Hash + ?Sized,
+    {
+        self.cut_over(self.new.hash_key(key))
+    }
+
+    /// Resolve `key` to a single owner, selecting the new owner if the key has
+    /// cut over, or the old owner otherwise.
+    pub fn route<Q>(&self, key: &Q) -> Option<&R>
+    where
+        Q: Hash + ?Sized,
+    {
+        let hash = self.new.hash_key(key);
+        if self.cut_over(hash) {
+            self.new.lookup_hash(hash)
+        } else {
+            self.old.lookup(key)
+        }
+    }
+
+    fn cut_over(&self, hash: u64) -> bool {
+        // Mix every bit of the hash into the rank, keeping it independent of
+        // the key placement (selected by the lower 32 bits) while still
+        // ranking keys hashed by hashers that only fill the lower 32 bits.
+        let rank = (mix64(hash ^ RANK_SEED) >> 32) as u32;
+        (rank as u64) < self.threshold
+    }
+
+    /// Returns a reference to the old instance.
+    pub fn old(&self) -> &AnchorHash<K, R, B> {
+        &self.old
+    }
+
+    /// Returns a reference to the new instance.
+    pub fn next(&self) -> &AnchorHash<K, R, B> {
+        &self.new
+    }
+
+    /// Consume the transition, returning the old and new instances.
+    pub fn into_inner(self) -> (AnchorHash<K, R, B>, AnchorHash<K, R, B>) {
+        (self.old, self.new)
+    }
+
+    /// Complete the migration, returning the new instance.
+    pub fn complete(self) -> AnchorHash<K, R, B> {
+        self.new
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::hash::{BuildHasherDefault, Hasher};
+
+    use fnv::FnvBuildHasher;
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+    use crate::{AnyKey, Builder};
+
+    fn transition() -> Transition<AnyKey, usize, FnvBuildHasher> {
+        let old = Builder::with_hasher(FnvBuildHasher::default())
+            .with_resources(0..10)
+            .build_any_key(20);
+        let mut new = old.clone();
+        new.remove_resource(&3).unwrap();
+        new.add_resource(10).unwrap();
+        new.add_resource(11).unwrap();
+
+        Transition::new(old, new)
+    }
+
+    #[test]
+    fn test_owners() {
+        let t = transition();
+
+        let mut moved = 0;
+        for k in 0..10_000 {
+            let (old, new) = t.owners(&k);
+            assert_eq!(old, t.old().lookup(&k));
+            assert_eq!(new, t.next().lookup(&k));
+            assert_eq!(t.is_moved(&k), old != new);
+            if old != new {
+                moved += 1;
+            }
+
+            // Only keys of the removed resource, or those taken by the added
+            // resources, move.
+            assert!(old == new || old == Some(&3) || new >= Some(&10));
+        }
+        assert!(moved > 0);
+    }
+
+    #[test]
+    fn test_cutover_bounds() {
+        let mut t = transition();
+        assert_eq!(t.cutover(), 0.0);
+        assert!((0..1_000).all(|k| t.route(&k) == t.owners(&k).0));
+
+        t.set_cutover(1.0);
+        assert_eq!(t.cutover(), 1.0);
+        assert!((0..1_000).all(|k| t.route(&k) == t.owners(&k).1));
+
+        t.set_cutover(42.0);
+        assert_eq!(t.cutover(), 1.0);
+        t.set_cutover(-1.0);
+        assert_eq!(t.cutover(), 0.0);
+        t.set_cutover(f64::NAN);
+        assert_eq!(t.cutover(), 0.0);
+    }
+
+    #[test]
+    fn test_cutover_fraction() {
+        let mut t = transition();
+        t.set_cutover(0.25);
+
+        let n = 100_000;
+        let got = (0..n).filter(|k| t.is_cut_over(k)).count();
+        let want = n / 4;
+        assert!(
+            (got as i64 - want as i64).abs() < (n / 100) as i64,
+            "got {} cut over, want ~{}",
+            got,
+            want
+        );
+    }
+
+    /// A hasher leaving the upper 32 bits of every hash unset, as many
+    /// integer hashers do.
+    #[derive(Default)]
+    struct LowBitsHasher(u32);
+
+    impl Hasher for LowBitsHasher {
+        fn finish(&self) -> u64 {
+            u64::from(self.0)
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for &b in bytes {
+                self.0 = self.0.wrapping_mul(31).wrapping_add(u32::from(b));
+            }
+        }
+    }
+
+    /// Keys cut over gradually even when their hashes only fill the lower 32
+    /// bits.
+    #[test]
+    fn test_cutover_fraction_low_bits() {
+        let old = Builder::with_hasher(BuildHasherDefault::<LowBitsHasher>::default())
+            .with_resources(0..10)
+            .build_any_key(20);
+        let mut new = old.clone();
+        new.remove_resource(&3).unwrap();
+        let mut t = Transition::new(old, new);
+        t.set_cutover(0.25);
+
+        let n = 100_000_u64;
+        let got = (0..n).filter(|k| t.is_cut_over(k)).count();
+        let want = n / 4;
+        assert!(
+            (got as i64 - want as i64).abs() < (n / 100) as i64,
+            "got {} cut over, want ~{}",
+            got,
+            want
+        );
+    }
+
+    /// Raising the cutover only ever moves more keys to their new owner.
+    #[quickcheck]
+    fn test_cutover_monotonic(a: u16, b: u16, keys: Vec<u64>) -> bool {
+        let (lo, hi) = (a.min(b), a.max(b));
+
+        let mut t = transition();
+        t.set_cutover(lo as f64 / u16::MAX as f64);
+        let before = keys.iter().map(|k| t.is_cut_over(k)).collect::<Vec<_>>();
+
+        t.set_cutover(hi as f64 / u16::MAX as f64);
+        keys.iter()
+            .zip(before)
+            .all(|(k, was)| !was || t.is_cut_over(k))
+    }
+}